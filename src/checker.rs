@@ -1,326 +1,4044 @@
 use std::{
-    fs::{self, File},
-    io::Read,
+    collections::{BTreeMap, VecDeque},
+    fmt::Write as _,
+    hash::{BuildHasher, RandomState},
+    io,
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    sync::Arc,
+    time::{Duration, Instant},
 };
 
-use log::{debug, error, info, trace};
+use log::{debug, error, info, trace, warn};
 
-use crate::{config::Config, fan::Fan, temp::Temp};
+#[cfg(feature = "dbus")]
+use crate::dbus;
+#[cfg(feature = "mqtt")]
+use crate::mqtt::Publisher;
+use crate::{
+    cli::Args,
+    config::{Config, LogContext, OnDeviceLoss, OnTempFailure, Threshold},
+    error::FanError,
+    events::{EventServer, StateChange},
+    fan::{Fan, Stall},
+    governor::Governor,
+    history::History,
+    http::{self, Health},
+    load,
+    status::Status,
+    sysfs::{RealSysfs, SysfsAccess},
+    temp::Temp,
+};
 
 pub struct Checker {
-    is_init: bool,
     pub config: Config,
+    channels: Vec<Channel>,
+    events: Option<EventServer>,
+    http: Option<http::Server>,
+    #[cfg(feature = "dbus")]
+    dbus: Option<dbus::Service>,
+    history: History,
+    last_success: Option<Instant>,
+    loops: u64,
+    started_at: Instant,
+    // Range of the temperatures seen since startup or `SIGHUP`
+    min_temp: Option<f32>,
+    max_temp: Option<f32>,
+    // Minutes since local midnight, for `QUIET_HOURS`
+    local_minutes: fn() -> u16,
+}
+
+// Result of one control loop iteration, for callers driving `step`
+// themselves.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StepOutcome {
+    // Skipped while `PAUSE_FILE` exists
+    pub paused: bool,
+    // Every fan is set to the state it should be in
+    pub controlled: bool,
+    pub fans: Vec<FanOutcome>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FanOutcome {
+    // Smoothed temperature read during this step
+    pub temp: Option<f32>,
+    // State the fan is known to be in afterwards
+    pub state: Option<u8>,
+    // A state was written to the device, never in dry run
+    pub wrote: bool,
+    pub error: Option<String>,
+}
+
+struct Channel {
+    is_init: bool,
+    config: Config,
     fan_device: Option<Fan>,
     temp_device: Option<Temp>,
+    // Where the devices are looked up again after being lost
+    sysfs: Arc<dyn SysfsAccess>,
+    smoothed_temp: Option<f32>,
+    previous_sample: Option<(f32, Instant)>,
+    temp_backoff: Backoff,
+    fan_backoff: Backoff,
+    last_error: Option<String>,
+    // State file the daemon isn't allowed to write, not retried
+    write_denied: Option<PathBuf>,
+    // Max state is due on the fan device found after losing one
+    failsafe_pending: bool,
+    // `FAILSAFE_STATE` held since the temperature can't be read
+    temp_failsafe: bool,
+    // Filled in while adjusting the speed
+    step: FanOutcome,
+    // Speed changes written within the last `WRITE_WINDOW`
+    writes: VecDeque<Instant>,
+    // Set once the governor was taken over with `TAKE_OVER_GOVERNOR`
+    governor: Option<Governor>,
+    // Since when `ALARM_INPUT_PATH` reads active
+    alarm_since: Option<Instant>,
+    // `CRITICAL_TEMP`, replaced by the zone's trip point when one is read
+    configured_critical_temp: Option<f32>,
+    // Thresholds to restore at the end of `QUIET_HOURS`
+    quiet_threshold: Option<Threshold>,
+    // Last `ON_CHANGE_CMD` run, until it's found finished
+    on_change: Option<Child>,
+    stats: Stats,
+    #[cfg(feature = "mqtt")]
+    mqtt: Option<Publisher>,
+    #[cfg(feature = "dbus")]
+    configured_fixed_state: Option<u8>,
 }
 
-impl Default for Checker {
-    fn default() -> Self {
-        Self::new()
-    }
+// Immediate re-reads of a bad temperature sample before the thermal zone is
+// dropped and rediscovered.
+const MAX_TEMP_READ_RETRIES: u32 = 2;
+
+// Window of `MAX_WRITES_PER_MIN`
+const WRITE_WINDOW: Duration = Duration::from_mins(1);
+
+const BACKOFF_MIN_DELAY: Duration = Duration::from_secs(1);
+const BACKOFF_MAX_DELAY: Duration = Duration::from_mins(1);
+
+// Delays rediscovery attempts exponentially while a device stays missing.
+struct Backoff {
+    delay: Duration,
+    retry_at: Option<Instant>,
+    attempts: u32,
 }
 
-impl Checker {
-    #[must_use]
-    pub fn new() -> Self {
-        let temp_device = match Temp::new() {
-            Ok(temp) => Some(temp),
-            Err(err) => {
-                error!("Can't read temperature: {err}");
-                None
-            }
+impl Backoff {
+    const fn new() -> Self {
+        Self {
+            delay: Duration::ZERO,
+            retry_at: None,
+            attempts: 0,
+        }
+    }
+
+    fn is_ready(&self, now: Instant) -> bool {
+        self.retry_at.is_none_or(|retry_at| now >= retry_at)
+    }
+
+    // Returns true on the first failure since the last success.
+    fn failed(&mut self, now: Instant) -> bool {
+        let first = self.delay.is_zero();
+        self.delay = if first {
+            BACKOFF_MIN_DELAY
+        } else {
+            (self.delay * 2).min(BACKOFF_MAX_DELAY)
         };
+        self.retry_at = Some(now + self.delay);
+        self.attempts = self.attempts.saturating_add(1);
+        first
+    }
+
+    const fn reset(&mut self) {
+        self.delay = Duration::ZERO;
+        self.retry_at = None;
+        self.attempts = 0;
+    }
+}
 
-        let config = Config::new();
-        let fan_device = Fan::new(&config);
+// Time spent at each state, accumulated between adjustments.
+struct Stats {
+    time_per_state: BTreeMap<u8, Duration>,
+    current: Option<(u8, Instant)>,
+}
 
+impl Stats {
+    const fn new() -> Self {
         Self {
-            is_init: false,
-            config,
-            fan_device,
-            temp_device,
+            time_per_state: BTreeMap::new(),
+            current: None,
         }
     }
 
-    pub fn adjust_speed(&mut self) {
-        if self.fan_device.is_none() {
-            if let Some((fan_path, path)) = Fan::get_fan_device() {
-                trace!("New fan device detected");
-                self.fan_device = Some(Fan::new_fan_device(fan_path, path, &self.config));
-            } else {
-                error!("Still no fan device available");
-                return;
-            }
+    fn record(&mut self, state: Option<u8>, now: Instant) {
+        if let Some((previous, since)) = self.current {
+            *self.time_per_state.entry(previous).or_default() +=
+                now.saturating_duration_since(since);
         }
+        self.current = state.map(|state| (state, now));
+    }
 
-        if self.temp_device.is_none() {
-            if let Ok(device) = Temp::new() {
-                trace!("New temp device detected");
-                self.temp_device = Some(device);
-            } else {
-                error!("Still no temp device available");
-                return;
-            }
+    fn reset(&mut self, now: Instant) {
+        self.time_per_state.clear();
+        self.current = self.current.map(|(state, _)| (state, now));
+    }
+
+    fn summary(&self) -> Option<String> {
+        let total: Duration = self.time_per_state.values().sum();
+        if total.is_zero() {
+            return None;
         }
 
-        let temp = self.temp_device.as_ref().unwrap();
-        let current_temp = match temp.get_current_temp() {
-            Ok(temp) => temp,
-            Err(err) => {
-                error!("Can't read temperature: {err}");
-                self.temp_device = None;
-                return;
+        let mut summary = String::new();
+        for (state, time) in &self.time_per_state {
+            if !summary.is_empty() {
+                summary.push_str(", ");
             }
-        };
-        debug!("Current temp {current_temp}");
+            let percent = time.as_secs_f32() / total.as_secs_f32() * 100.0;
+            let _ = write!(summary, "state {state}: {percent:.0}%");
+        }
+        Some(summary)
+    }
+}
 
-        let fan = self.fan_device.as_mut().unwrap();
-        let desired_speed = fan.choose_speed(current_temp, &self.config);
-        debug!("Desired speed {desired_speed}");
+impl Default for Checker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-        if fan.last_state == Some(desired_speed) {
-            debug!("State unchanged");
-            return;
+impl Checker {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_args(&Args::default())
+    }
+
+    fn fan_configs(args: &Args, config: &Config) -> Vec<Config> {
+        let mut configs: Vec<Config> = Config::fan_configs()
+            .into_iter()
+            .map(|config| Config::from_args(args, config))
+            .collect();
+        if configs.is_empty() {
+            configs.push(config.clone());
+        } else {
+            info!("{} fans configured", configs.len());
         }
+        configs
+    }
 
-        let current_speed: u8 = match File::open(&fan.state) {
-            Ok(mut f) => {
-                let mut buf = [0u8; 16];
-                let n = match f.read(&mut buf) {
-                    Ok(n) => n,
-                    Err(e) => {
-                        error!("Device is not available: {e}");
-                        self.fan_device = None;
-                        return;
-                    }
-                };
+    #[must_use]
+    pub fn with_args(args: &Args) -> Self {
+        Self::with_config(args, Config::with_args(args))
+    }
 
-                let bytes = &buf[..n];
+    #[must_use]
+    pub fn with_config(args: &Args, config: Config) -> Self {
+        let sysfs: Arc<dyn SysfsAccess> = Arc::new(RealSysfs);
+        Self::with_sysfs(args, config, &sysfs)
+    }
 
-                let mut value: u8 = 0;
-                let mut started = false;
+    // Every fan and thermal zone is discovered and driven through `sysfs`.
+    #[must_use]
+    pub fn with_sysfs(args: &Args, config: Config, sysfs: &Arc<dyn SysfsAccess>) -> Self {
+        let configs = Self::fan_configs(args, &config);
 
-                for &b in bytes {
-                    if b.is_ascii_whitespace() {
-                        if started {
-                            break;
-                        }
-                        continue;
+        let events =
+            config
+                .event_socket
+                .as_deref()
+                .and_then(|path| match EventServer::bind(path) {
+                    Ok(server) => Some(server),
+                    Err(err) => {
+                        error!(
+                            "Can't listen for event clients on {}: {err}",
+                            path.display()
+                        );
+                        None
                     }
+                });
 
-                    if b.is_ascii_digit() {
-                        started = true;
-                        value = if let Some(v) =
-                            value.checked_mul(10).and_then(|v| v.checked_add(b - b'0'))
-                        {
-                            v
-                        } else {
-                            error!("Can't parse speed value: overflow");
-                            return;
-                        };
-                    } else {
-                        error!("Can't parse speed value: invalid character");
-                        return;
-                    }
+        let http = config
+            .http_addr
+            .as_deref()
+            .and_then(|addr| match http::Server::bind(addr) {
+                Ok(server) => Some(server),
+                Err(err) => {
+                    error!("Can't serve HTTP on {addr}: {err}");
+                    None
                 }
+            });
 
-                value
-            }
+        let channels = configs
+            .into_iter()
+            .map(|config| Channel::new(config, sysfs.clone()))
+            .collect();
+        let history = History::new(config.history_size);
+        #[cfg(feature = "dbus")]
+        let dbus = config.dbus_bus.map(dbus::Service::new);
+        Self {
+            config,
+            channels,
+            events,
+            http,
+            #[cfg(feature = "dbus")]
+            dbus,
+            history,
+            last_success: None,
+            loops: 0,
+            started_at: Instant::now(),
+            min_temp: None,
+            max_temp: None,
+            local_minutes,
+        }
+    }
 
-            Err(e) => {
-                error!("Device is not available: {e}");
-                self.fan_device = None;
-                return;
-            }
-        };
+    // Resolved settings and detected devices of every fan, without starting
+    // any of the background services.
+    #[must_use]
+    pub fn describe(args: &Args, config: &Config) -> String {
+        let configs = Self::fan_configs(args, config);
 
-        if current_speed != desired_speed || !self.is_init {
-            if !self.is_init {
-                debug!("Setting the speed for the first time!");
-                self.is_init = true;
+        let mut description = String::new();
+        for (index, config) in configs.iter().enumerate() {
+            if configs.len() > 1 {
+                let _ = writeln!(description, "[fan {index}]");
             }
-            let buf: [u8; 1] = [desired_speed];
-            info!("Adjusting fan speed to {desired_speed} (Temp: {current_temp:.2}°C)");
-            if fs::write(&fan.state, buf).is_ok() {
-                fan.last_state = Some(desired_speed);
-            } else {
-                error!("Can't set speed on device {}", fan.state.display());
-                self.fan_device = None;
+            description.push_str(&config.describe());
+            description.push_str(&Self::describe_devices(config, Arc::new(RealSysfs)));
+            if index + 1 < configs.len() {
+                description.push('\n');
             }
-        } else {
-            debug!("Temp: {current_temp:.2}°C, no speed change needed");
         }
+        description
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::config::{DEFAULT_MAX_STATE, DEFAULT_SLEEP_TIME, State, Threshold};
-    use std::path::PathBuf;
+    // Effective settings and devices of every fan as a single log entry, for
+    // users to paste in support requests.
+    pub fn log_startup_summary(&self) {
+        info!("Startup summary\n{}", self.startup_summary().trim_end());
+    }
 
-    fn create_test_config() -> Config {
-        Config {
-            threshold: Threshold {
-                min: 45.0,
-                max: 70.0,
-            },
-            state: State {
-                min: 0,
-                max: Some(DEFAULT_MAX_STATE),
-            },
-            sleep_time: DEFAULT_SLEEP_TIME,
+    fn startup_summary(&self) -> String {
+        let mut summary = String::new();
+        for (index, channel) in self.channels.iter().enumerate() {
+            if self.channels.len() > 1 {
+                let _ = writeln!(summary, "[fan {index}]");
+            }
+            let unavailable = channel.last_error.as_deref().unwrap_or("not found");
+            summary.push_str(&channel.config.describe());
+            summary.push_str(&Self::describe_found_devices(
+                &channel.config,
+                channel.fan_device.as_ref().ok_or(unavailable),
+                channel.temp_device.as_ref().ok_or(unavailable),
+            ));
         }
+        summary
     }
 
-    struct TestEnv {
-        path: PathBuf,
+    // Probed only, a report doesn't export or enable a PWM channel.
+    fn describe_devices(config: &Config, sysfs: Arc<dyn SysfsAccess>) -> String {
+        let fan = Fan::probe(config, sysfs.clone()).map_err(|err| err.to_string());
+        let temp = Temp::new(config, sysfs).map_err(|err| err.to_string());
+        Self::describe_found_devices(
+            config,
+            fan.as_ref().map_err(String::as_str),
+            temp.as_ref().map_err(String::as_str),
+        )
     }
 
-    impl TestEnv {
-        fn new(name: &str) -> Self {
-            let path = std::env::temp_dir().join(name);
-            fs::create_dir_all(&path).unwrap();
-            Self { path }
+    fn describe_found_devices(
+        config: &Config,
+        fan: Result<&Fan, &str>,
+        temp: Result<&Temp, &str>,
+    ) -> String {
+        let mut description = String::new();
+        match fan {
+            Ok(fan) => {
+                let slots: Vec<String> = fan
+                    .temp_slots
+                    .iter()
+                    .flatten()
+                    .map(|&(state, temp)| format!("{state}@{}", config.temp_unit.display(temp)))
+                    .collect();
+                let max_state = fan.max_state.to_string();
+                let path = fan.path.display().to_string();
+                Config::describe_setting(&mut description, "fan path", &path);
+                Config::describe_setting(&mut description, "device max state", &max_state);
+                Config::describe_setting(&mut description, "computed slots", &slots.join(", "));
+                if let Some(curve) = &fan.curve {
+                    let points: Vec<String> = curve
+                        .iter()
+                        .map(|&(temp, state)| format!("{state}@{}", config.temp_unit.display(temp)))
+                        .collect();
+                    Config::describe_setting(&mut description, "curve", &points.join(", "));
+                }
+                for (low, high, state) in fan.curve_table(config) {
+                    let unit = config.temp_unit;
+                    let mut range = if low.is_finite() {
+                        format!("from {}", unit.display(low))
+                    } else {
+                        format!("below {}", unit.display(high))
+                    };
+                    if low.is_finite() && high.is_finite() {
+                        let _ = write!(range, " to {}", unit.display(high));
+                    }
+                    let hysteresis = config.effective_hysteresis(fan.max_state);
+                    if low.is_finite() && hysteresis > 0.0 {
+                        let down = unit.display(low - hysteresis);
+                        let _ = write!(range, ", down below {down}");
+                    }
+                    let name = format!("state {state}");
+                    Config::describe_setting(&mut description, &name, &range);
+                }
+            }
+            Err(err) => {
+                let value = format!("unavailable ({err})");
+                Config::describe_setting(&mut description, "fan path", &value);
+            }
         }
 
-        fn create_fan(&self, state_content: &str, last_state: Option<u8>) -> Fan {
-            let state_file = self.path.join("cur_state");
-            fs::write(&state_file, state_content).unwrap();
+        let paths = temp.map_or_else(
+            |err| format!("unavailable ({err})"),
+            |temp| {
+                temp.paths
+                    .iter()
+                    .map(|path| {
+                        Temp::zone_type(temp.sysfs.as_ref(), path).map_or_else(
+                            || path.display().to_string(),
+                            |zone_type| format!("{} ({zone_type})", path.display()),
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            },
+        );
+        Config::describe_setting(&mut description, "temp paths", &paths);
+        description
+    }
 
-            Fan {
-                path: self.path.clone(),
-                state: state_file,
-                max_state: DEFAULT_MAX_STATE,
-                temp_slots: [
-                    Some((1, 45.0)),
-                    Some((2, 50.0)),
-                    Some((3, 55.0)),
-                    Some((4, 60.0)),
-                    Some((5, 65.0)),
-                    (None),
-                ],
-                last_state,
+    // Checklist of what a fan needs to be controlled, and whether all of it
+    // passed. Nothing is exported, enabled or written, the write access is
+    // checked by opening the state.
+    #[must_use]
+    pub fn validate(args: &Args, config: &Config) -> (String, bool) {
+        let configs = Self::fan_configs(args, config);
+
+        let mut report = String::new();
+        let mut passed = true;
+        for (index, config) in configs.iter().enumerate() {
+            if configs.len() > 1 {
+                let _ = writeln!(report, "[fan {index}]");
             }
+            passed &= Self::validate_devices(config, Arc::new(RealSysfs), &mut report);
         }
+        (report, passed)
+    }
 
-        fn create_temp(&self, content: &str) -> Temp {
-            let temp_file = self.path.join("temp");
-            fs::write(&temp_file, content).unwrap();
+    fn validate_devices(config: &Config, sysfs: Arc<dyn SysfsAccess>, report: &mut String) -> bool {
+        let mut passed = true;
+        let mut check = |name: &str, result: Result<String, String>| {
+            let (mark, detail) = match result {
+                Ok(detail) => ("ok", detail),
+                Err(detail) => {
+                    passed = false;
+                    ("FAIL", detail)
+                }
+            };
+            let _ = writeln!(report, "[{mark:<4}] {name:<12}{detail}");
+        };
 
-            Temp { path: temp_file }
+        match Fan::probe(config, sysfs.clone()) {
+            Ok(fan) => {
+                let path = fan.path.display();
+                check(
+                    "fan device",
+                    Ok(format!("{path}, max state {}", fan.max_state)),
+                );
+                check("config", Ok("valid for the device".to_owned()));
+                let target = fan.write_access_path();
+                let read = if target == fan.state {
+                    fan.read_state()
+                        .map(|state| format!("state {state}"))
+                        .map_err(|err| err.to_string())
+                } else {
+                    Ok(format!("{path} not exported yet"))
+                };
+                check("fan read", read);
+                check(
+                    "fan write",
+                    fan.sysfs
+                        .check_write(&target)
+                        .map(|()| target.display().to_string())
+                        .map_err(|err| match err.kind() {
+                            io::ErrorKind::PermissionDenied => format!(
+                                "permission denied, run as root or grant write access to {}",
+                                target.display()
+                            ),
+                            _ => err.to_string(),
+                        }),
+                );
+            }
+            Err(FanError::Config(err)) => check("config", Err(err.to_string())),
+            Err(err) => check("fan device", Err(err.to_string())),
         }
-    }
 
-    impl Drop for TestEnv {
-        fn drop(&mut self) {
-            let _ = fs::remove_dir_all(&self.path);
-        }
+        let temp = Temp::new(config, sysfs).and_then(|temp| {
+            let current = temp.get_current_temp()?;
+            let paths: Vec<String> = temp
+                .paths
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect();
+            Ok(format!(
+                "{} from {}",
+                config.temp_unit.display(current),
+                paths.join(", ")
+            ))
+        });
+        check("temperature", temp.map_err(|err| err.to_string()));
+        passed
     }
 
-    #[test]
-    fn test_checker_structure() {
-        let checker = Checker {
-            is_init: false,
-            config: create_test_config(),
-            fan_device: None,
-            temp_device: None,
-        };
-        assert!(!checker.is_init);
-        assert!(checker.fan_device.is_none());
-        assert!(checker.temp_device.is_none());
+    // Aligned tables of every thermal zone and cooling device, to pick the
+    // ones to configure on an unfamiliar board.
+    #[must_use]
+    pub fn list_devices(config: &Config) -> String {
+        Self::list_devices_in(&RealSysfs, &config.thermal_dir(), config)
     }
 
-    #[test]
-    fn test_adjust_speed_without_fan_device() {
-        let mut checker = Checker {
-            is_init: false,
-            config: create_test_config(),
-            fan_device: None,
-            temp_device: None,
+    fn list_devices_in(sysfs: &dyn SysfsAccess, dir: &Path, config: &Config) -> String {
+        let unknown = || "-".to_owned();
+        let name = |path: &Path| {
+            path.file_name()
+                .map_or_else(unknown, |name| name.to_string_lossy().into_owned())
         };
 
-        checker.adjust_speed();
+        let zones: Vec<[String; 3]> = Temp::list_zones(sysfs, dir, config.temp_scale)
+            .into_iter()
+            .map(|(zone, zone_type, temp)| {
+                [
+                    name(&zone),
+                    zone_type.unwrap_or_else(unknown),
+                    temp.map_or_else(unknown, |temp| config.temp_unit.display_with(temp, 1)),
+                ]
+            })
+            .collect();
+        let devices: Vec<[String; 3]> = Fan::list_cooling_devices(sysfs, dir)
+            .into_iter()
+            .map(|(device, device_type, max_state)| {
+                [
+                    name(&device),
+                    device_type.unwrap_or_else(unknown),
+                    max_state.map_or_else(unknown, |state| state.to_string()),
+                ]
+            })
+            .collect();
+
+        let mut output = String::new();
+        write_table(&mut output, ["ZONE", "TYPE", "TEMP"], &zones);
+        output.push('\n');
+        write_table(&mut output, ["DEVICE", "TYPE", "MAX STATE"], &devices);
+        output
     }
 
-    #[test]
-    fn test_checker_with_mock_fan() {
-        let env = TestEnv::new("test_checker_mock");
-        let fan = env.create_fan("2", None);
-        let temp = env.create_temp("55000");
+    #[must_use]
+    pub fn with_devices(
+        config: Config,
+        fan_device: Option<Fan>,
+        temp_device: Option<Temp>,
+    ) -> Self {
+        let channel = Channel::with_devices(config.clone(), fan_device, temp_device);
+        let history = History::new(config.history_size);
+        Self {
+            config,
+            channels: vec![channel],
+            events: None,
+            http: None,
+            #[cfg(feature = "dbus")]
+            dbus: None,
+            history,
+            last_success: None,
+            loops: 0,
+            started_at: Instant::now(),
+            min_temp: None,
+            max_temp: None,
+            local_minutes,
+        }
+    }
 
-        let mut checker = Checker {
-            is_init: false,
-            config: create_test_config(),
-            fan_device: Some(fan),
-            temp_device: Some(temp),
-        };
+    #[must_use]
+    pub fn sleep_time(&self) -> Duration {
+        self.channels
+            .iter()
+            .map(Channel::sleep_time)
+            .min()
+            .unwrap_or_else(|| Duration::from_secs(self.config.sleep_time))
+    }
 
-        checker.adjust_speed();
-        assert!(checker.is_init);
+    // `sleep_time` moved by up to `SLEEP_JITTER` either way, so that boards
+    // started together don't keep polling in lockstep.
+    #[must_use]
+    pub fn jittered_sleep_time(&self) -> Duration {
+        let sleep_time = self.sleep_time();
+        if self.config.sleep_jitter == 0 {
+            return sleep_time;
+        }
+        // Hashing with a fresh `RandomState` is enough randomness here and
+        // avoids pulling in a dependency.
+        let random = RandomState::new().hash_one(self.loops);
+        apply_jitter(
+            sleep_time,
+            Duration::from_secs(self.config.sleep_jitter),
+            random,
+        )
     }
 
-    #[test]
-    fn test_adjust_speed_with_same_desired_speed() {
-        let env = TestEnv::new("test_checker_same_speed");
-        let fan = env.create_fan("3", Some(3));
-        let temp = env.create_temp("55000");
+    // State the first fan would be set to at `temp`, without touching any
+    // device. `None` until a fan device is available.
+    #[must_use]
+    pub fn desired_state_for(&self, temp: f32) -> Option<u8> {
+        let channel = self.channels.first()?;
+        let fan = channel.fan_device.as_ref()?;
+        Some(Channel::decide_speed(fan, temp, &channel.config, 0, false).1)
+    }
 
-        let mut checker = Checker {
-            is_init: true,
-            config: create_test_config(),
-            fan_device: Some(fan),
-            temp_device: Some(temp),
-        };
+    // Present while an external tool needs the fan left alone, e.g. during a
+    // firmware update.
+    fn paused(&self) -> Option<&Path> {
+        self.config
+            .pause_file
+            .as_deref()
+            .filter(|path| path.exists())
+    }
 
-        checker.adjust_speed();
+    pub fn adjust_speed(&mut self) -> bool {
+        self.step().controlled
     }
 
-    #[test]
-    fn test_adjust_speed_with_invalid_temp_file() {
-        let env = TestEnv::new("test_checker_invalid_temp");
-        let fan = env.create_fan("2", None);
-        let temp = env.create_temp("invalid");
+    // One iteration of the control loop, leaving the timing to the caller.
+    pub fn step(&mut self) -> StepOutcome {
+        if let Some(path) = self.paused() {
+            debug!("Paused while {} exists", path.display());
+            self.last_success = Some(Instant::now());
+            return StepOutcome {
+                paused: true,
+                controlled: true,
+                fans: Vec::new(),
+            };
+        }
 
-        let mut checker = Checker {
-            is_init: false,
-            config: create_test_config(),
-            fan_device: Some(fan),
-            temp_device: Some(temp),
-        };
+        let mut controlled = true;
+        let mut fans = Vec::with_capacity(self.channels.len());
+        let warming_up = self.started_at.elapsed() < Duration::from_secs(self.config.warmup_time);
+        let interval = self.config.rescan_interval;
+        let rescan = interval != 0 && self.loops > 0 && self.loops.is_multiple_of(interval);
+        let minutes = self
+            .channels
+            .iter()
+            .any(|channel| channel.config.quiet_hours.is_some())
+            .then(self.local_minutes);
+        #[cfg(feature = "dbus")]
+        let fixed_state = self.dbus.as_ref().and_then(dbus::Service::fixed_state);
+        let labeled = self.channels.len() > 1;
+        for (fan, channel) in self.channels.iter_mut().enumerate() {
+            let _context = labeled.then(|| channel.log_context(fan).enter());
+            #[cfg(feature = "dbus")]
+            {
+                channel.config.fixed_state = fixed_state.or(channel.configured_fixed_state);
+            }
+            let previous_state = channel.last_state();
+            if rescan {
+                channel.refresh_max_state();
+            }
+            if let Some(minutes) = minutes {
+                channel.apply_quiet_hours(minutes);
+            }
+            let adjusted = channel.adjust_speed(warming_up);
+            if adjusted
+                && let (Some(temp), Some(state)) = (channel.smoothed_temp, channel.last_state())
+            {
+                self.history.record(fan, temp, state);
+                self.min_temp = Some(self.min_temp.map_or(temp, |min| min.min(temp)));
+                self.max_temp = Some(self.max_temp.map_or(temp, |max| max.max(temp)));
+            }
+            controlled &= adjusted;
+            fans.push(channel.outcome(adjusted));
+            channel.run_on_change(previous_state);
+            #[cfg(feature = "dbus")]
+            if let Some(dbus) = &self.dbus
+                && let Some(temp) = channel.smoothed_temp
+            {
+                dbus.update(fan, temp, channel.last_state(), previous_state);
+            }
+            Self::notify(self.events.as_mut(), fan, channel, previous_state);
+        }
 
-        checker.adjust_speed();
-        assert!(checker.temp_device.is_none());
+        let now = Instant::now();
+        if controlled {
+            self.last_success = Some(now);
+        }
+        if let Some(http) = &self.http {
+            http.update(self.health());
+            http.update_history(self.history.to_json());
+            http.update_metrics(self.metrics());
+        }
+        self.update_stats(now);
+        StepOutcome {
+            paused: false,
+            controlled,
+            fans,
+        }
     }
 
-    #[test]
-    fn test_adjust_speed_with_invalid_speed_file() {
-        let env = TestEnv::new("test_checker_invalid_speed");
-        let fan = env.create_fan("invalid_speed", None);
-        let temp = env.create_temp("50000");
+    fn update_stats(&mut self, now: Instant) {
+        for channel in &mut self.channels {
+            let state = channel.last_state();
+            channel.stats.record(state, now);
+        }
 
-        let mut checker = Checker {
-            is_init: false,
-            config: create_test_config(),
-            fan_device: Some(fan),
-            temp_device: Some(temp),
-        };
+        self.loops += 1;
+        let interval = self.config.stats_interval;
+        if interval == 0 || !self.loops.is_multiple_of(interval) {
+            return;
+        }
+        let labeled = self.channels.len() > 1;
+        for (fan, channel) in self.channels.iter().enumerate() {
+            let _context = labeled.then(|| channel.log_context(fan).enter());
+            if let Some(summary) = channel.stats.summary() {
+                info!("Time per state: {summary}");
+            }
+        }
+        if let (Some(min), Some(max)) = (self.min_temp, self.max_temp) {
+            let unit = self.config.temp_unit;
+            info!(
+                "Temperature range: {} to {}",
+                unit.display(min),
+                unit.display(max)
+            );
+        }
+    }
 
-        checker.adjust_speed();
+    #[must_use]
+    pub const fn min_temp(&self) -> Option<f32> {
+        self.min_temp
     }
 
-    #[test]
-    fn test_adjust_speed_without_temp_device() {
-        let env = TestEnv::new("test_checker_no_temp");
-        let fan = env.create_fan("2", None);
+    #[must_use]
+    pub const fn max_temp(&self) -> Option<f32> {
+        self.max_temp
+    }
 
-        let mut checker = Checker {
-            is_init: false,
-            config: create_test_config(),
-            fan_device: Some(fan),
-            temp_device: None,
-        };
+    fn metrics(&self) -> String {
+        let mut metrics = String::new();
+        for (name, help, value) in [
+            ("min", "Lowest", self.min_temp),
+            ("max", "Highest", self.max_temp),
+        ] {
+            let Some(value) = value else {
+                continue;
+            };
+            let metric = format!("cm3588_fan_temperature_{name}_celsius");
+            let _ = writeln!(
+                metrics,
+                "# HELP {metric} {help} temperature since startup or SIGHUP\n# TYPE {metric} gauge\n{metric} {value}"
+            );
+        }
+        metrics.pop();
+        metrics
+    }
 
-        checker.adjust_speed();
+    pub fn reset_stats(&mut self) {
+        let now = Instant::now();
+        for channel in &mut self.channels {
+            channel.stats.reset(now);
+        }
+        self.loops = 0;
+        self.min_temp = None;
+        self.max_temp = None;
+    }
+
+    pub fn dump_history(&self) {
+        let mut decisions = self.history.iter().peekable();
+        if decisions.peek().is_none() {
+            info!("No decision recorded yet");
+            return;
+        }
+        for decision in decisions {
+            info!(
+                "[{}] fan {}: {} -> state {}",
+                decision.timestamp,
+                decision.fan,
+                self.config.temp_unit.display(decision.temp),
+                decision.state
+            );
+        }
+    }
+
+    fn health(&self) -> Health {
+        let errors: Vec<String> = self
+            .channels
+            .iter()
+            .enumerate()
+            .filter_map(|(fan, channel)| {
+                let err = channel.last_error.as_ref()?;
+                Some(if self.channels.len() > 1 {
+                    format!("fan {fan}: {err}")
+                } else {
+                    err.clone()
+                })
+            })
+            .collect();
+
+        Health {
+            last_success: self.last_success,
+            last_error: (!errors.is_empty()).then(|| errors.join("; ")),
+            devices_present: self
+                .channels
+                .iter()
+                .all(|channel| channel.fan_device.is_some() && channel.temp_device.is_some()),
+            fan_stalled: self.channels.iter().any(|channel| {
+                channel
+                    .fan_device
+                    .as_ref()
+                    .is_some_and(|fan| fan.stall == Stall::Stalled)
+            }),
+            max_age: self.sleep_time() * 2,
+        }
+    }
+
+    // Runs the checks of the blocking loop from a tokio task until `shutdown`
+    // completes, then sets the shutdown state. `tokio::fs` would hand every
+    // sysfs read and write to the blocking pool one by one, a whole step is
+    // sent there at once instead. For embedding only, the daemon keeps its
+    // own loop.
+    #[cfg(feature = "tokio")]
+    pub async fn run(mut self, shutdown: impl Future<Output = ()>) -> Self {
+        use tokio::time::{self, MissedTickBehavior};
+
+        tokio::pin!(shutdown);
+        let mut period = self.sleep_time();
+        let mut interval = time::interval(period);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                () = &mut shutdown => break,
+                _ = interval.tick() => {}
+            }
+            self = self
+                .blocking(|checker| {
+                    checker.step();
+                })
+                .await;
+
+            // The sleep time adapts to the temperature trend
+            let sleep_time = self.sleep_time();
+            if sleep_time != period {
+                period = sleep_time;
+                interval = time::interval_at(time::Instant::now() + period, period);
+                interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            }
+        }
+        self.blocking(Self::shutdown).await
+    }
+
+    #[cfg(feature = "tokio")]
+    async fn blocking(mut self, f: fn(&mut Self)) -> Self {
+        tokio::task::spawn_blocking(move || {
+            f(&mut self);
+            self
+        })
+        .await
+        .unwrap_or_else(|err| std::panic::resume_unwind(err.into_panic()))
+    }
+
+    pub fn shutdown(&mut self) {
+        if let Some(path) = self.paused() {
+            info!(
+                "Paused while {} exists, leaving the fan as is",
+                path.display()
+            );
+            return;
+        }
+        let labeled = self.channels.len() > 1;
+        for (fan, channel) in self.channels.iter_mut().enumerate() {
+            let _context = labeled.then(|| channel.log_context(fan).enter());
+            let previous_state = channel.last_state();
+            channel.shutdown();
+            Self::notify(self.events.as_mut(), fan, channel, previous_state);
+        }
+    }
+
+    fn notify(
+        events: Option<&mut EventServer>,
+        fan: usize,
+        channel: &Channel,
+        previous_state: Option<u8>,
+    ) {
+        if let Some(events) = events
+            && let Some(state) = channel.last_state()
+            && previous_state != Some(state)
+        {
+            events.send(&StateChange {
+                fan,
+                previous_state,
+                state,
+                temp: channel.smoothed_temp.unwrap_or_default(),
+            });
+        }
+    }
+}
+
+impl Channel {
+    fn new(config: Config, sysfs: Arc<dyn SysfsAccess>) -> Self {
+        let mut channel = Self::with_devices(config, None, None);
+        channel.sysfs = sysfs;
+        match Temp::new(&channel.config, channel.sysfs.clone()) {
+            Ok(temp) => channel.set_temp_device(temp),
+            Err(err) => error!("Can't read temperature: {err}"),
+        }
+        #[cfg(feature = "mqtt")]
+        {
+            channel.mqtt = channel
+                .config
+                .mqtt_host
+                .as_deref()
+                .map(|host| Publisher::new(host, &channel.config.mqtt_topic_prefix));
+        }
+        match channel.open_fan() {
+            Ok(fan) => channel.fan_device = Some(fan),
+            Err(err) => error!("{err}"),
+        }
+        channel
+    }
+
+    fn with_devices(config: Config, fan_device: Option<Fan>, temp_device: Option<Temp>) -> Self {
+        let sysfs = fan_device
+            .as_ref()
+            .map(|fan| fan.sysfs.clone())
+            .or_else(|| temp_device.as_ref().map(|temp| temp.sysfs.clone()))
+            .unwrap_or_else(|| Arc::new(RealSysfs));
+        #[cfg(feature = "dbus")]
+        let configured_fixed_state = config.fixed_state;
+        let configured_critical_temp = config.critical_temp;
+        Self {
+            is_init: false,
+            config,
+            fan_device,
+            temp_device,
+            sysfs,
+            smoothed_temp: None,
+            previous_sample: None,
+            temp_backoff: Backoff::new(),
+            fan_backoff: Backoff::new(),
+            last_error: None,
+            write_denied: None,
+            failsafe_pending: false,
+            temp_failsafe: false,
+            step: FanOutcome {
+                temp: None,
+                state: None,
+                wrote: false,
+                error: None,
+            },
+            writes: VecDeque::new(),
+            governor: None,
+            alarm_since: None,
+            configured_critical_temp,
+            quiet_threshold: None,
+            on_change: None,
+            stats: Stats::new(),
+            #[cfg(feature = "mqtt")]
+            mqtt: None,
+            #[cfg(feature = "dbus")]
+            configured_fixed_state,
+        }
+    }
+
+    // Starts `ON_CHANGE_CMD` without waiting for it once a new state was
+    // written, with the state and the temperature as `$1` and `$2` and in the
+    // environment. A change made while the previous run is still going
+    // isn't reported, so a hanging command never piles up.
+    fn run_on_change(&mut self, previous_state: Option<u8>) {
+        let running = match self.on_change.as_mut().map(Child::try_wait) {
+            Some(Ok(None)) => true,
+            Some(Ok(Some(status))) => {
+                if !status.success() {
+                    warn!("ON_CHANGE_CMD failed: {status}");
+                }
+                false
+            }
+            Some(Err(err)) => {
+                error!("Can't check on ON_CHANGE_CMD: {err}");
+                false
+            }
+            None => false,
+        };
+        if !running {
+            self.on_change = None;
+        }
+
+        let (true, Some(command), Some(state)) = (
+            self.step.wrote,
+            &self.config.on_change_cmd,
+            self.last_state(),
+        ) else {
+            return;
+        };
+        if running {
+            warn!("ON_CHANGE_CMD still running, not run for state {state}");
+            return;
+        }
+
+        let state = state.to_string();
+        let temp = self
+            .smoothed_temp
+            .map(|temp| format!("{temp:.2}"))
+            .unwrap_or_default();
+        let spawned = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .args(["sh", &state, &temp])
+            .env("FAN_STATE", &state)
+            .env(
+                "FAN_PREVIOUS_STATE",
+                previous_state
+                    .map(|state| state.to_string())
+                    .unwrap_or_default(),
+            )
+            .env("FAN_TEMP", &temp)
+            .stdin(Stdio::null())
+            .spawn();
+        match spawned {
+            Ok(child) => self.on_change = Some(child),
+            Err(err) => error!("Can't run ON_CHANGE_CMD: {err}"),
+        }
+    }
+
+    fn outcome(&self, controlled: bool) -> FanOutcome {
+        FanOutcome {
+            state: self.last_state(),
+            error: if controlled {
+                None
+            } else {
+                self.last_error.clone()
+            },
+            ..self.step
+        }
+    }
+
+    fn fail(&mut self, err: String) -> bool {
+        error!("{err}");
+        self.last_error = Some(err);
+        false
+    }
+
+    // A denied write won't succeed on a rediscovered device either, so the
+    // device is kept and the write isn't attempted again.
+    fn write_failed(&mut self, action: &str, err: &io::Error) -> bool {
+        let Some(path) = self.fan_device.as_ref().map(|fan| fan.state.clone()) else {
+            return false;
+        };
+        if err.kind() != io::ErrorKind::PermissionDenied {
+            self.lose_fan();
+            return self.fail(format!(
+                "Can't {action} on device {}: {err}",
+                path.display()
+            ));
+        }
+
+        error!(
+            "Can't {action}: permission denied, run as root or grant write access to {}",
+            path.display()
+        );
+        self.last_error = Some(format!("No write access to {}", path.display()));
+        self.write_denied = Some(path);
+        false
+    }
+
+    // With `CRITICAL_TRIP`, the critical trip point of the zone wins
+    // over `CRITICAL_TEMP`.
+    fn set_temp_device(&mut self, device: Temp) {
+        if self.config.critical_trip {
+            self.config.critical_temp = if let Some(trip) = device.read_critical_trip() {
+                info!(
+                    "Critical temp from trip point: {}",
+                    self.config.temp_unit.display(trip)
+                );
+                Some(trip)
+            } else {
+                debug!("No critical trip point found, using CRITICAL_TEMP");
+                self.configured_critical_temp
+            };
+        }
+        self.temp_device = Some(device);
+    }
+
+    // With `ON_DEVICE_LOSS=failsafe`, the next device found is initialized
+    // again at max state.
+    fn lose_fan(&mut self) {
+        self.fan_device = None;
+        if self.config.on_device_loss == OnDeviceLoss::Failsafe {
+            self.is_init = false;
+            self.failsafe_pending = true;
+        }
+    }
+
+    fn last_state(&self) -> Option<u8> {
+        self.fan_device.as_ref().and_then(|fan| fan.last_state)
+    }
+
+    // Basename of the fan device and of the thermal zones it follows, the
+    // configured device or the fan index until they are found.
+    fn log_context(&self, fan: usize) -> LogContext {
+        let basename = |path: &Path| {
+            path.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+        };
+        let device = self
+            .fan_device
+            .as_ref()
+            .and_then(|device| basename(&device.path))
+            .or_else(|| {
+                self.config
+                    .cooling_device
+                    .map(|index| format!("cooling_device{index}"))
+            })
+            .unwrap_or_else(|| format!("fan{fan}"));
+        let zone = self.temp_device.as_ref().map(|temp| {
+            temp.paths
+                .iter()
+                .filter_map(|path| path.parent().and_then(basename))
+                .collect::<Vec<_>>()
+                .join("+")
+        });
+        LogContext { device, zone }
+    }
+
+    // Sets the fan up again when its device reports another max state, e.g.
+    // after the fan was replaced, so the config is checked and the slots
+    // computed for the new one.
+    fn refresh_max_state(&mut self) {
+        let Some(fan) = &self.fan_device else {
+            return;
+        };
+        let max_state = match fan.read_max_state() {
+            Ok(max_state) if max_state != fan.max_state => max_state,
+            Ok(_) => return,
+            Err(err) => {
+                debug!("Can't read the max state of {}: {err}", fan.path.display());
+                return;
+            }
+        };
+
+        info!(
+            "Fan max state changed from {} to {max_state}",
+            fan.max_state
+        );
+        match self.open_fan() {
+            Ok(device) => self.fan_device = Some(device),
+            Err(err) => {
+                error!("Can't set the fan up again: {err}");
+                self.lose_fan();
+            }
+        }
+    }
+
+    fn open_fan(&mut self) -> Result<Fan, FanError> {
+        match Fan::new(&self.config, self.sysfs.clone()) {
+            Err(FanError::Config(err)) => {
+                error!(
+                    "Invalid configuration: {err}, falling back to default thresholds and states"
+                );
+                self.config.reset_to_defaults();
+                Fan::new(&self.config, self.sysfs.clone())
+            }
+            result => result,
+        }
+    }
+
+    fn sleep_time(&self) -> Duration {
+        let config = &self.config;
+        let sleep_time = Duration::from_secs(config.sleep_time);
+        let Some(temp) = self.smoothed_temp else {
+            return sleep_time;
+        };
+
+        if temp >= config.threshold.max {
+            Duration::from_secs(config.min_sleep_time)
+        } else if temp < config.threshold.min {
+            Duration::from_secs(config.max_sleep_time)
+        } else {
+            let ratio =
+                (temp - config.threshold.min) / (config.threshold.max - config.threshold.min);
+            let span = sleep_time.saturating_sub(Duration::from_secs(config.min_sleep_time));
+            sleep_time.saturating_sub(span.mul_f32(ratio))
+        }
+    }
+
+    fn smooth_temp(&mut self, raw_temp: f32) -> f32 {
+        let alpha = self.config.temp_ema_alpha;
+        let smoothed = self.smoothed_temp.map_or(raw_temp, |previous| {
+            alpha.mul_add(raw_temp - previous, previous)
+        });
+        self.smoothed_temp = Some(smoothed);
+        smoothed
+    }
+
+    // Raises the thresholds by `QUIET_THRESHOLD_OFFSET` within
+    // `QUIET_HOURS`, and puts them back afterwards.
+    fn apply_quiet_hours(&mut self, minutes: u16) {
+        let quiet = self
+            .config
+            .quiet_hours
+            .is_some_and(|hours| hours.contains(minutes));
+        match (quiet, self.quiet_threshold.take()) {
+            (true, None) => {
+                let offset = self.config.quiet_threshold_offset;
+                let threshold = self.config.threshold.clone();
+                info!(
+                    "Quiet hours started, raising the thresholds by {}",
+                    self.config.temp_unit.display_delta(offset)
+                );
+                self.config.threshold = Threshold {
+                    min: threshold.min + offset,
+                    max: threshold.max + offset,
+                };
+                self.quiet_threshold = Some(threshold);
+            }
+            (false, Some(threshold)) => {
+                info!("Quiet hours ended, restoring the thresholds");
+                self.config.threshold = threshold;
+            }
+            (_, threshold) => {
+                self.quiet_threshold = threshold;
+                return;
+            }
+        }
+        if let Some(fan) = &mut self.fan_device {
+            fan.refresh_slots(&self.config);
+        }
+    }
+
+    // Done once, with the first fan device found. A single run doesn't shut
+    // down, so it would never hand the zone back.
+    fn take_over_governor(&mut self) {
+        let config = &self.config;
+        if !config.take_over_governor || config.run_once || self.governor.is_some() {
+            return;
+        }
+        let Some(fan) = &self.fan_device else {
+            return;
+        };
+        let zones = Governor::bound_zones(fan.sysfs.as_ref(), &config.thermal_dir(), &fan.path);
+        self.governor = Some(Governor::take_over(
+            &zones,
+            fan.sysfs.clone(),
+            config.dry_run,
+        ));
+    }
+
+    // Another daemon or the kernel thermal governor writing to the device
+    // fights our control. The state read is kept so the desired one is
+    // written again. Returns whether the state changed behind our back.
+    fn check_external_change(&mut self) -> bool {
+        let Some(fan) = self.fan_device.as_mut() else {
+            return false;
+        };
+        if self.config.dry_run {
+            return false;
+        }
+        let Some(last_state) = fan.last_state else {
+            return false;
+        };
+        let state = match fan.read_state() {
+            Ok(state) if state != last_state => state,
+            Ok(_) => return false,
+            Err(err) => {
+                debug!("Can't read the current state: {err}");
+                return false;
+            }
+        };
+
+        warn!(
+            "Fan state changed from {last_state} to {state} by another process, \
+             check that the kernel thermal governor or another daemon isn't controlling {}",
+            fan.path.display()
+        );
+        fan.record_state(state, self.config.state.min);
+        true
+    }
+
+    // Looks for a fan device when there's none, with backoff between
+    // attempts. Returns whether a device is available.
+    fn find_fan(&mut self) -> bool {
+        if self.fan_device.is_some() {
+            return true;
+        }
+
+        let now = Instant::now();
+        if !self.fan_backoff.is_ready(now) {
+            trace!("Waiting before looking for a fan device again");
+            return false;
+        }
+
+        let Ok(device) = self.open_fan() else {
+            self.last_error = Some("No fan device available".to_owned());
+            if self.fan_backoff.failed(now) {
+                warn!("No fan device available, retrying with backoff");
+            } else {
+                debug!(
+                    "Still no fan device available after {} retries, next attempt in {}s",
+                    self.fan_backoff.attempts - 1,
+                    self.fan_backoff.delay.as_secs()
+                );
+            }
+            return false;
+        };
+
+        if self.fan_backoff.attempts > 0 {
+            info!(
+                "Fan device found after {} retries",
+                self.fan_backoff.attempts
+            );
+        } else {
+            trace!("New fan device detected");
+        }
+        self.fan_device = Some(device);
+        self.fan_backoff.reset();
+        true
+    }
+
+    fn find_temp(&mut self) -> bool {
+        if self.temp_device.is_some() {
+            return true;
+        }
+
+        let now = Instant::now();
+        if !self.temp_backoff.is_ready(now) {
+            trace!("Waiting before looking for a thermal zone again");
+            return false;
+        }
+
+        let Ok(device) = Temp::new(&self.config, self.sysfs.clone()) else {
+            self.last_error = Some("No temp device available".to_owned());
+            if self.temp_backoff.failed(now) {
+                warn!("No temp device available, retrying with backoff");
+            } else {
+                debug!(
+                    "Still no temp device available, next attempt in {}s",
+                    self.temp_backoff.delay.as_secs()
+                );
+            }
+            return false;
+        };
+
+        trace!("New temp device detected");
+        self.set_temp_device(device);
+        self.temp_backoff.reset();
+        true
+    }
+
+    // Writes `FAILSAFE_STATE` under `ON_TEMP_FAILURE=failsafe`, the curve
+    // takes over again with the next temperature read.
+    fn hold_failsafe(&mut self) {
+        if self.config.on_temp_failure != OnTempFailure::Failsafe {
+            return;
+        }
+        let Some(fan) = self.fan_device.as_mut() else {
+            return;
+        };
+        let state = self
+            .config
+            .failsafe_state
+            .map_or(fan.max_state, |state| state.min(fan.max_state));
+        if !self.temp_failsafe {
+            warn!("Temperature unavailable, holding failsafe state {state}");
+            self.temp_failsafe = true;
+        }
+        self.force_state(state, "set failsafe state");
+    }
+
+    // True while `ALARM_INPUT_PATH` reads non-zero, e.g. the GPIO of a
+    // chassis over-temperature alarm. It doesn't depend on our own sensor,
+    // so it's read first.
+    fn alarm_active(&mut self) -> bool {
+        let (Some(path), Some(fan)) = (&self.config.alarm_input_path, &self.fan_device) else {
+            return false;
+        };
+        let active = match fan.sysfs.read_to_string(path) {
+            Ok(value) => value.trim().parse::<i64>().is_ok_and(|value| value != 0),
+            Err(err) => {
+                error!("Can't read alarm input {}: {err}", path.display());
+                false
+            }
+        };
+
+        match (active, self.alarm_since) {
+            (true, None) => {
+                warn!(
+                    "Alarm input {} active, forcing the max state",
+                    path.display()
+                );
+                self.alarm_since = Some(Instant::now());
+            }
+            (false, Some(since)) => {
+                info!("Alarm input cleared after {}s", since.elapsed().as_secs());
+                self.alarm_since = None;
+            }
+            _ => {}
+        }
+        active
+    }
+
+    // Writes a state outside of the curve, without the limits meant for the
+    // regular changes.
+    fn force_state(&mut self, state: u8, action: &str) -> bool {
+        let Some(fan) = self.fan_device.as_mut() else {
+            return false;
+        };
+        if fan.last_state == Some(state) {
+            return true;
+        }
+        if self.write_denied.as_ref() == Some(&fan.state) {
+            return false;
+        }
+
+        if self.config.dry_run {
+            fan.record_state(state, self.config.state.min);
+            return true;
+        }
+        match fan.write_state(state) {
+            Ok(()) => {
+                fan.record_state(state, self.config.state.min);
+                self.step.wrote = true;
+                self.writes.push_back(Instant::now());
+                true
+            }
+            Err(err) => self.write_failed(action, &err),
+        }
+    }
+
+    fn adjust_speed(&mut self, warming_up: bool) -> bool {
+        self.step = FanOutcome::default();
+        if !self.find_fan() {
+            return false;
+        }
+        self.take_over_governor();
+        self.check_external_change();
+        if self.alarm_active() {
+            let max_state = self.fan_device.as_ref().map_or(0, |fan| fan.max_state);
+            return self.force_state(max_state, "set alarm state");
+        }
+
+        if !self.find_temp() {
+            self.hold_failsafe();
+            return false;
+        }
+
+        let temp = self.temp_device.as_ref().unwrap();
+        let raw_temp = match Self::read_temp(temp, self.config.temp_samples) {
+            Ok(temp) => temp,
+            Err(err) => {
+                self.temp_device = None;
+                let failed = self.fail(format!("Can't read temperature: {err}"));
+                self.hold_failsafe();
+                return failed;
+            }
+        };
+        if self.temp_failsafe {
+            info!("Temperature readable again, leaving the failsafe state");
+            self.temp_failsafe = false;
+        }
+        let current_temp = self.smooth_temp(raw_temp);
+        self.step.temp = Some(current_temp);
+        debug!(
+            temp = current_temp;
+            "Current temp {} (smoothed {})",
+            self.config.temp_unit.display(raw_temp),
+            self.config.temp_unit.display(current_temp)
+        );
+
+        let rising = self.is_rising(current_temp, Instant::now());
+        let bump = u8::from(rising).saturating_add(self.load_bump());
+        let fan = self.fan_device.as_mut().unwrap();
+        let recovering = Self::check_rpm(fan, &self.config);
+
+        let (desired_speed, next_speed) =
+            Self::decide_speed(fan, current_temp, &self.config, bump, self.is_init);
+        debug!("Desired speed {desired_speed}");
+        let next_speed = if recovering {
+            fan.max_state
+        } else if self.failsafe_pending {
+            info!(
+                "Fan device lost before, writing max state {}",
+                fan.max_state
+            );
+            fan.max_state
+        } else if warming_up && !Fan::is_critical(current_temp, &self.config) {
+            let state = self.config.warmup_state.unwrap_or(self.config.state.min);
+            debug!("Warming up, holding state {state}");
+            state
+        } else {
+            next_speed
+        };
+
+        let max_state = fan.max_state;
+        let controlled = self.apply_speed(next_speed, current_temp);
+        if controlled {
+            self.failsafe_pending = false;
+        }
+        self.write_status(current_temp, desired_speed, max_state);
+        #[cfg(feature = "mqtt")]
+        if let Some(mqtt) = &self.mqtt {
+            mqtt.publish(
+                current_temp,
+                self.fan_device.as_ref().and_then(|fan| fan.last_state),
+            );
+        }
+        if controlled {
+            self.last_error = None;
+        }
+        controlled
+    }
+
+    // True when the temperature climbs faster than `TEMP_RISE_RATE` since
+    // the previous check.
+    fn is_rising(&mut self, temp: f32, now: Instant) -> bool {
+        let previous = self.previous_sample.replace((temp, now));
+        let (Some(limit), Some((previous_temp, at))) = (self.config.temp_rise_rate, previous)
+        else {
+            return false;
+        };
+        let elapsed = now.saturating_duration_since(at).as_secs_f32();
+        if elapsed <= 0.0 {
+            return false;
+        }
+
+        let rate = (temp - previous_temp) / elapsed;
+        if rate > limit {
+            debug!(
+                "Temperature rising {}/s, anticipating with one more state",
+                self.config.temp_unit.display_delta(rate)
+            );
+        }
+        rate > limit
+    }
+
+    // States added by `LOAD_BIAS` for the current load average.
+    fn load_bump(&self) -> u8 {
+        let bias = self.config.load_bias;
+        let Some(fan) = self.fan_device.as_ref().filter(|_| bias != 0) else {
+            return 0;
+        };
+        match load::read_load(fan.sysfs.as_ref()) {
+            Ok(average) => {
+                let bump = load::load_bump(average, load::cpu_count(), bias);
+                if bump != 0 {
+                    debug!("Load average {average:.2}, anticipating with {bump} more states");
+                }
+                bump
+            }
+            Err(err) => {
+                warn!("Can't read the load average: {err}");
+                0
+            }
+        }
+    }
+
+    // Median of `samples` readings, re-read up to `MAX_TEMP_READ_RETRIES` times.
+    fn read_temp(temp: &Temp, samples: usize) -> Result<f32, FanError> {
+        let mut result = temp.get_median_temp(samples);
+        for retry in 1..=MAX_TEMP_READ_RETRIES {
+            let Err(err) = &result else {
+                break;
+            };
+            debug!("Can't read temperature ({err}), retry {retry}/{MAX_TEMP_READ_RETRIES}");
+            result = temp.get_median_temp(samples);
+        }
+        result
+    }
+
+    // Returns the state the fan should end at and the state to write now.
+    // `bump` states are added for a rising temperature or a high load,
+    // `deadband` is off until the first write.
+    fn decide_speed(
+        fan: &Fan,
+        current_temp: f32,
+        config: &Config,
+        bump: u8,
+        deadband: bool,
+    ) -> (u8, u8) {
+        let current_temp = config.round_for_decision(current_temp);
+        let mut desired_speed = fan.choose_speed(current_temp, config);
+        let pinned = Fan::is_critical(current_temp, config) || config.fixed_state.is_some();
+        if !pinned {
+            desired_speed = desired_speed.saturating_add(bump).min(fan.max_state);
+        }
+        let next_speed = if pinned {
+            desired_speed
+        } else {
+            let target = if deadband {
+                Self::deadband(fan, desired_speed, config)
+            } else {
+                desired_speed
+            };
+            let held_speed = fan.hold_min_on_time(target, config);
+            let held_speed = fan.hold_dwell(held_speed, current_temp, config);
+            fan.ramp(held_speed, config)
+        };
+        (desired_speed, next_speed)
+    }
+
+    // Keeps the written state as the target while the desired one is within
+    // `DEADBAND_STATES` of it. Moves to or from the min and max states always
+    // go through, so the fan can still stop and reach full speed.
+    fn deadband(fan: &Fan, desired_speed: u8, config: &Config) -> u8 {
+        let deadband = config.deadband_states;
+        let bounds = [config.state.min, fan.policy(config).max_state];
+        match fan.last_state {
+            Some(last_state)
+                if deadband != 0
+                    && last_state != desired_speed
+                    && last_state.abs_diff(desired_speed) <= deadband
+                    && !bounds.contains(&last_state)
+                    && !bounds.contains(&desired_speed) =>
+            {
+                debug!(
+                    "State {desired_speed} within {deadband} of state {last_state}, not written"
+                );
+                last_state
+            }
+            _ => desired_speed,
+        }
+    }
+
+    fn apply_speed(&mut self, desired_speed: u8, current_temp: f32) -> bool {
+        let Some(fan) = self.fan_device.as_mut() else {
+            return false;
+        };
+
+        // Last line of defense against a state the device can't take
+        let desired_speed = if desired_speed > fan.max_state {
+            warn!(
+                "Desired state {desired_speed} exceeds device max state {}, clamping",
+                fan.max_state
+            );
+            fan.max_state
+        } else {
+            desired_speed
+        };
+
+        if fan.last_state == Some(desired_speed) {
+            debug!("State unchanged");
+            return true;
+        }
+
+        if self.config.dry_run {
+            info!(
+                temp = current_temp, state = desired_speed;
+                "Dry run: would adjust fan speed to {desired_speed} (Temp: {})",
+                self.config.temp_unit.display(current_temp)
+            );
+            fan.record_state(desired_speed, self.config.state.min);
+            self.is_init = true;
+            return true;
+        }
+
+        if self.write_denied.as_ref() == Some(&fan.state) {
+            trace!("No write access to {}, skipping", fan.state.display());
+            return false;
+        }
+
+        let now = Instant::now();
+        while self
+            .writes
+            .front()
+            .is_some_and(|at| now.saturating_duration_since(*at) >= WRITE_WINDOW)
+        {
+            self.writes.pop_front();
+        }
+        let limit = self.config.max_writes_per_min;
+        let escalating = Fan::is_critical(current_temp, &self.config)
+            && fan.last_state.is_none_or(|state| desired_speed > state);
+        if limit != 0 && self.writes.len() >= limit && !escalating {
+            debug!("{limit} writes in the last minute, deferring state {desired_speed}");
+            return true;
+        }
+
+        let current_speed = match fan.read_state() {
+            Ok(speed) => speed,
+            Err(FanError::Io(e)) => {
+                self.lose_fan();
+                return self.fail(format!("Device is not available: {e}"));
+            }
+            Err(e) => return self.fail(format!("Can't parse speed value: {e}")),
+        };
+
+        if current_speed != desired_speed || !self.is_init {
+            if !self.is_init {
+                debug!("Setting the speed for the first time!");
+                self.is_init = true;
+            }
+            info!(
+                temp = current_temp, state = desired_speed;
+                "Adjusting fan speed to {desired_speed} (Temp: {})",
+                self.config.temp_unit.display(current_temp)
+            );
+            if Fan::needs_kick(current_speed, desired_speed, &self.config) {
+                debug!(
+                    "Kicking fan at state {} for {} ms",
+                    fan.max_state, self.config.spinup_ms
+                );
+                if let Err(err) = fan.kick(Duration::from_millis(self.config.spinup_ms)) {
+                    return self.write_failed("kick fan", &err);
+                }
+            }
+            if let Err(err) = fan.write_state(desired_speed) {
+                return self.write_failed("set speed", &err);
+            }
+            fan.record_state(desired_speed, self.config.state.min);
+            self.step.wrote = true;
+            self.writes.push_back(now);
+        } else {
+            debug!(
+                "Temp: {}, no speed change needed",
+                self.config.temp_unit.display(current_temp)
+            );
+        }
+
+        true
+    }
+
+    fn write_status(&self, current_temp: f32, desired_speed: u8, max_state: u8) {
+        let Some(path) = &self.config.state_file else {
+            return;
+        };
+
+        let current_state = self.fan_device.as_ref().and_then(|fan| fan.last_state);
+        let status = Status::new(current_temp, current_state, desired_speed, max_state);
+        if let Err(err) = status.write_atomic(path) {
+            error!("Can't write state file {}: {err}", path.display());
+        }
+    }
+
+    // Returns true when max state should be written to recover a stalled fan
+    fn check_rpm(fan: &mut Fan, config: &Config) -> bool {
+        let Some(rpm) = fan.read_rpm() else {
+            return false;
+        };
+
+        let state = match fan.last_state {
+            Some(state) if state > 0 && rpm <= config.stall_rpm => state,
+            last_state => {
+                if fan.stall != Stall::Spinning {
+                    info!("Fan spinning again at {rpm} RPM");
+                    fan.stall = Stall::Spinning;
+                }
+                match last_state {
+                    Some(state) => debug!("Fan speed {rpm} RPM (state {state})"),
+                    None => debug!("Fan speed {rpm} RPM"),
+                }
+                return false;
+            }
+        };
+
+        fan.stall = match fan.stall {
+            Stall::Spinning | Stall::Suspected(_) => {
+                let checks = match fan.stall {
+                    Stall::Suspected(checks) => checks + 1,
+                    _ => 1,
+                };
+                if checks >= config.stall_checks {
+                    error!(
+                        "Fan reports {rpm} RPM at state {state} for {checks} checks, writing max state {} to recover",
+                        fan.max_state
+                    );
+                    Stall::Recovering
+                } else {
+                    warn!(
+                        "Fan reports {rpm} RPM while state {state} is commanded, it may be stalled"
+                    );
+                    Stall::Suspected(checks)
+                }
+            }
+            Stall::Recovering => {
+                error!("Fan still reports {rpm} RPM at max state, it is stalled");
+                Stall::Stalled
+            }
+            Stall::Stalled => {
+                debug!("Fan still stalled at {rpm} RPM");
+                Stall::Stalled
+            }
+        };
+        fan.stall == Stall::Recovering
+    }
+
+    fn shutdown(&mut self) {
+        self.write_shutdown_state();
+        if let Some(governor) = &mut self.governor {
+            governor.restore();
+        }
+    }
+
+    fn write_shutdown_state(&mut self) {
+        if self.fan_device.is_none() {
+            if let Ok(device) = self.open_fan() {
+                self.fan_device = Some(device);
+            } else {
+                error!("No fan device available, can't restore a safe state");
+                return;
+            }
+        }
+
+        let fan = self.fan_device.as_mut().unwrap();
+        let state = self
+            .config
+            .shutdown_state
+            .unwrap_or(fan.max_state)
+            .min(fan.max_state);
+
+        if self.config.dry_run {
+            info!("Dry run: would set fan speed to {state} on shutdown");
+            return;
+        }
+
+        info!("Shutting down, setting fan speed to {state}");
+        match fan.write_state(state) {
+            Ok(()) => fan.record_state(state, self.config.state.min),
+            Err(err) => error!("Can't set speed on device {}: {err}", fan.state.display()),
+        }
+    }
+}
+
+fn write_table(output: &mut String, header: [&str; 3], rows: &[[String; 3]]) {
+    let mut widths = header.map(|title| title.chars().count());
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let [first, second, _] = widths;
+    let _ = writeln!(
+        output,
+        "{:<first$}  {:<second$}  {}",
+        header[0], header[1], header[2]
+    );
+    for [name, kind, value] in rows {
+        let _ = writeln!(output, "{name:<first$}  {kind:<second$}  {value}");
+    }
+    if rows.is_empty() {
+        let _ = writeln!(output, "(none found)");
+    }
+}
+
+// UTC when the local time zone can't be found
+fn local_minutes() -> u16 {
+    let now = jiff::Zoned::now();
+    u16::try_from(now.hour()).unwrap_or(0) * 60 + u16::try_from(now.minute()).unwrap_or(0)
+}
+
+// Offset in [-jitter, +jitter] at millisecond resolution picked from
+// `random`, never going below zero.
+fn apply_jitter(sleep_time: Duration, jitter: Duration, random: u64) -> Duration {
+    let span = u64::try_from(jitter.as_millis()).unwrap_or(u64::MAX / 2);
+    let offset = random % (2 * span + 1);
+    if offset >= span {
+        sleep_time.saturating_add(Duration::from_millis(offset - span))
+    } else {
+        sleep_time.saturating_sub(Duration::from_millis(span - offset))
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::float_cmp)]
+mod tests {
+    use super::*;
+    use crate::fan::Backend;
+    use crate::{PWM_DIR, THERMAL_DIR};
+    use std::fs;
+
+    use crate::config::{
+        ConfigBuilder, DEFAULT_MAX_STATE, DEFAULT_SLEEP_TIME, FanBackend, Pwm, State,
+        TempAggregation, TempScale, Threshold,
+    };
+    use crate::sysfs::{MockSysfs, RealSysfs};
+    use std::path::PathBuf;
+    use std::sync::{Arc, Mutex};
+
+    fn create_test_config() -> Config {
+        ConfigBuilder::new()
+            .min_threshold(45.0)
+            .max_threshold(70.0)
+            .min_state(0)
+            .max_state(DEFAULT_MAX_STATE)
+            .sleep_time(DEFAULT_SLEEP_TIME)
+            .build()
+            .unwrap()
+    }
+
+    struct TestEnv {
+        path: PathBuf,
+    }
+
+    impl TestEnv {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(name);
+            fs::create_dir_all(&path).unwrap();
+            Self { path }
+        }
+
+        fn create_fan(&self, state_content: &str, last_state: Option<u8>) -> Fan {
+            let state_file = self.path.join("cur_state");
+            fs::write(&state_file, state_content).unwrap();
+
+            Fan {
+                path: self.path.clone(),
+                state: state_file,
+                backend: Backend::CoolingDevice,
+                tach: None,
+                max_state: DEFAULT_MAX_STATE,
+                temp_slots: [
+                    Some((1, 45.0)),
+                    Some((2, 50.0)),
+                    Some((3, 55.0)),
+                    Some((4, 60.0)),
+                    Some((5, 65.0)),
+                    (None),
+                ],
+                last_state,
+                spun_up_at: None,
+                changed_at: None,
+                stall: Stall::Spinning,
+                curve: None,
+                inverted: false,
+                sysfs: Arc::new(RealSysfs),
+            }
+        }
+
+        fn create_temp(&self, content: &str) -> Temp {
+            let temp_file = self.path.join("temp");
+            fs::write(&temp_file, content).unwrap();
+
+            Temp {
+                paths: vec![temp_file],
+                aggregation: TempAggregation::First,
+                scale: TempScale::Auto,
+                weights: Vec::new(),
+                offset: 0.0,
+                sysfs: Arc::new(RealSysfs),
+                detected_scales: Mutex::default(),
+            }
+        }
+    }
+
+    impl Drop for TestEnv {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.path);
+        }
+    }
+
+    #[test]
+    fn test_adjust_speed_refreshes_changed_max_state() {
+        let env = TestEnv::new("test_checker_refresh_max_state");
+        fs::write(env.path.join("max_state"), "5\n").unwrap();
+        let config = Config {
+            fan_device_path: Some(env.path.clone()),
+            rescan_interval: 2,
+            ..create_test_config()
+        };
+        let fan = env.create_fan("0", None);
+        let temp = env.create_temp("80000");
+        let mut checker = Checker::with_devices(config, Some(fan), Some(temp));
+
+        assert!(checker.adjust_speed());
+        assert_eq!(checker.channels[0].last_state(), Some(5));
+
+        // Fan replaced by one with fewer states, noticed every other loop
+        fs::write(env.path.join("max_state"), "3\n").unwrap();
+        assert!(checker.adjust_speed());
+        assert_eq!(checker.channels[0].last_state(), Some(5));
+
+        assert!(checker.adjust_speed());
+        let fan = checker.channels[0].fan_device.as_ref().unwrap();
+        assert_eq!(fan.max_state, 3);
+        assert_eq!(fan.last_state, Some(3));
+        assert_eq!(fs::read_to_string(&fan.state).unwrap(), "3");
+        // MAX_STATE=5 doesn't fit the new fan anymore
+        assert_eq!(checker.channels[0].config.state.max, None);
+    }
+
+    #[test]
+    fn test_startup_summary() {
+        let env = TestEnv::new("test_checker_startup_summary");
+        fs::write(env.path.join("type"), "soc-thermal\n").unwrap();
+        let fan = env.create_fan("0", None);
+        let temp = env.create_temp("45000");
+        let checker = Checker::with_devices(create_test_config(), Some(fan), Some(temp));
+
+        let summary = checker.startup_summary();
+        let temp_path = env.path.join("temp");
+        let expected = [
+            "min threshold      45.00°C".to_owned(),
+            format!("fan path           {}", env.path.display()),
+            "device max state   5".to_owned(),
+            "computed slots     1@45.00°C, 2@50.00°C".to_owned(),
+            format!("temp paths         {} (soc-thermal)", temp_path.display()),
+        ];
+        for line in expected {
+            assert!(summary.contains(&line), "{line} missing from\n{summary}");
+        }
+
+        let checker = Checker::with_devices(create_test_config(), None, None);
+        let summary = checker.startup_summary();
+        assert!(summary.contains("fan path           unavailable (not found)"));
+    }
+
+    #[test]
+    fn test_validate_devices() {
+        let env = TestEnv::new("test_checker_validate_devices");
+        fs::write(env.path.join("max_state"), "5\n").unwrap();
+        fs::write(env.path.join("cur_state"), "2\n").unwrap();
+        fs::write(env.path.join("temp"), "47000\n").unwrap();
+        let config = Config {
+            fan_device_path: Some(env.path.clone()),
+            thermal_zone_path: Some(env.path.join("temp")),
+            ..Config::default()
+        };
+
+        let mut report = String::new();
+        assert!(Checker::validate_devices(
+            &config,
+            Arc::new(RealSysfs),
+            &mut report
+        ));
+        assert!(report.contains("[ok  ] fan read    state 2"), "{report}");
+        assert!(report.contains("[ok  ] temperature 47.00°C"), "{report}");
+        assert!(!report.contains("FAIL"), "{report}");
+        assert_eq!(
+            fs::read_to_string(env.path.join("cur_state")).unwrap(),
+            "2\n"
+        );
+    }
+
+    #[test]
+    fn test_validate_devices_leaves_pwm_alone() {
+        let env = TestEnv::new("test_checker_validate_pwm");
+        let chip = env.path.join(PWM_DIR).join("pwmchip0");
+        fs::create_dir_all(&chip).unwrap();
+        fs::write(chip.join("export"), "").unwrap();
+        let hwmon = env.path.join("hwmon0");
+        fs::create_dir_all(&hwmon).unwrap();
+        fs::write(hwmon.join("pwm1"), "0\n").unwrap();
+        fs::write(hwmon.join("pwm1_enable"), "2\n").unwrap();
+        fs::write(env.path.join("temp"), "47000\n").unwrap();
+        let config = Config {
+            fan_backend: FanBackend::PwmChip,
+            sysfs_root: env.path.clone(),
+            thermal_zone_path: Some(env.path.join("temp")),
+            ..Config::default()
+        };
+
+        let mut report = String::new();
+        assert!(
+            Checker::validate_devices(&config, Arc::new(RealSysfs), &mut report),
+            "{report}"
+        );
+        assert!(report.contains("not exported yet"), "{report}");
+        assert!(!chip.join("pwm0").exists());
+        assert_eq!(fs::read_to_string(chip.join("export")).unwrap(), "");
+
+        let config = Config {
+            fan_backend: FanBackend::Hwmon,
+            pwm: Pwm {
+                sysfs_path: Some(hwmon.join("pwm1")),
+                ..Config::default().pwm
+            },
+            ..config
+        };
+        let mut report = String::new();
+        assert!(
+            Checker::validate_devices(&config, Arc::new(RealSysfs), &mut report),
+            "{report}"
+        );
+        assert_eq!(
+            fs::read_to_string(hwmon.join("pwm1_enable")).unwrap(),
+            "2\n"
+        );
+        assert_eq!(fs::read_to_string(hwmon.join("pwm1")).unwrap(), "0\n");
+    }
+
+    #[test]
+    fn test_validate_starts_with_the_report() {
+        let env = TestEnv::new("test_checker_validate_report");
+        let config = Config {
+            sysfs_root: env.path.clone(),
+            ..create_test_config()
+        };
+
+        let (report, passed) = Checker::validate(&Args::default(), &config);
+        assert!(!passed);
+        assert!(report.starts_with("[FAIL] fan device"), "{report}");
+    }
+
+    #[test]
+    fn test_validate_devices_invalid_config() {
+        let env = TestEnv::new("test_checker_validate_invalid_config");
+        fs::write(env.path.join("max_state"), "5\n").unwrap();
+        fs::write(env.path.join("cur_state"), "2\n").unwrap();
+        fs::write(env.path.join("temp"), "47000\n").unwrap();
+        let config = Config {
+            fan_device_path: Some(env.path.clone()),
+            thermal_zone_path: Some(env.path.join("temp")),
+            state: State {
+                max: Some(7),
+                min: 0,
+            },
+            ..Config::default()
+        };
+
+        let mut report = String::new();
+        assert!(!Checker::validate_devices(
+            &config,
+            Arc::new(RealSysfs),
+            &mut report
+        ));
+        assert!(report.contains("[FAIL] config"), "{report}");
+        assert!(report.contains("[ok  ] temperature"), "{report}");
+        assert_eq!(
+            fs::read_to_string(env.path.join("cur_state")).unwrap(),
+            "2\n"
+        );
+    }
+
+    #[test]
+    fn test_checker_structure() {
+        let checker = Checker::with_devices(create_test_config(), None, None);
+        assert!(!checker.channels[0].is_init);
+        assert!(checker.channels[0].fan_device.is_none());
+        assert!(checker.channels[0].temp_device.is_none());
+    }
+
+    #[test]
+    fn test_adjust_speed_without_fan_device() {
+        let mut checker = Checker::with_devices(create_test_config(), None, None);
+
+        assert!(!checker.adjust_speed());
+    }
+
+    #[test]
+    fn test_checker_with_mock_fan() {
+        let env = TestEnv::new("test_checker_mock");
+        let fan = env.create_fan("2", None);
+        let temp = env.create_temp("55000");
+
+        let mut checker = Checker::with_devices(create_test_config(), Some(fan), Some(temp));
+
+        assert!(checker.adjust_speed());
+        assert!(checker.channels[0].is_init);
+    }
+
+    #[test]
+    fn test_adjust_speed_with_same_desired_speed() {
+        let env = TestEnv::new("test_checker_same_speed");
+        let fan = env.create_fan("3", Some(3));
+        let temp = env.create_temp("55000");
+
+        let mut checker = Checker::with_devices(create_test_config(), Some(fan), Some(temp));
+        checker.channels[0].is_init = true;
+
+        checker.adjust_speed();
+    }
+
+    #[test]
+    fn test_adjust_speed_holds_warmup_state() {
+        let env = TestEnv::new("test_checker_warmup");
+        let fan = env.create_fan("0", None);
+        let state = fan.state.clone();
+        let temp = env.create_temp("80000");
+        let config = Config {
+            warmup_time: 60,
+            ..create_test_config()
+        };
+        let mut checker = Checker::with_devices(config, Some(fan), Some(temp));
+
+        assert!(checker.adjust_speed());
+        assert_eq!(fs::read_to_string(&state).unwrap(), "0");
+
+        checker.channels[0].config.warmup_state = Some(2);
+        assert!(checker.adjust_speed());
+        assert_eq!(fs::read_to_string(&state).unwrap(), "2");
+
+        checker.started_at = Instant::now().checked_sub(Duration::from_mins(1)).unwrap();
+        assert!(checker.adjust_speed());
+        assert_eq!(fs::read_to_string(&state).unwrap(), "5");
+    }
+
+    #[test]
+    fn test_adjust_speed_failsafe_after_device_loss() {
+        let env = TestEnv::new("test_checker_failsafe");
+        let fan = env.create_fan("0", None);
+        let state = fan.state.clone();
+        let config = Config {
+            on_device_loss: OnDeviceLoss::Failsafe,
+            ..create_test_config()
+        };
+        let mut checker = Checker::with_devices(config, Some(fan), Some(env.create_temp("52000")));
+        assert!(checker.adjust_speed());
+        assert_eq!(fs::read_to_string(&state).unwrap(), "2");
+
+        fs::remove_file(&state).unwrap();
+        env.create_temp("58000");
+        assert!(!checker.adjust_speed());
+        let channel = &checker.channels[0];
+        assert!(channel.fan_device.is_none());
+        assert!(!channel.is_init);
+        assert!(channel.failsafe_pending);
+
+        // Rediscovered
+        checker.channels[0].fan_device = Some(env.create_fan("2", Some(2)));
+        assert!(checker.adjust_speed());
+        assert_eq!(fs::read_to_string(&state).unwrap(), "5");
+        assert!(checker.channels[0].is_init);
+
+        // Back on the curve, within the hysteresis band of state 4
+        assert!(checker.adjust_speed());
+        assert_eq!(fs::read_to_string(&state).unwrap(), "4");
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn test_run_until_shutdown() {
+        let env = TestEnv::new("test_checker_run");
+        let fan = env.create_fan("0", None);
+        let state = fan.state.clone();
+        let config = Config {
+            shutdown_state: Some(1),
+            ..create_test_config()
+        };
+        let checker = Checker::with_devices(config, Some(fan), Some(env.create_temp("58000")));
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        let checker = runtime.block_on(async {
+            let shutdown = tokio::time::sleep(Duration::from_millis(50));
+            checker.run(shutdown).await
+        });
+
+        assert_eq!(checker.loops, 1);
+        assert!(checker.last_success.is_some());
+        assert_eq!(fs::read_to_string(&state).unwrap(), "1");
+    }
+
+    #[test]
+    fn test_temperature_range() {
+        let env = TestEnv::new("test_checker_temp_range");
+        let fan = env.create_fan("0", None);
+        let mut checker = Checker::with_devices(
+            create_test_config(),
+            Some(fan),
+            Some(env.create_temp("52000")),
+        );
+        assert_eq!(checker.metrics(), "");
+
+        for temp in ["58000", "47000"] {
+            checker.adjust_speed();
+            env.create_temp(temp);
+        }
+        checker.adjust_speed();
+        assert_eq!(checker.min_temp(), Some(47.0));
+        assert_eq!(checker.max_temp(), Some(58.0));
+        assert!(
+            checker
+                .metrics()
+                .ends_with("cm3588_fan_temperature_max_celsius 58")
+        );
+
+        checker.reset_stats();
+        assert_eq!(checker.min_temp(), None);
+        assert_eq!(checker.max_temp(), None);
+    }
+
+    #[test]
+    fn test_critical_trip_replaces_critical_temp() {
+        let env = TestEnv::new("test_checker_critical_trip");
+        let config = Config {
+            critical_temp: Some(80.0),
+            critical_trip: true,
+            ..create_test_config()
+        };
+        let mut channel = Channel::with_devices(config, None, None);
+
+        channel.set_temp_device(env.create_temp("45000"));
+        assert_eq!(channel.config.critical_temp, Some(80.0));
+
+        fs::write(env.path.join("trip_point_0_type"), "critical\n").unwrap();
+        fs::write(env.path.join("trip_point_0_temp"), "95000\n").unwrap();
+        channel.set_temp_device(env.create_temp("45000"));
+        assert_eq!(channel.config.critical_temp, Some(95.0));
+
+        fs::remove_file(env.path.join("trip_point_0_type")).unwrap();
+        channel.set_temp_device(env.create_temp("45000"));
+        assert_eq!(channel.config.critical_temp, Some(80.0));
+    }
+
+    #[test]
+    fn test_adjust_speed_paused() {
+        let env = TestEnv::new("test_checker_paused");
+        let pause_file = env.path.join("pause");
+        let fan = env.create_fan("0", None);
+        let state = fan.state.clone();
+        let config = Config {
+            pause_file: Some(pause_file.clone()),
+            ..create_test_config()
+        };
+        let mut checker = Checker::with_devices(config, Some(fan), Some(env.create_temp("58000")));
+
+        fs::write(&pause_file, "").unwrap();
+        assert!(checker.adjust_speed());
+        assert_eq!(fs::read_to_string(&state).unwrap(), "0");
+
+        fs::remove_file(&pause_file).unwrap();
+        assert!(checker.adjust_speed());
+        assert_eq!(fs::read_to_string(&state).unwrap(), "3");
+    }
+
+    #[test]
+    fn test_adjust_speed_with_invalid_temp_file() {
+        let env = TestEnv::new("test_checker_invalid_temp");
+        let fan = env.create_fan("2", None);
+        let temp = env.create_temp("invalid");
+
+        let mut checker = Checker::with_devices(create_test_config(), Some(fan), Some(temp));
+
+        assert!(!checker.adjust_speed());
+        assert!(checker.channels[0].temp_device.is_none());
+    }
+
+    #[test]
+    fn test_adjust_speed_with_invalid_speed_file() {
+        let env = TestEnv::new("test_checker_invalid_speed");
+        let fan = env.create_fan("invalid_speed", None);
+        let temp = env.create_temp("50000");
+
+        let mut checker = Checker::with_devices(create_test_config(), Some(fan), Some(temp));
+
+        checker.adjust_speed();
+    }
+
+    #[test]
+    fn test_adjust_speed_without_temp_device() {
+        let env = TestEnv::new("test_checker_no_temp");
+        let fan = env.create_fan("2", None);
+
+        let mut checker = Checker::with_devices(create_test_config(), Some(fan), None);
+
+        checker.adjust_speed();
+    }
+
+    #[test]
+    fn test_adjust_speed_hysteresis_keeps_state_stable() {
+        let env = TestEnv::new("test_checker_hysteresis");
+        let fan = env.create_fan("0", None);
+        let temp = env.create_temp("55500");
+
+        let mut checker = Checker::with_devices(create_test_config(), Some(fan), Some(temp));
+
+        checker.adjust_speed();
+        assert_eq!(
+            checker.channels[0].fan_device.as_ref().unwrap().last_state,
+            Some(3)
+        );
+
+        for temp in ["54500", "55500", "54000", "55000", "53500"] {
+            env.create_temp(temp);
+            checker.adjust_speed();
+            assert_eq!(
+                checker.channels[0].fan_device.as_ref().unwrap().last_state,
+                Some(3)
+            );
+        }
+
+        env.create_temp("52500");
+        checker.adjust_speed();
+        assert_eq!(
+            checker.channels[0].fan_device.as_ref().unwrap().last_state,
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn test_shutdown_writes_max_state() {
+        let env = TestEnv::new("test_checker_shutdown_max");
+        let fan = env.create_fan("1", Some(1));
+
+        let mut checker = Checker::with_devices(create_test_config(), Some(fan), None);
+        checker.channels[0].is_init = true;
+
+        checker.shutdown();
+        let fan = checker.channels[0].fan_device.as_ref().unwrap();
+        assert_eq!(fs::read_to_string(&fan.state).unwrap(), "5");
+        assert_eq!(fan.last_state, Some(DEFAULT_MAX_STATE));
+    }
+
+    #[test]
+    fn test_shutdown_writes_configured_state() {
+        let env = TestEnv::new("test_checker_shutdown_configured");
+        let fan = env.create_fan("1", Some(1));
+
+        let mut checker = Checker::with_devices(
+            Config {
+                shutdown_state: Some(3),
+                ..create_test_config()
+            },
+            Some(fan),
+            None,
+        );
+        checker.channels[0].is_init = true;
+
+        checker.shutdown();
+        let fan = checker.channels[0].fan_device.as_ref().unwrap();
+        assert_eq!(fs::read_to_string(&fan.state).unwrap(), "3");
+    }
+
+    #[test]
+    fn test_shutdown_clamps_to_device_max_state() {
+        let env = TestEnv::new("test_checker_shutdown_clamp");
+        let fan = env.create_fan("1", Some(1));
+
+        let mut checker = Checker::with_devices(
+            Config {
+                shutdown_state: Some(9),
+                ..create_test_config()
+            },
+            Some(fan),
+            None,
+        );
+        checker.channels[0].is_init = true;
+
+        checker.shutdown();
+        let fan = checker.channels[0].fan_device.as_ref().unwrap();
+        assert_eq!(fs::read_to_string(&fan.state).unwrap(), "5");
+    }
+
+    #[test]
+    fn test_dry_run_shutdown_leaves_state() {
+        let env = TestEnv::new("test_checker_shutdown_dry_run");
+        let fan = env.create_fan("1", Some(1));
+
+        let mut checker = Checker::with_devices(
+            Config {
+                dry_run: true,
+                ..create_test_config()
+            },
+            Some(fan),
+            None,
+        );
+        checker.channels[0].is_init = true;
+
+        checker.shutdown();
+        let fan = checker.channels[0].fan_device.as_ref().unwrap();
+        assert_eq!(fs::read_to_string(&fan.state).unwrap(), "1");
+    }
+
+    #[test]
+    fn test_adjust_speed_writes_to_cur_state_file() {
+        let env = TestEnv::new("test_checker_writes_cur_state");
+        let fan = env.create_fan("0", None);
+        let temp = env.create_temp("55000");
+
+        let mut checker = Checker::with_devices(create_test_config(), Some(fan), Some(temp));
+
+        checker.adjust_speed();
+
+        let fan = checker.channels[0].fan_device.as_ref().unwrap();
+        assert_eq!(fan.state, env.path.join("cur_state"));
+        assert_eq!(fs::read_to_string(&fan.state).unwrap(), "3");
+        assert!(fan.path.is_dir());
+        assert_eq!(fan.last_state, Some(3));
+    }
+
+    #[test]
+    fn test_adjust_speed_clamps_to_device_max_state() {
+        let env = TestEnv::new("test_checker_clamps_max_state");
+        let fan = Fan {
+            max_state: 2,
+            ..env.create_fan("0", None)
+        };
+        let temp = env.create_temp("80000");
+
+        let mut checker = Checker::with_devices(create_test_config(), Some(fan), Some(temp));
+
+        assert!(checker.adjust_speed());
+
+        let fan = checker.channels[0].fan_device.as_ref().unwrap();
+        assert_eq!(fs::read_to_string(&fan.state).unwrap(), "2");
+        assert_eq!(fan.last_state, Some(2));
+    }
+
+    #[test]
+    fn test_adjust_speed_inverted_fan() {
+        let env = TestEnv::new("test_checker_inverted");
+        let fan = Fan {
+            inverted: true,
+            ..env.create_fan("5", None)
+        };
+        let temp = env.create_temp("80000");
+
+        let mut checker = Checker::with_devices(create_test_config(), Some(fan), Some(temp));
+
+        assert!(checker.adjust_speed());
+        let state = env.path.join("cur_state");
+        assert_eq!(fs::read_to_string(&state).unwrap(), "0");
+
+        fs::write(env.path.join("temp"), "30000").unwrap();
+        checker.channels[0].fan_device.as_mut().unwrap().last_state = None;
+        assert!(checker.adjust_speed());
+        assert_eq!(fs::read_to_string(&state).unwrap(), "5");
+        assert_eq!(
+            checker.channels[0].fan_device.as_ref().unwrap().last_state,
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn test_adjust_speed_retries_bad_temperature_sample() {
+        let env = TestEnv::new("test_checker_temp_retry");
+        let sysfs = Arc::new(MockSysfs::default());
+        let zone = PathBuf::from("thermal_zone0/temp");
+        sysfs.set(&zone, "58000");
+        let temp = Temp {
+            paths: vec![zone.clone()],
+            aggregation: TempAggregation::First,
+            scale: TempScale::Auto,
+            weights: Vec::new(),
+            offset: 0.0,
+            sysfs: sysfs.clone(),
+            detected_scales: Mutex::default(),
+        };
+        let fan = env.create_fan("0", None);
+        let state = fan.state.clone();
+        let mut checker = Checker::with_devices(create_test_config(), Some(fan), Some(temp));
+
+        sysfs.fail_reads(&zone, 1);
+        assert!(checker.adjust_speed());
+        assert!(checker.channels[0].temp_device.is_some());
+        assert_eq!(fs::read_to_string(&state).unwrap(), "3");
+
+        sysfs.fail_reads(&zone, MAX_TEMP_READ_RETRIES + 1);
+        assert!(!checker.adjust_speed());
+        assert!(checker.channels[0].temp_device.is_none());
+    }
+
+    const MEMORY_STATE: &str = "cooling_device0/cur_state";
+    const MEMORY_ZONE: &str = "thermal_zone0/temp";
+
+    // Fan and thermal zone kept in `sysfs`, the fan starts at state 0 and
+    // the zone at 40°C.
+    fn create_memory_checker(sysfs: &Arc<MockSysfs>, config: Config) -> Checker {
+        sysfs.set(MEMORY_STATE, "0");
+        sysfs.set(MEMORY_ZONE, "40000");
+
+        let fan = Fan {
+            path: "cooling_device0".into(),
+            state: MEMORY_STATE.into(),
+            backend: Backend::CoolingDevice,
+            tach: None,
+            max_state: DEFAULT_MAX_STATE,
+            temp_slots: [
+                Some((1, 45.0)),
+                Some((2, 50.0)),
+                Some((3, 55.0)),
+                Some((4, 60.0)),
+                Some((5, 65.0)),
+                None,
+            ],
+            last_state: None,
+            spun_up_at: None,
+            changed_at: None,
+            stall: Stall::Spinning,
+            curve: None,
+            inverted: false,
+            sysfs: sysfs.clone(),
+        };
+        Checker::with_devices(config, Some(fan), Some(create_memory_temp(sysfs)))
+    }
+
+    fn create_memory_temp(sysfs: &Arc<MockSysfs>) -> Temp {
+        Temp {
+            paths: vec![MEMORY_ZONE.into()],
+            aggregation: TempAggregation::First,
+            scale: TempScale::Auto,
+            weights: Vec::new(),
+            offset: 0.0,
+            sysfs: sysfs.clone(),
+            detected_scales: Mutex::default(),
+        }
+    }
+
+    #[test]
+    fn test_adjust_speed_sub_zero_temperature() {
+        let sysfs = Arc::new(MockSysfs::default());
+        let state = PathBuf::from(MEMORY_STATE);
+        let config = Config {
+            state: State {
+                max: Some(DEFAULT_MAX_STATE),
+                min: 1,
+            },
+            ..create_test_config()
+        };
+        let mut checker = create_memory_checker(&sysfs, config);
+        sysfs.set(MEMORY_ZONE, "-10000");
+
+        assert!(checker.adjust_speed());
+        assert_eq!(checker.channels[0].smoothed_temp, Some(-10.0));
+        assert_eq!(sysfs.get(&state).as_deref(), Some("1"));
+        assert_eq!(checker.min_temp(), Some(-10.0));
+    }
+
+    #[test]
+    fn test_adjust_speed_temp_offset() {
+        let sysfs = Arc::new(MockSysfs::default());
+        let state = PathBuf::from(MEMORY_STATE);
+        let mut checker = create_memory_checker(&sysfs, create_test_config());
+        sysfs.set(MEMORY_ZONE, "52000");
+        assert!(checker.adjust_speed());
+        assert_eq!(sysfs.get(&state).as_deref(), Some("2"));
+
+        // The zone reads 4°C low, the corrected 56°C is in the next slot
+        let mut checker = create_memory_checker(&sysfs, create_test_config());
+        sysfs.set(MEMORY_ZONE, "52000");
+        checker.channels[0].set_temp_device(Temp {
+            offset: 4.0,
+            ..create_memory_temp(&sysfs)
+        });
+        assert!(checker.adjust_speed());
+        assert_eq!(checker.channels[0].smoothed_temp, Some(56.0));
+        assert_eq!(sysfs.get(&state).as_deref(), Some("3"));
+    }
+
+    #[test]
+    fn test_with_sysfs_discovers_devices() {
+        let sysfs = Arc::new(MockSysfs::default());
+        let config = create_test_config();
+        let dir = config.thermal_dir();
+        let zone = dir.join("thermal_zone0/temp");
+        let device = dir.join("cooling_device0");
+        let state = device.join("cur_state");
+        sysfs.set(&zone, "40000");
+        sysfs.set(dir.join("thermal_zone0/type"), "cpu-thermal\n");
+        sysfs.set(device.join("type"), "pwm-fan\n");
+        sysfs.set(device.join("max_state"), "5\n");
+        sysfs.set(&state, "0");
+
+        let devices: Arc<dyn SysfsAccess> = sysfs.clone();
+        let mut checker = Checker::with_sysfs(&Args::default(), config, &devices);
+        let channel = &checker.channels[0];
+        assert_eq!(channel.fan_device.as_ref().unwrap().path, device);
+        assert_eq!(
+            channel.temp_device.as_ref().unwrap().paths,
+            vec![zone.clone()]
+        );
+
+        for (temp, expected) in [("40000", "0"), ("52000", "2"), ("75000", "5")] {
+            sysfs.set(&zone, temp);
+            assert!(checker.adjust_speed());
+            assert_eq!(sysfs.get(&state).as_deref(), Some(expected));
+        }
+    }
+
+    #[test]
+    fn test_describe_starts_with_the_report() {
+        let env = TestEnv::new("test_checker_describe_report");
+        let config = Config {
+            sysfs_root: env.path.clone(),
+            ..create_test_config()
+        };
+
+        let description = Checker::describe(&Args::default(), &config);
+        assert!(description.starts_with("min threshold"), "{description}");
+        assert!(description.contains("\nfan device"), "{description}");
+    }
+
+    #[test]
+    fn test_describe_devices_leaves_pwm_alone() {
+        let env = TestEnv::new("test_checker_describe_pwm");
+        let chip = env.path.join(PWM_DIR).join("pwmchip0");
+        fs::create_dir_all(&chip).unwrap();
+        fs::write(chip.join("export"), "").unwrap();
+        fs::write(env.path.join("pwm1_enable"), "2\n").unwrap();
+        let config = Config {
+            fan_backend: FanBackend::PwmChip,
+            sysfs_root: env.path.clone(),
+            ..create_test_config()
+        };
+
+        let description = Checker::describe_devices(&config, Arc::new(RealSysfs));
+        let path = format!("fan path           {}", chip.join("pwm0").display());
+        assert!(description.contains(&path), "{description}");
+        assert!(!chip.join("pwm0").exists());
+        assert_eq!(fs::read_to_string(chip.join("export")).unwrap(), "");
+
+        let config = Config {
+            fan_backend: FanBackend::Hwmon,
+            pwm: Pwm {
+                sysfs_path: Some(env.path.join("pwm1")),
+                ..Config::default().pwm
+            },
+            ..config
+        };
+        Checker::describe_devices(&config, Arc::new(RealSysfs));
+        assert_eq!(
+            fs::read_to_string(env.path.join("pwm1_enable")).unwrap(),
+            "2\n"
+        );
+    }
+
+    #[test]
+    fn test_list_devices() {
+        let env = TestEnv::new("test_checker_list_devices");
+        for (name, file, content) in [
+            ("thermal_zone0", "type", "soc-thermal\n"),
+            ("thermal_zone0", "temp", "45000\n"),
+            ("thermal_zone1", "type", "gpu-thermal\n"),
+            ("cooling_device0", "type", "cpufreq-cpu0\n"),
+            ("cooling_device0", "max_state", "12\n"),
+            ("cooling_device1", "type", "pwm-fan\n"),
+            ("cooling_device1", "max_state", "5\n"),
+        ] {
+            fs::create_dir_all(env.path.join(name)).unwrap();
+            fs::write(env.path.join(name).join(file), content).unwrap();
+        }
+
+        assert_eq!(
+            Checker::list_devices_in(&RealSysfs, &env.path, &create_test_config()),
+            "ZONE           TYPE         TEMP\n\
+             thermal_zone0  soc-thermal  45.0°C\n\
+             thermal_zone1  gpu-thermal  -\n\
+             \n\
+             DEVICE           TYPE          MAX STATE\n\
+             cooling_device0  cpufreq-cpu0  12\n\
+             cooling_device1  pwm-fan       5\n"
+        );
+
+        let empty = TestEnv::new("test_checker_list_devices_empty");
+        assert!(
+            Checker::list_devices_in(&RealSysfs, &empty.path, &create_test_config())
+                .starts_with("ZONE  TYPE  TEMP\n(none found)\n")
+        );
+        // Nothing but the tables on stdout
+        let config = Config {
+            sysfs_root: empty.path.clone(),
+            ..create_test_config()
+        };
+        assert!(Checker::list_devices(&config).starts_with("ZONE  TYPE  TEMP\n"));
+    }
+
+    #[test]
+    fn test_adjust_speed_max_writes_per_min() {
+        let sysfs = Arc::new(MockSysfs::default());
+        let state = PathBuf::from(MEMORY_STATE);
+        let config = Config {
+            max_writes_per_min: 2,
+            critical_temp: Some(80.0),
+            ..create_test_config()
+        };
+        let mut checker = create_memory_checker(&sysfs, config);
+
+        for (temp, expected) in [("50000", "2"), ("55000", "3"), ("60000", "3")] {
+            sysfs.set(MEMORY_ZONE, temp);
+            assert!(checker.adjust_speed());
+            assert_eq!(sysfs.get(&state).as_deref(), Some(expected));
+        }
+
+        // A critical temperature isn't held back by the limit
+        sysfs.set(MEMORY_ZONE, "85000");
+        assert!(checker.adjust_speed());
+        assert_eq!(sysfs.get(&state).as_deref(), Some("5"));
+
+        // The deferred change goes through once the window rolled over
+        let expired = Instant::now().checked_sub(WRITE_WINDOW).unwrap();
+        checker.channels[0]
+            .writes
+            .iter_mut()
+            .for_each(|at| *at = expired);
+        sysfs.set(MEMORY_ZONE, "60000");
+        assert!(checker.adjust_speed());
+        assert_eq!(sysfs.get(&state).as_deref(), Some("4"));
+    }
+
+    #[test]
+    fn test_adjust_speed_external_change() {
+        let sysfs = Arc::new(MockSysfs::default());
+        let state = PathBuf::from(MEMORY_STATE);
+        let mut checker = create_memory_checker(&sysfs, create_test_config());
+        sysfs.set(MEMORY_ZONE, "50000");
+        assert!(checker.adjust_speed());
+        assert_eq!(sysfs.get(&state).as_deref(), Some("2"));
+        assert!(!checker.channels[0].check_external_change());
+
+        // Overridden between two checks, then written back
+        sysfs.set(MEMORY_STATE, "0");
+        assert!(checker.channels[0].check_external_change());
+        assert_eq!(checker.channels[0].last_state(), Some(0));
+
+        sysfs.set(MEMORY_STATE, "4");
+        let outcome = checker.step();
+        assert!(outcome.fans[0].wrote);
+        assert_eq!(sysfs.get(&state).as_deref(), Some("2"));
+    }
+
+    #[test]
+    fn test_adjust_speed_alarm_input() {
+        let sysfs = Arc::new(MockSysfs::default());
+        let state = PathBuf::from(MEMORY_STATE);
+        let config = Config {
+            alarm_input_path: Some("gpio/value".into()),
+            ..create_test_config()
+        };
+        let mut checker = create_memory_checker(&sysfs, config);
+        sysfs.set("gpio/value", "0\n");
+        sysfs.set(MEMORY_ZONE, "50000");
+        assert!(checker.adjust_speed());
+        assert_eq!(sysfs.get(&state).as_deref(), Some("2"));
+
+        // Max state whatever the temperature
+        sysfs.set("gpio/value", "1\n");
+        sysfs.set(MEMORY_ZONE, "30000");
+        assert!(checker.adjust_speed());
+        assert_eq!(sysfs.get(&state).as_deref(), Some("5"));
+        assert!(checker.channels[0].alarm_since.is_some());
+
+        sysfs.set("gpio/value", "0\n");
+        assert!(checker.adjust_speed());
+        assert_eq!(sysfs.get(&state).as_deref(), Some("0"));
+        assert!(checker.channels[0].alarm_since.is_none());
+    }
+
+    #[test]
+    fn test_adjust_speed_quiet_hours() {
+        let sysfs = Arc::new(MockSysfs::default());
+        let state = PathBuf::from(MEMORY_STATE);
+        let config = Config {
+            quiet_hours: Some("22:00-07:00".parse().unwrap()),
+            quiet_threshold_offset: 5.0,
+            ..create_test_config()
+        };
+        let mut checker = create_memory_checker(&sysfs, config);
+        sysfs.set(MEMORY_ZONE, "55000");
+
+        // Thresholds 50 to 75 at night, 55°C is in the first slot
+        checker.local_minutes = || 23 * 60;
+        assert!(checker.adjust_speed());
+        assert_eq!(checker.channels[0].config.threshold.min, 50.0);
+        assert_eq!(sysfs.get(&state).as_deref(), Some("1"));
+
+        checker.local_minutes = || 6 * 60 + 59;
+        assert!(checker.adjust_speed());
+        assert_eq!(sysfs.get(&state).as_deref(), Some("1"));
+
+        // Back to 45 to 70 in the morning
+        checker.local_minutes = || 7 * 60;
+        assert!(checker.adjust_speed());
+        assert_eq!(checker.channels[0].config.threshold.min, 45.0);
+        assert_eq!(checker.channels[0].config.threshold.max, 70.0);
+        assert_eq!(sysfs.get(&state).as_deref(), Some("2"));
+    }
+
+    #[test]
+    fn test_adjust_speed_fake_sysfs_root() {
+        let env = TestEnv::new("test_checker_sysfs_root");
+        let thermal = env.path.join(THERMAL_DIR);
+        for (name, file, content) in [
+            ("thermal_zone0", "type", "soc-thermal\n"),
+            ("thermal_zone0", "temp", "56000\n"),
+            ("cooling_device0", "type", "pwm-fan\n"),
+            ("cooling_device0", "max_state", "5\n"),
+            ("cooling_device0", "cur_state", "0\n"),
+        ] {
+            fs::create_dir_all(thermal.join(name)).unwrap();
+            fs::write(thermal.join(name).join(file), content).unwrap();
+        }
+
+        // Both devices discovered below the root, nothing else touched
+        let config = Config {
+            sysfs_root: env.path.clone(),
+            ..create_test_config()
+        };
+        let mut checker = Checker::with_devices(config, None, None);
+        assert!(checker.adjust_speed());
+        let state = thermal.join("cooling_device0/cur_state");
+        assert_eq!(fs::read_to_string(state).unwrap(), "2");
+    }
+
+    #[test]
+    fn test_step_outcome() {
+        let sysfs = Arc::new(MockSysfs::default());
+        let mut checker = create_memory_checker(&sysfs, create_test_config());
+        sysfs.set(MEMORY_ZONE, "50000");
+
+        let outcome = checker.step();
+        assert!(outcome.controlled);
+        assert!(!outcome.paused);
+        assert_eq!(
+            outcome.fans,
+            [FanOutcome {
+                temp: Some(50.0),
+                state: Some(2),
+                wrote: true,
+                error: None,
+            }]
+        );
+
+        let outcome = checker.step();
+        assert!(!outcome.fans[0].wrote);
+        assert_eq!(outcome.fans[0].state, Some(2));
+
+        sysfs.fail_reads(MEMORY_ZONE, MAX_TEMP_READ_RETRIES + 1);
+        let outcome = checker.step();
+        assert!(!outcome.controlled);
+        assert_eq!(outcome.fans[0].temp, None);
+        assert!(!outcome.fans[0].wrote);
+        assert!(
+            outcome.fans[0]
+                .error
+                .as_deref()
+                .is_some_and(|err| err.starts_with("Can't read temperature"))
+        );
+    }
+
+    #[test]
+    fn test_adjust_speed_temp_failure_hold() {
+        let sysfs = Arc::new(MockSysfs::default());
+        let state = PathBuf::from(MEMORY_STATE);
+        let mut checker = create_memory_checker(&sysfs, create_test_config());
+        sysfs.set(MEMORY_ZONE, "50000");
+        assert!(checker.adjust_speed());
+        assert_eq!(sysfs.get(&state).as_deref(), Some("2"));
+
+        sysfs.fail_reads(MEMORY_ZONE, MAX_TEMP_READ_RETRIES + 1);
+        assert!(!checker.adjust_speed());
+        assert_eq!(sysfs.get(&state).as_deref(), Some("2"));
+        assert!(!checker.channels[0].temp_failsafe);
+    }
+
+    #[test]
+    fn test_adjust_speed_temp_failure_failsafe() {
+        let sysfs = Arc::new(MockSysfs::default());
+        let state = PathBuf::from(MEMORY_STATE);
+        let config = Config {
+            on_temp_failure: OnTempFailure::Failsafe,
+            ..create_test_config()
+        };
+        let mut checker = create_memory_checker(&sysfs, config);
+        sysfs.set(MEMORY_ZONE, "50000");
+        assert!(checker.adjust_speed());
+        assert_eq!(sysfs.get(&state).as_deref(), Some("2"));
+
+        sysfs.fail_reads(MEMORY_ZONE, MAX_TEMP_READ_RETRIES + 1);
+        assert!(!checker.adjust_speed());
+        assert_eq!(sysfs.get(&state).as_deref(), Some("5"));
+        assert!(checker.channels[0].temp_failsafe);
+
+        // Back to the curve with the next good reading
+        checker.channels[0].set_temp_device(create_memory_temp(&sysfs));
+        assert!(checker.adjust_speed());
+        assert_eq!(sysfs.get(&state).as_deref(), Some("2"));
+        assert!(!checker.channels[0].temp_failsafe);
+    }
+
+    #[test]
+    fn test_adjust_speed_temp_failure_failsafe_state() {
+        let sysfs = Arc::new(MockSysfs::default());
+        let state = PathBuf::from(MEMORY_STATE);
+        let config = Config {
+            on_temp_failure: OnTempFailure::Failsafe,
+            failsafe_state: Some(4),
+            ..create_test_config()
+        };
+        let mut checker = create_memory_checker(&sysfs, config);
+
+        sysfs.fail_reads(MEMORY_ZONE, MAX_TEMP_READ_RETRIES + 1);
+        assert!(!checker.adjust_speed());
+        assert_eq!(sysfs.get(&state).as_deref(), Some("4"));
+        let writes = sysfs.writes().len();
+
+        // Held without writing again
+        checker.channels[0].set_temp_device(create_memory_temp(&sysfs));
+        sysfs.fail_reads(MEMORY_ZONE, MAX_TEMP_READ_RETRIES + 1);
+        assert!(!checker.adjust_speed());
+        assert_eq!(sysfs.writes().len(), writes);
+    }
+
+    #[test]
+    fn test_adjust_speed_follows_rising_temperature_in_memory() {
+        let sysfs = Arc::new(MockSysfs::default());
+        let state = PathBuf::from(MEMORY_STATE);
+        let zone = PathBuf::from(MEMORY_ZONE);
+        let mut checker = create_memory_checker(&sysfs, create_test_config());
+
+        for (temp, expected) in [
+            ("40000", "0"),
+            ("47000", "1"),
+            ("52000", "2"),
+            ("58000", "3"),
+            ("63000", "4"),
+            ("70000", "5"),
+        ] {
+            sysfs.set(&zone, temp);
+            assert!(checker.adjust_speed());
+            assert_eq!(sysfs.get(&state).as_deref(), Some(expected));
+        }
+
+        sysfs.remove(&zone);
+        assert!(!checker.adjust_speed());
+        assert!(checker.channels[0].temp_device.is_none());
+    }
+
+    #[test]
+    fn test_on_change_cmd_runs_on_state_change() {
+        let env = TestEnv::new("test_checker_on_change_cmd");
+        let output = env.path.join("changes");
+        let sysfs = Arc::new(MockSysfs::default());
+        let config = Config {
+            on_change_cmd: Some(format!(
+                r#"echo "$1 $2 $FAN_PREVIOUS_STATE" >> "{}""#,
+                output.display()
+            )),
+            ..create_test_config()
+        };
+        let mut checker = create_memory_checker(&sysfs, config);
+        let wait = |checker: &mut Checker| {
+            checker.channels[0]
+                .on_change
+                .take()
+                .map(|mut child| assert!(child.wait().unwrap().success()))
+        };
+
+        sysfs.set(MEMORY_ZONE, "52000");
+        assert!(checker.adjust_speed());
+        assert!(wait(&mut checker).is_some());
+
+        // Same state, nothing written
+        assert!(checker.adjust_speed());
+        assert!(wait(&mut checker).is_none());
+
+        sysfs.set(MEMORY_ZONE, "58000");
+        assert!(checker.adjust_speed());
+        assert!(wait(&mut checker).is_some());
+
+        let changes = fs::read_to_string(&output).unwrap();
+        let changes: Vec<&str> = changes.lines().collect();
+        assert_eq!(changes.len(), 2);
+        assert!(changes[0].starts_with("2 52.00"));
+        assert_eq!(changes[1], "3 58.00 2");
+    }
+
+    #[test]
+    fn test_deadband_skips_small_changes() {
+        let sysfs = Arc::new(MockSysfs::default());
+        let state = PathBuf::from(MEMORY_STATE);
+        let config = Config {
+            deadband_states: 1,
+            ..create_test_config()
+        };
+        let mut checker = create_memory_checker(&sysfs, config);
+
+        for (temp, expected) in [
+            ("40000", "0"),
+            // Leaving the min state always goes through
+            ("47000", "1"),
+            // One state away, not written
+            ("52000", "1"),
+            ("58000", "3"),
+            ("63000", "3"),
+            // Reaching the max state always goes through
+            ("70000", "5"),
+            // And so does leaving it
+            ("58000", "4"),
+        ] {
+            sysfs.set(MEMORY_ZONE, temp);
+            assert!(checker.adjust_speed());
+            assert_eq!(sysfs.get(&state).as_deref(), Some(expected), "{temp}");
+        }
+    }
+
+    #[test]
+    fn test_deadband_with_ramp_reaches_target() {
+        let sysfs = Arc::new(MockSysfs::default());
+        let state = PathBuf::from(MEMORY_STATE);
+        let config = Config {
+            deadband_states: 1,
+            ramp_step: Some(1),
+            ..create_test_config()
+        };
+        let mut checker = create_memory_checker(&sysfs, config);
+
+        sysfs.set(MEMORY_ZONE, "40000");
+        assert!(checker.adjust_speed());
+        sysfs.set(MEMORY_ZONE, "80000");
+        for expected in ["1", "2", "3", "4", "5", "5"] {
+            assert!(checker.adjust_speed());
+            assert_eq!(sysfs.get(&state).as_deref(), Some(expected));
+        }
+    }
+
+    #[test]
+    fn test_load_bias_adds_states() {
+        let sysfs = Arc::new(MockSysfs::default());
+        let state = PathBuf::from(MEMORY_STATE);
+        let config = Config {
+            load_bias: 2,
+            ..create_test_config()
+        };
+        let mut checker = create_memory_checker(&sysfs, config);
+        sysfs.set(MEMORY_ZONE, "52000");
+
+        // Every CPU busy whatever their number
+        sysfs.set(load::LOADAVG_PATH, "1000.00 500.00 100.00 9/512 4242\n");
+        assert!(checker.adjust_speed());
+        assert_eq!(sysfs.get(&state).as_deref(), Some("4"));
+
+        sysfs.set(load::LOADAVG_PATH, "0.00 0.00 0.00 1/512 4242\n");
+        assert!(checker.adjust_speed());
+        assert_eq!(sysfs.get(&state).as_deref(), Some("2"));
+
+        // Unreadable load, the temperature alone decides
+        sysfs.remove(Path::new(load::LOADAVG_PATH));
+        assert!(checker.adjust_speed());
+        assert_eq!(sysfs.get(&state).as_deref(), Some("2"));
+    }
+
+    // Slots every 5°C from 45°C and the default 2°C hysteresis: states step
+    // up as soon as a slot is reached and down only 2°C below it.
+    #[test]
+    fn test_adjust_speed_temperature_cycle() {
+        let sysfs = Arc::new(MockSysfs::default());
+        let mut checker = create_memory_checker(&sysfs, create_test_config());
+
+        let mut states = Vec::new();
+        for temp in [
+            40, 45, 50, 55, 60, 65, 70, 75, 70, 66, 64, 62, 59, 57, 54, 52, 44, 42, 40,
+        ] {
+            sysfs.set(MEMORY_ZONE, (temp * 1000).to_string());
+            assert!(checker.adjust_speed());
+            states.push(checker.channels[0].last_state().unwrap());
+        }
+        assert_eq!(
+            states,
+            vec![0, 1, 2, 3, 4, 5, 5, 5, 5, 5, 5, 4, 4, 3, 3, 2, 1, 0, 0]
+        );
+
+        // Only changes are written, after the initial write
+        let writes: Vec<String> = sysfs
+            .writes()
+            .into_iter()
+            .filter(|(path, _)| path == Path::new(MEMORY_STATE))
+            .map(|(_, value)| value)
+            .collect();
+        assert_eq!(
+            writes,
+            ["0", "1", "2", "3", "4", "5", "4", "3", "2", "1", "0"]
+        );
+    }
+
+    #[test]
+    fn test_adjust_speed_permission_denied_keeps_device() {
+        let env = TestEnv::new("test_checker_permission_denied");
+        let sysfs = Arc::new(MockSysfs::default());
+        let fan = Fan {
+            sysfs: sysfs.clone(),
+            ..env.create_fan("0", None)
+        };
+        let state = fan.state.clone();
+        sysfs.set(&state, "0");
+        sysfs.set_read_only(&state);
+
+        let mut checker = Checker::with_devices(
+            create_test_config(),
+            Some(fan),
+            Some(env.create_temp("58000")),
+        );
+
+        for _ in 0..2 {
+            assert!(!checker.adjust_speed());
+            let channel = &checker.channels[0];
+            assert!(channel.fan_device.is_some());
+            assert_eq!(channel.write_denied.as_ref(), Some(&state));
+            assert_eq!(
+                channel.last_error.as_deref(),
+                Some(format!("No write access to {}", state.display()).as_str())
+            );
+        }
+        assert_eq!(sysfs.get(&state).as_deref(), Some("0"));
+    }
+
+    #[test]
+    fn test_adjust_speed_seeded_state_is_not_written_again() {
+        let env = TestEnv::new("test_checker_seeded_state");
+        let state = env.path.join("cur_state");
+        fs::write(env.path.join("max_state"), "5").unwrap();
+        fs::write(&state, "3").unwrap();
+
+        let config = create_test_config();
+        let mut fan = Fan::new_fan_device(
+            state.clone(),
+            env.path.clone(),
+            &config,
+            Arc::new(RealSysfs),
+        )
+        .unwrap();
+        assert_eq!(fan.last_state, Some(3));
+
+        // Any read or write now goes to memory, where the state file is missing
+        let sysfs = Arc::new(MockSysfs::default());
+        fan.sysfs = sysfs.clone();
+        let mut checker = Checker::with_devices(config, Some(fan), Some(env.create_temp("58000")));
+
+        assert!(checker.adjust_speed());
+        assert_eq!(sysfs.get(&state), None);
+
+        // A different desired state is still written
+        env.create_temp("64000");
+        sysfs.set(&state, "3");
+        assert!(checker.adjust_speed());
+        assert_eq!(sysfs.get(&state).as_deref(), Some("4"));
+    }
+
+    #[test]
+    fn test_adjust_speed_waits_for_dwell_time() {
+        let env = TestEnv::new("test_checker_dwell_time");
+        let mut checker = Checker::with_devices(
+            Config {
+                dwell_time: 60,
+                ..create_test_config()
+            },
+            Some(env.create_fan("0", None)),
+            Some(env.create_temp("55000")),
+        );
+
+        let mut changes = 0;
+        for temp in ["55000", "62000", "50000"] {
+            env.create_temp(temp);
+            let previous_state = checker.channels[0].last_state();
+            assert!(checker.adjust_speed());
+            if checker.channels[0].last_state() != previous_state {
+                changes += 1;
+            }
+        }
+        assert_eq!(changes, 1);
+        let fan = checker.channels[0].fan_device.as_ref().unwrap();
+        assert_eq!(fs::read_to_string(&fan.state).unwrap(), "3");
+
+        // Speeding up past the max threshold doesn't wait
+        env.create_temp("72000");
+        assert!(checker.adjust_speed());
+        let fan = checker.channels[0].fan_device.as_ref().unwrap();
+        assert_eq!(fs::read_to_string(&fan.state).unwrap(), "5");
+    }
+
+    #[test]
+    fn test_adjust_speed_anticipates_fast_rise() {
+        let env = TestEnv::new("test_checker_temp_rise_rate");
+        let config = Config {
+            temp_rise_rate: Some(1.0),
+            ..create_test_config()
+        };
+        let mut checker = Checker::with_devices(
+            config,
+            Some(env.create_fan("0", None)),
+            Some(env.create_temp("52000")),
+        );
+        let state = |checker: &Checker| {
+            let fan = checker.channels[0].fan_device.as_ref().unwrap();
+            fs::read_to_string(&fan.state).unwrap()
+        };
+
+        // No previous sample to compare with
+        assert!(checker.adjust_speed());
+        assert_eq!(state(&checker), "2");
+
+        let second_ago = Instant::now().checked_sub(Duration::from_secs(1)).unwrap();
+        checker.channels[0].previous_sample = Some((51.5, second_ago));
+        assert!(checker.adjust_speed());
+        assert_eq!(state(&checker), "2");
+
+        checker.channels[0].previous_sample = Some((50.0, second_ago));
+        assert!(checker.adjust_speed());
+        assert_eq!(state(&checker), "3");
+
+        env.create_temp("70000");
+        checker.channels[0].previous_sample = Some((60.0, second_ago));
+        assert!(checker.adjust_speed());
+        assert_eq!(state(&checker), "5");
+    }
+
+    #[test]
+    fn test_adjust_speed_dry_run_does_not_write() {
+        let env = TestEnv::new("test_checker_dry_run");
+        let fan = env.create_fan("0", None);
+        let temp = env.create_temp("55000");
+
+        let mut checker = Checker::with_devices(
+            Config {
+                dry_run: true,
+                ..create_test_config()
+            },
+            Some(fan),
+            Some(temp),
+        );
+
+        checker.adjust_speed();
+        let fan = checker.channels[0].fan_device.as_ref().unwrap();
+        assert_eq!(fs::read_to_string(&fan.state).unwrap(), "0");
+        assert_eq!(fan.last_state, Some(3));
+
+        env.create_temp("66000");
+        checker.adjust_speed();
+        let fan = checker.channels[0].fan_device.as_ref().unwrap();
+        assert_eq!(fs::read_to_string(&fan.state).unwrap(), "0");
+        assert_eq!(fan.last_state, Some(5));
+    }
+
+    #[test]
+    fn test_adjust_speed_with_stalled_fan() {
+        let env = TestEnv::new("test_checker_stalled_fan");
+        let mut fan = env.create_fan("3", Some(3));
+        let tach = env.path.join("fan1_input");
+        fs::write(&tach, "0").unwrap();
+        fan.tach = Some(tach);
+        let temp = env.create_temp("55000");
+
+        let mut checker = Checker::with_devices(create_test_config(), Some(fan), Some(temp));
+        checker.channels[0].is_init = true;
+
+        checker.adjust_speed();
+        let fan = checker.channels[0].fan_device.as_ref().unwrap();
+        assert_eq!(fan.read_rpm(), Some(0));
+        assert_eq!(fan.last_state, Some(3));
+        assert_eq!(fan.stall, Stall::Suspected(1));
+    }
+
+    #[test]
+    fn test_adjust_speed_recovers_stalled_fan_at_max_state() {
+        let env = TestEnv::new("test_checker_stall_recovery");
+        let mut fan = env.create_fan("3", Some(3));
+        let tach = env.path.join("fan1_input");
+        fs::write(&tach, "80").unwrap();
+        fan.tach = Some(tach.clone());
+        let temp = env.create_temp("55000");
+
+        let config = Config {
+            stall_rpm: 100,
+            ..create_test_config()
+        };
+        let mut checker = Checker::with_devices(config, Some(fan), Some(temp));
+        checker.channels[0].is_init = true;
+        let state = |checker: &Checker| {
+            let fan = checker.channels[0].fan_device.as_ref().unwrap();
+            (fs::read_to_string(&fan.state).unwrap(), fan.stall)
+        };
+
+        checker.adjust_speed();
+        assert_eq!(state(&checker), ("3".to_owned(), Stall::Suspected(1)));
+        checker.adjust_speed();
+        assert_eq!(state(&checker), ("5".to_owned(), Stall::Recovering));
+        checker.adjust_speed();
+        assert_eq!(state(&checker), ("3".to_owned(), Stall::Stalled));
+        assert!(checker.health().fan_stalled);
+
+        fs::write(&tach, "1200").unwrap();
+        checker.adjust_speed();
+        assert_eq!(state(&checker), ("3".to_owned(), Stall::Spinning));
+        assert!(!checker.health().fan_stalled);
+    }
+
+    #[test]
+    fn test_smooth_temp_initializes_with_first_reading() {
+        let mut checker = Checker::with_devices(
+            Config {
+                temp_ema_alpha: 0.3,
+                ..create_test_config()
+            },
+            None,
+            None,
+        );
+
+        assert_eq!(checker.channels[0].smooth_temp(50.0), 50.0);
+        assert_eq!(checker.channels[0].smoothed_temp, Some(50.0));
+    }
+
+    #[test]
+    fn test_smooth_temp_without_smoothing() {
+        let mut checker = Checker::with_devices(create_test_config(), None, None);
+
+        assert_eq!(checker.channels[0].smooth_temp(50.0), 50.0);
+        assert_eq!(checker.channels[0].smooth_temp(80.0), 80.0);
+    }
+
+    #[test]
+    fn test_smooth_temp_spike_decays_gradually() {
+        let env = TestEnv::new("test_checker_ema_spike");
+        let fan = env.create_fan("0", None);
+        let temp = env.create_temp("50000");
+
+        let mut checker = Checker::with_devices(
+            Config {
+                temp_ema_alpha: 0.2,
+                ..create_test_config()
+            },
+            Some(fan),
+            Some(temp),
+        );
+
+        checker.adjust_speed();
+        assert_eq!(
+            checker.channels[0].fan_device.as_ref().unwrap().last_state,
+            Some(2)
+        );
+
+        env.create_temp("90000");
+        checker.adjust_speed();
+        assert_eq!(checker.channels[0].smoothed_temp, Some(58.0));
+        assert_eq!(
+            checker.channels[0].fan_device.as_ref().unwrap().last_state,
+            Some(3)
+        );
+
+        env.create_temp("50000");
+        checker.adjust_speed();
+        assert!(checker.channels[0].smoothed_temp.unwrap() < 58.0);
+        assert!(checker.channels[0].smoothed_temp.unwrap() > 50.0);
+        assert_ne!(
+            checker.channels[0].fan_device.as_ref().unwrap().last_state,
+            Some(DEFAULT_MAX_STATE)
+        );
+    }
+
+    #[test]
+    fn test_adjust_speed_writes_state_file() {
+        let env = TestEnv::new("test_checker_state_file");
+        let fan = env.create_fan("0", None);
+        let temp = env.create_temp("55000");
+        let state_file = env.path.join("status.json");
+
+        let mut checker = Checker::with_devices(
+            Config {
+                state_file: Some(state_file.clone()),
+                ..create_test_config()
+            },
+            Some(fan),
+            Some(temp),
+        );
+
+        checker.adjust_speed();
+
+        let content = fs::read_to_string(&state_file).unwrap();
+        assert!(content.starts_with(
+            r#"{"temp":55.00,"current_state":3,"desired_state":3,"max_state":5,"timestamp":"#
+        ));
+    }
+
+    #[test]
+    fn test_adjust_speed_without_state_file() {
+        let env = TestEnv::new("test_checker_no_state_file");
+        let fan = env.create_fan("0", None);
+        let temp = env.create_temp("55000");
+
+        let mut checker = Checker::with_devices(create_test_config(), Some(fan), Some(temp));
+
+        checker.adjust_speed();
+        assert!(!env.path.join("status.json").exists());
+    }
+
+    #[test]
+    fn test_sleep_time_adapts_to_temperature() {
+        let mut checker = Checker::with_devices(
+            Config {
+                sleep_time: 10,
+                min_sleep_time: 2,
+                max_sleep_time: 30,
+                ..create_test_config()
+            },
+            None,
+            None,
+        );
+
+        assert_eq!(checker.sleep_time(), Duration::from_secs(10));
+
+        checker.channels[0].smoothed_temp = Some(30.0);
+        assert_eq!(checker.sleep_time(), Duration::from_secs(30));
+
+        checker.channels[0].smoothed_temp = Some(45.0);
+        assert_eq!(checker.sleep_time(), Duration::from_secs(10));
+
+        checker.channels[0].smoothed_temp = Some(57.5);
+        assert_eq!(checker.sleep_time(), Duration::from_secs(6));
+
+        checker.channels[0].smoothed_temp = Some(70.0);
+        assert_eq!(checker.sleep_time(), Duration::from_secs(2));
+
+        checker.channels[0].smoothed_temp = Some(90.0);
+        assert_eq!(checker.sleep_time(), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_sleep_time_fixed_by_default() {
+        let mut checker = Checker::with_devices(create_test_config(), None, None);
+
+        for temp in [20.0, 50.0, 90.0] {
+            checker.channels[0].smoothed_temp = Some(temp);
+            assert_eq!(
+                checker.sleep_time(),
+                Duration::from_secs(DEFAULT_SLEEP_TIME)
+            );
+        }
+    }
+
+    #[test]
+    fn test_sleep_jitter() {
+        let sleep_time = Duration::from_secs(10);
+        let jitter = Duration::from_secs(2);
+        assert_eq!(apply_jitter(sleep_time, jitter, 0), Duration::from_secs(8));
+        assert_eq!(apply_jitter(sleep_time, jitter, 2000), sleep_time);
+        assert_eq!(
+            apply_jitter(sleep_time, jitter, 4000),
+            Duration::from_secs(12)
+        );
+        assert_eq!(
+            apply_jitter(sleep_time, jitter, 4001),
+            Duration::from_secs(8)
+        );
+        assert_eq!(
+            apply_jitter(Duration::from_secs(1), jitter, 0),
+            Duration::ZERO
+        );
+
+        let mut checker = Checker::with_devices(create_test_config(), None, None);
+        assert_eq!(checker.jittered_sleep_time(), checker.sleep_time());
+
+        checker.config.sleep_jitter = 2;
+        for loops in 0..50 {
+            checker.loops = loops;
+            let sleep_time = checker.jittered_sleep_time();
+            assert!((Duration::from_secs(3)..=Duration::from_secs(7)).contains(&sleep_time));
+        }
+    }
+
+    #[test]
+    fn test_adjust_speed_drives_fans_independently() {
+        let first_env = TestEnv::new("test_checker_multi_fan_0");
+        let second_env = TestEnv::new("test_checker_multi_fan_1");
+
+        let mut checker = Checker {
+            config: create_test_config(),
+            channels: vec![
+                Channel::with_devices(
+                    create_test_config(),
+                    Some(first_env.create_fan("0", None)),
+                    Some(first_env.create_temp("55000")),
+                ),
+                Channel::with_devices(
+                    Config {
+                        threshold: Threshold {
+                            min: 30.0,
+                            max: 40.0,
+                        },
+                        ..create_test_config()
+                    },
+                    Some(second_env.create_fan("0", None)),
+                    Some(second_env.create_temp("45000")),
+                ),
+            ],
+            events: None,
+            http: None,
+            #[cfg(feature = "dbus")]
+            dbus: None,
+            history: History::new(10),
+            last_success: None,
+            loops: 0,
+            started_at: Instant::now(),
+            min_temp: None,
+            max_temp: None,
+            local_minutes,
+        };
+
+        assert!(checker.adjust_speed());
+        assert_eq!(
+            checker.channels[0].fan_device.as_ref().unwrap().last_state,
+            Some(3)
+        );
+        assert_eq!(
+            checker.channels[1].fan_device.as_ref().unwrap().last_state,
+            Some(5)
+        );
+
+        let decisions: Vec<(usize, u8)> = checker
+            .history
+            .iter()
+            .map(|decision| (decision.fan, decision.state))
+            .collect();
+        assert_eq!(decisions, vec![(0, 3), (1, 5)]);
+    }
+
+    #[test]
+    fn test_log_context_labels_the_fan() {
+        let sysfs = Arc::new(MockSysfs::default());
+        let checker = create_memory_checker(&sysfs, create_test_config());
+        assert_eq!(
+            checker.channels[0].log_context(0),
+            LogContext {
+                device: "cooling_device0".to_owned(),
+                zone: Some("thermal_zone0".to_owned()),
+            }
+        );
+
+        // Not found yet
+        let configured = Channel::with_devices(
+            Config {
+                cooling_device: Some(2),
+                ..create_test_config()
+            },
+            None,
+            None,
+        );
+        assert_eq!(configured.log_context(1).device, "cooling_device2");
+        let discovered = Channel::with_devices(create_test_config(), None, None);
+        assert_eq!(
+            discovered.log_context(1),
+            LogContext {
+                device: "fan1".to_owned(),
+                zone: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_adjust_speed_fails_if_any_fan_fails() {
+        let env = TestEnv::new("test_checker_multi_fan_failure");
+
+        let mut checker = Checker {
+            config: create_test_config(),
+            channels: vec![
+                Channel::with_devices(
+                    create_test_config(),
+                    Some(env.create_fan("0", None)),
+                    Some(env.create_temp("55000")),
+                ),
+                Channel::with_devices(create_test_config(), None, None),
+            ],
+            events: None,
+            http: None,
+            #[cfg(feature = "dbus")]
+            dbus: None,
+            history: History::new(0),
+            last_success: None,
+            loops: 0,
+            started_at: Instant::now(),
+            min_temp: None,
+            max_temp: None,
+            local_minutes,
+        };
+
+        assert!(!checker.adjust_speed());
+        assert_eq!(
+            checker.channels[0].fan_device.as_ref().unwrap().last_state,
+            Some(3)
+        );
+    }
+
+    #[test]
+    fn test_adjust_speed_emits_event_on_state_change() {
+        use std::io::{BufRead, BufReader};
+        use std::os::unix::net::UnixStream;
+
+        let env = TestEnv::new("test_checker_events");
+        let path = env.path.join("events.sock");
+        let mut checker = Checker::with_devices(
+            create_test_config(),
+            Some(env.create_fan("0", None)),
+            Some(env.create_temp("55000")),
+        );
+        checker.events = Some(EventServer::bind(&path).unwrap());
+        let client = UnixStream::connect(&path).unwrap();
+
+        assert!(checker.adjust_speed());
+        assert!(checker.adjust_speed());
+        checker.events = None;
+
+        let lines: Vec<String> = BufReader::new(client).lines().map(Result::unwrap).collect();
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].contains(r#""fan":0,"previous_state":null,"state":3,"temp":55.00"#));
+    }
+
+    #[test]
+    fn test_health_tracks_last_success_and_error() {
+        let env = TestEnv::new("test_checker_health");
+        let mut checker = Checker::with_devices(
+            create_test_config(),
+            Some(env.create_fan("0", None)),
+            Some(env.create_temp("55000")),
+        );
+
+        assert!(checker.adjust_speed());
+        let health = checker.health();
+        assert!(health.last_success.is_some());
+        assert!(health.check(Instant::now()).is_ok());
+
+        fs::remove_file(env.path.join("temp")).unwrap();
+        assert!(!checker.adjust_speed());
+        let health = checker.health();
+        assert!(!health.devices_present);
+        assert!(
+            health
+                .check(Instant::now())
+                .unwrap_err()
+                .contains("Can't read temperature")
+        );
+    }
+
+    #[test]
+    fn test_stats_summary() {
+        let start = Instant::now();
+        let mut stats = Stats::new();
+        assert!(stats.summary().is_none());
+
+        stats.record(Some(0), start);
+        stats.record(Some(3), start + Duration::from_secs(40));
+        stats.record(Some(5), start + Duration::from_secs(90));
+        stats.record(Some(5), start + Duration::from_secs(100));
+        assert_eq!(
+            stats.summary().as_deref(),
+            Some("state 0: 40%, state 3: 50%, state 5: 10%")
+        );
+
+        stats.reset(start + Duration::from_secs(100));
+        assert!(stats.summary().is_none());
+        stats.record(Some(0), start + Duration::from_secs(110));
+        assert_eq!(stats.summary().as_deref(), Some("state 5: 100%"));
+    }
+
+    #[test]
+    fn test_adjust_speed_accumulates_stats() {
+        let env = TestEnv::new("test_checker_stats");
+        let mut checker = Checker::with_devices(
+            Config {
+                stats_interval: 2,
+                ..create_test_config()
+            },
+            Some(env.create_fan("0", None)),
+            Some(env.create_temp("55000")),
+        );
+
+        assert!(checker.adjust_speed());
+        assert!(checker.adjust_speed());
+        assert_eq!(checker.loops, 2);
+        assert!(checker.channels[0].stats.time_per_state.contains_key(&3));
+
+        checker.reset_stats();
+        assert_eq!(checker.loops, 0);
+        assert!(checker.channels[0].stats.summary().is_none());
+    }
+
+    #[test]
+    fn test_sleep_time_uses_hottest_fan() {
+        let config = Config {
+            sleep_time: 10,
+            min_sleep_time: 2,
+            max_sleep_time: 30,
+            ..create_test_config()
+        };
+        let mut checker = Checker {
+            config: config.clone(),
+            channels: vec![
+                Channel::with_devices(config.clone(), None, None),
+                Channel::with_devices(config, None, None),
+            ],
+            events: None,
+            http: None,
+            #[cfg(feature = "dbus")]
+            dbus: None,
+            history: History::new(0),
+            last_success: None,
+            loops: 0,
+            started_at: Instant::now(),
+            min_temp: None,
+            max_temp: None,
+            local_minutes,
+        };
+
+        checker.channels[0].smoothed_temp = Some(30.0);
+        checker.channels[1].smoothed_temp = Some(70.0);
+        assert_eq!(checker.sleep_time(), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_adjust_speed_ramps_one_step_per_cycle() {
+        let env = TestEnv::new("test_checker_ramp");
+        let fan = env.create_fan("1", Some(1));
+        let temp = env.create_temp("66000");
+
+        let mut checker = Checker::with_devices(
+            Config {
+                ramp_step: Some(1),
+                ..create_test_config()
+            },
+            Some(fan),
+            Some(temp),
+        );
+        checker.channels[0].is_init = true;
+
+        for expected in [2, 3, 4, 5, 5] {
+            checker.adjust_speed();
+            let fan = checker.channels[0].fan_device.as_ref().unwrap();
+            assert_eq!(fan.last_state, Some(expected));
+            assert_eq!(
+                fs::read_to_string(&fan.state).unwrap(),
+                expected.to_string()
+            );
+        }
+    }
+
+    #[test]
+    fn test_adjust_speed_fixed_state_skips_ramp() {
+        let env = TestEnv::new("test_checker_fixed_state");
+        let fan = env.create_fan("1", Some(1));
+        let temp = env.create_temp("30000");
+
+        let mut checker = Checker::with_devices(
+            Config {
+                ramp_step: Some(1),
+                fixed_state: Some(4),
+                ..create_test_config()
+            },
+            Some(fan),
+            Some(temp),
+        );
+
+        assert!(checker.adjust_speed());
+        let fan = checker.channels[0].fan_device.as_ref().unwrap();
+        assert_eq!(fan.last_state, Some(4));
+        assert_eq!(fs::read_to_string(&fan.state).unwrap(), "4");
+    }
+
+    #[test]
+    fn test_adjust_speed_without_ramp_jumps_to_target() {
+        let env = TestEnv::new("test_checker_no_ramp");
+        let fan = env.create_fan("1", Some(1));
+        let temp = env.create_temp("66000");
+
+        let mut checker = Checker::with_devices(create_test_config(), Some(fan), Some(temp));
+        checker.channels[0].is_init = true;
+
+        checker.adjust_speed();
+        assert_eq!(
+            checker.channels[0].fan_device.as_ref().unwrap().last_state,
+            Some(5)
+        );
+    }
+
+    #[test]
+    fn test_critical_temp_bypasses_ramp() {
+        let env = TestEnv::new("test_checker_ramp_critical");
+        let fan = env.create_fan("1", Some(1));
+        let temp = env.create_temp("90000");
+
+        let mut checker = Checker::with_devices(
+            Config {
+                ramp_step: Some(1),
+                critical_temp: Some(85.0),
+                ..create_test_config()
+            },
+            Some(fan),
+            Some(temp),
+        );
+        checker.channels[0].is_init = true;
+
+        checker.adjust_speed();
+        assert_eq!(
+            checker.channels[0].fan_device.as_ref().unwrap().last_state,
+            Some(DEFAULT_MAX_STATE)
+        );
+    }
+
+    #[test]
+    fn test_min_on_time_prevents_quick_stop() {
+        let env = TestEnv::new("test_checker_min_on_time");
+        let fan = env.create_fan("0", None);
+        let temp = env.create_temp("52000");
+
+        let mut checker = Checker::with_devices(
+            Config {
+                min_on_time: 60,
+                hysteresis: 0.0,
+                ..create_test_config()
+            },
+            Some(fan),
+            Some(temp),
+        );
+
+        checker.adjust_speed();
+        assert_eq!(
+            checker.channels[0].fan_device.as_ref().unwrap().last_state,
+            Some(2)
+        );
+
+        env.create_temp("40000");
+        checker.adjust_speed();
+        assert_eq!(
+            checker.channels[0].fan_device.as_ref().unwrap().last_state,
+            Some(1)
+        );
+
+        checker.channels[0].fan_device.as_mut().unwrap().spun_up_at =
+            Instant::now().checked_sub(Duration::from_secs(61));
+        checker.adjust_speed();
+        assert_eq!(
+            checker.channels[0].fan_device.as_ref().unwrap().last_state,
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn test_spinup_kick_settles_to_desired_state() {
+        let env = TestEnv::new("test_checker_spinup_kick");
+        let fan = env.create_fan("0", Some(0));
+        let temp = env.create_temp("46000");
+
+        let mut checker = Checker::with_devices(
+            Config {
+                spinup_kick: true,
+                spinup_ms: 0,
+                ..create_test_config()
+            },
+            Some(fan),
+            Some(temp),
+        );
+        checker.channels[0].is_init = true;
+
+        assert!(checker.adjust_speed());
+        let fan = checker.channels[0].fan_device.as_ref().unwrap();
+        assert_eq!(fs::read_to_string(&fan.state).unwrap(), "1");
+        assert_eq!(fan.last_state, Some(1));
+    }
+
+    #[test]
+    fn test_desired_state_for_does_not_write() {
+        let env = TestEnv::new("test_checker_desired_state_for");
+        let fan = env.create_fan("0", Some(3));
+
+        let checker = Checker::with_devices(
+            Config {
+                critical_temp: Some(85.0),
+                ..create_test_config()
+            },
+            Some(fan),
+            None,
+        );
+
+        assert_eq!(checker.desired_state_for(30.0), Some(0));
+        assert_eq!(checker.desired_state_for(54.0), Some(3));
+        assert_eq!(checker.desired_state_for(52.5), Some(2));
+        assert_eq!(checker.desired_state_for(66.0), Some(5));
+        assert_eq!(checker.desired_state_for(90.0), Some(DEFAULT_MAX_STATE));
+
+        let fan = checker.channels[0].fan_device.as_ref().unwrap();
+        assert_eq!(fs::read_to_string(&fan.state).unwrap(), "0");
+        assert_eq!(fan.last_state, Some(3));
+    }
+
+    #[test]
+    fn test_desired_state_for_rounds_at_slot_boundaries() {
+        let env = TestEnv::new("test_checker_decision_rounding");
+        let rounded = Checker::with_devices(
+            Config {
+                decision_rounding: Some(0.5),
+                ..create_test_config()
+            },
+            Some(env.create_fan("0", None)),
+            None,
+        );
+        let exact =
+            Checker::with_devices(create_test_config(), Some(env.create_fan("0", None)), None);
+
+        assert_eq!(exact.desired_state_for(49.8), Some(1));
+        assert_eq!(rounded.desired_state_for(49.8), Some(2));
+        assert_eq!(exact.desired_state_for(50.2), Some(2));
+        assert_eq!(rounded.desired_state_for(50.2), Some(2));
+        assert_eq!(rounded.desired_state_for(49.7), Some(1));
+    }
+
+    #[test]
+    fn test_desired_state_for_without_fan() {
+        let checker = Checker::with_devices(create_test_config(), None, None);
+        assert_eq!(checker.desired_state_for(50.0), None);
+    }
+
+    #[test]
+    fn test_backoff_grows_and_resets() {
+        let mut backoff = Backoff::new();
+        let now = Instant::now();
+        assert!(backoff.is_ready(now));
+
+        assert!(backoff.failed(now));
+        assert!(!backoff.is_ready(now));
+        assert!(backoff.is_ready(now + BACKOFF_MIN_DELAY));
+
+        assert!(!backoff.failed(now));
+        assert_eq!(backoff.delay, BACKOFF_MIN_DELAY * 2);
+        assert!(!backoff.is_ready(now + BACKOFF_MIN_DELAY));
+
+        for _ in 0..10 {
+            backoff.failed(now);
+        }
+        assert_eq!(backoff.delay, BACKOFF_MAX_DELAY);
+        assert_eq!(backoff.attempts, 12);
+
+        backoff.reset();
+        assert_eq!(backoff.attempts, 0);
+        assert!(backoff.is_ready(now));
+        assert!(backoff.failed(now));
+    }
+
+    #[test]
+    fn test_missing_fan_device_is_retried_after_backoff() {
+        let mut checker = Checker::with_devices(create_test_config(), None, None);
+
+        assert!(!checker.adjust_speed());
+        assert_eq!(checker.channels[0].fan_backoff.attempts, 1);
+        assert!(checker.channels[0].fan_backoff.retry_at.is_some());
+
+        assert!(!checker.adjust_speed());
+        assert_eq!(checker.channels[0].fan_backoff.attempts, 1);
+
+        checker.channels[0].fan_backoff.retry_at = None;
+        assert!(!checker.adjust_speed());
+        assert_eq!(checker.channels[0].fan_backoff.attempts, 2);
+        assert_eq!(checker.channels[0].fan_backoff.delay, BACKOFF_MIN_DELAY * 2);
+    }
+
+    #[test]
+    fn test_missing_temp_device_is_retried_after_backoff() {
+        let env = TestEnv::new("test_checker_temp_backoff");
+        let fan = env.create_fan("0", None);
+        let mut checker = Checker::with_devices(create_test_config(), Some(fan), None);
+        checker.channels[0].temp_backoff.retry_at = Instant::now().checked_add(BACKOFF_MAX_DELAY);
+
+        assert!(!checker.adjust_speed());
+        assert!(checker.channels[0].temp_device.is_none());
+
+        env.create_temp("55000");
+        checker.channels[0].config.thermal_zone_path = Some(env.path.join("temp"));
+        assert!(!checker.adjust_speed());
+
+        checker.channels[0].temp_backoff.retry_at = None;
+        assert!(checker.adjust_speed());
+        assert!(checker.channels[0].temp_device.is_some());
+        assert!(checker.channels[0].temp_backoff.retry_at.is_none());
     }
 }
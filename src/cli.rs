@@ -0,0 +1,39 @@
+use clap::Parser;
+
+#[derive(Debug, Default, Parser)]
+#[allow(clippy::struct_excessive_bools)]
+#[command(version, about)]
+pub struct Args {
+    #[arg(long, value_name = "DEGREES", help = "Overrides MIN_THRESHOLD")]
+    pub min_threshold: Option<f32>,
+    #[arg(long, value_name = "DEGREES", help = "Overrides MAX_THRESHOLD")]
+    pub max_threshold: Option<f32>,
+    #[arg(long, value_name = "SECONDS", help = "Overrides SLEEP_TIME")]
+    pub sleep_time: Option<u64>,
+    #[arg(long, value_name = "STATE", help = "Overrides MIN_STATE")]
+    pub min_state: Option<u8>,
+    #[arg(long, value_name = "STATE", help = "Overrides MAX_STATE")]
+    pub max_state: Option<u8>,
+    #[arg(long, help = "Same as DEBUG=true")]
+    pub debug: bool,
+    #[arg(
+        long,
+        help = "Adjust the fan speed once and exit, same as RUN_ONCE=true"
+    )]
+    pub once: bool,
+    #[arg(
+        long,
+        help = "Print the resolved configuration and detected devices, then exit"
+    )]
+    pub print_config: bool,
+    #[arg(
+        long,
+        help = "Check the configuration and the access to the devices without changing the fan speed, then exit"
+    )]
+    pub validate: bool,
+    #[arg(
+        long,
+        help = "List every thermal zone and cooling device with its type, then exit"
+    )]
+    pub list_devices: bool,
+}
@@ -1,19 +1,143 @@
-use std::{env, io::Write, str::FromStr};
+use std::{
+    cell::RefCell,
+    env,
+    ffi::OsStr,
+    fmt::{self, Write as _},
+    io::{self, IsTerminal, Write},
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
-use env_logger::Builder;
-use log::{Level, LevelFilter, info};
+use env_logger::{Builder, fmt::Formatter};
+use log::{
+    Level, LevelFilter, Record, error, info,
+    kv::{self, Key, Value, VisitSource},
+    warn,
+};
+
+pub use crate::policy::{BoundaryBias, CurveMode};
+use crate::{
+    DEFAULT_SYSFS_ROOT, PWM_DIR, THERMAL_DIR, cli::Args, error::ConfigError, fan::Fan, policy,
+};
 
 const DEFAULT_LOWER_TEMP_THRESHOLD: f32 = 45.0;
 const DEFAULT_UPPER_TEMP_THRESHOLD: f32 = 65.0;
 const DEFAULT_MIN_STATE: u8 = 0;
 pub const DEFAULT_MAX_STATE: u8 = 5;
+const DEFAULT_HYSTERESIS: f32 = 2.0;
+const DEFAULT_QUIET_THRESHOLD_OFFSET: f32 = 5.0;
 
 pub const DEFAULT_SLEEP_TIME: u64 = 5;
+// A zero sleep time would busy loop on sysfs, a huge one is most likely a typo
+const SLEEP_TIME_RANGE: RangeInclusive<u64> = 1..=3600;
+const DEFAULT_PWM_PERIOD: u32 = 40_000;
+const DEFAULT_PWM_MAX_VALUE: u32 = 255;
+const DEFAULT_TEMP_EMA_ALPHA: f32 = 1.0;
+const DEFAULT_SPINUP_MS: u64 = 500;
+const DEFAULT_MQTT_TOPIC_PREFIX: &str = "cm3588-fan";
+const DEFAULT_HISTORY_SIZE: usize = 100;
+const DEFAULT_TEMP_FILE_DIVISOR: u32 = 1000;
+const DEFAULT_STALL_CHECKS: u32 = 2;
+const DESCRIBE_WIDTH: usize = 19;
+// The board idles around this temperature, a lower max threshold keeps the
+// fan at its max state all the time
+const LOW_MAX_THRESHOLD: f32 = 40.0;
+const DEFAULT_LOG_PRECISION: usize = 2;
+const MAX_LOG_PRECISION: usize = 6;
+
+// Decimals of the temperatures in logs, set once with the logger.
+static LOG_PRECISION: AtomicUsize = AtomicUsize::new(DEFAULT_LOG_PRECISION);
+
+thread_local! {
+    // Fan the log lines of this thread refer to, see `LogContext::enter`.
+    static LOG_CONTEXT: RefCell<Option<LogContext>> = const { RefCell::new(None) };
+}
 
+#[derive(Clone)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Config {
     pub threshold: Threshold,
     pub state: State,
     pub sleep_time: u64,
+    pub hysteresis: f32,
+    pub hysteresis_percent: Option<f32>,
+    pub temp_rise_rate: Option<f32>,
+    pub decision_rounding: Option<f32>,
+    pub temp_aggregation: TempAggregation,
+    pub shutdown_state: Option<u8>,
+    pub curve_mode: CurveMode,
+    pub boundary_bias: BoundaryBias,
+    pub on_device_loss: OnDeviceLoss,
+    pub on_temp_failure: OnTempFailure,
+    pub failsafe_state: Option<u8>,
+    pub fan_profile: Option<FanProfile>,
+    pub thermal_zone_path: Option<PathBuf>,
+    // Any file with a temperature, read instead of the thermal zones
+    pub temp_file_path: Option<PathBuf>,
+    pub temp_file_divisor: u32,
+    pub fan_device_path: Option<PathBuf>,
+    pub dry_run: bool,
+    pub thermal_zone_types: Vec<String>,
+    pub fan_backend: FanBackend,
+    pub pwm: Pwm,
+    pub tach_path: Option<PathBuf>,
+    pub stall_rpm: u32,
+    pub stall_checks: u32,
+    pub run_once: bool,
+    pub temp_ema_alpha: f32,
+    pub state_file: Option<PathBuf>,
+    pub critical_temp: Option<f32>,
+    pub critical_trip: bool,
+    pub slots: Option<Vec<(u8, f32)>>,
+    pub min_sleep_time: u64,
+    pub max_sleep_time: u64,
+    pub sleep_jitter: u64,
+    pub temp_scale: TempScale,
+    // Added to every reading to correct a sensor bias, in Celsius
+    pub temp_offset: f32,
+    pub cooling_device: Option<u32>,
+    pub fan_device_name: Option<String>,
+    pub ramp_step: Option<u8>,
+    pub min_on_time: u64,
+    pub warmup_time: u64,
+    pub warmup_state: Option<u8>,
+    pub dwell_time: u64,
+    pub max_writes_per_min: usize,
+    pub deadband_states: u8,
+    pub spinup_kick: bool,
+    pub spinup_ms: u64,
+    pub temp_unit: TempUnit,
+    pub mqtt_host: Option<String>,
+    pub mqtt_topic_prefix: String,
+    pub fan_off_temp: Option<f32>,
+    pub temp_samples: usize,
+    pub event_socket: Option<PathBuf>,
+    pub curve_file: Option<PathBuf>,
+    pub http_addr: Option<String>,
+    pub invert_state: bool,
+    pub take_over_governor: bool,
+    pub stats_interval: u64,
+    pub rescan_interval: u64,
+    // Used when the cooling device's `max_state` can't be read
+    pub default_max_state: Option<u8>,
+    pub temp_zones: Option<Vec<(String, f32)>>,
+    pub fixed_state: Option<u8>,
+    pub history_size: usize,
+    pub dbus_bus: Option<DbusBus>,
+    pub pid_file: Option<PathBuf>,
+    pub pause_file: Option<PathBuf>,
+    // Shell command run in the background after a new state is written
+    pub on_change_cmd: Option<String>,
+    // Where devices are discovered, a fake tree in end-to-end tests
+    pub sysfs_root: PathBuf,
+    pub alarm_input_path: Option<PathBuf>,
+    pub quiet_hours: Option<QuietHours>,
+    // Added to both thresholds during `quiet_hours`, in Celsius
+    pub quiet_threshold_offset: f32,
+    // States added when every CPU is busy, 0 to ignore the load
+    pub load_bias: u8,
 }
 const RED: &str = "\x1b[31m";
 const YELLOW: &str = "\x1b[33m";
@@ -22,25 +146,607 @@ const BLUE: &str = "\x1b[34m";
 const CYAN: &str = "\x1b[36m";
 const RESET: &str = "\x1b[0m";
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct State {
     pub max: Option<u8>,
     pub min: u8,
 }
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Threshold {
     pub max: f32,
     pub min: f32,
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TempAggregation {
+    #[default]
+    First,
+    Max,
+    Mean,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TempScale {
+    #[default]
+    Auto,
+    Milli,
+    Deci,
+    Unit,
+    // `TEMP_FILE_DIVISOR`, never 0
+    Divisor(u32),
+}
+
+impl FromStr for TempScale {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "auto" => Ok(Self::Auto),
+            "milli" => Ok(Self::Milli),
+            "deci" => Ok(Self::Deci),
+            "unit" => Ok(Self::Unit),
+            _ => Err(format!("unknown temperature scale: {s}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TempUnit {
+    #[default]
+    Celsius,
+    Fahrenheit,
+}
+
+impl TempUnit {
+    #[must_use]
+    pub fn to_celsius(self, value: f32) -> f32 {
+        match self {
+            Self::Celsius => value,
+            Self::Fahrenheit => (value - 32.0) * 5.0 / 9.0,
+        }
+    }
+
+    #[must_use]
+    pub fn from_celsius(self, celsius: f32) -> f32 {
+        match self {
+            Self::Celsius => celsius,
+            Self::Fahrenheit => celsius.mul_add(9.0 / 5.0, 32.0),
+        }
+    }
+
+    // Converts a temperature difference, such as the hysteresis
+    #[must_use]
+    pub fn delta_to_celsius(self, delta: f32) -> f32 {
+        match self {
+            Self::Celsius => delta,
+            Self::Fahrenheit => delta * 5.0 / 9.0,
+        }
+    }
+
+    #[must_use]
+    pub fn delta_from_celsius(self, delta: f32) -> f32 {
+        match self {
+            Self::Celsius => delta,
+            Self::Fahrenheit => delta * 9.0 / 5.0,
+        }
+    }
+
+    #[must_use]
+    pub fn display(self, celsius: f32) -> String {
+        self.display_with(celsius, LOG_PRECISION.load(Ordering::Relaxed))
+    }
+
+    #[must_use]
+    pub fn display_with(self, celsius: f32, precision: usize) -> String {
+        self.format(self.from_celsius(celsius), precision)
+    }
+
+    // A temperature difference in Celsius, 2°C of hysteresis are 3.6°F and
+    // not 35.6°F.
+    #[must_use]
+    pub fn display_delta(self, delta: f32) -> String {
+        self.format(
+            self.delta_from_celsius(delta),
+            LOG_PRECISION.load(Ordering::Relaxed),
+        )
+    }
+
+    fn format(self, value: f32, precision: usize) -> String {
+        let symbol = match self {
+            Self::Celsius => 'C',
+            Self::Fahrenheit => 'F',
+        };
+        let value = format!("{value:.precision$}");
+        // A value rounding to 0 from below isn't printed as "-0.00"
+        let value = match value.strip_prefix('-') {
+            Some(abs) if abs.chars().all(|c| c == '0' || c == '.') => abs,
+            _ => &value,
+        };
+        format!("{value}°{symbol}")
+    }
+}
+
+impl FromStr for TempUnit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "c" | "celsius" => Ok(Self::Celsius),
+            "f" | "fahrenheit" => Ok(Self::Fahrenheit),
+            _ => Err(format!("unknown temperature unit: {s}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("unknown log format: {s}")),
+        }
+    }
+}
+
+// Where the logs go, syslog needs the `syslog` feature.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogTarget {
+    #[default]
+    Stderr,
+    Syslog,
+}
+
+impl FromStr for LogTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "stderr" => Ok(Self::Stderr),
+            "syslog" => Ok(Self::Syslog),
+            _ => Err(format!("unknown log target: {s}")),
+        }
+    }
+}
+
+// ISO 8601 timestamp in front of the text logs, for systems where journald
+// doesn't add one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogTimestamps {
+    #[default]
+    Off,
+    Seconds,
+    Millis,
+}
+
+impl FromStr for LogTimestamps {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(Self::Off),
+            "seconds" => Ok(Self::Seconds),
+            "millis" => Ok(Self::Millis),
+            _ => Err(format!("unknown log timestamps: {s}")),
+        }
+    }
+}
+
+// Device label added to the log lines, `[cooling_device0]` in the text format
+// and `device`/`zone` fields in the JSON one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogContext {
+    pub device: String,
+    pub zone: Option<String>,
+}
+
+impl LogContext {
+    // Labels the log lines of the current thread until the guard is dropped.
+    #[must_use]
+    pub fn enter(self) -> LogContextGuard {
+        LogContextGuard(LOG_CONTEXT.replace(Some(self)))
+    }
+
+    #[must_use]
+    pub fn current() -> Option<Self> {
+        LOG_CONTEXT.with_borrow(Clone::clone)
+    }
+
+    pub(crate) fn prefix() -> String {
+        LOG_CONTEXT.with_borrow(|context| {
+            context
+                .as_ref()
+                .map_or_else(String::new, |context| format!("[{}] ", context.device))
+        })
+    }
+
+    fn push_json(line: &mut String) {
+        LOG_CONTEXT.with_borrow(|context| {
+            if let Some(context) = context {
+                let _ = write!(line, r#","device":{}"#, json_string(&context.device));
+                if let Some(zone) = &context.zone {
+                    let _ = write!(line, r#","zone":{}"#, json_string(zone));
+                }
+            }
+        });
+    }
+}
+
+// Restores the previous context once dropped.
+pub struct LogContextGuard(Option<LogContext>);
+
+impl Drop for LogContextGuard {
+    fn drop(&mut self) {
+        LOG_CONTEXT.set(self.0.take());
+    }
+}
+
+struct JsonFields<'a>(&'a mut String);
+
+impl<'kvs> VisitSource<'kvs> for JsonFields<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        let value = value
+            .to_u64()
+            .map(|v| v.to_string())
+            .or_else(|| value.to_i64().map(|v| v.to_string()))
+            .or_else(|| {
+                value
+                    .to_f64()
+                    .filter(|v| v.is_finite())
+                    .map(|v| v.to_string())
+            })
+            .or_else(|| value.to_bool().map(|v| v.to_string()))
+            .unwrap_or_else(|| json_string(&value.to_string()));
+        self.0.push(',');
+        self.0.push_str(&json_string(key.as_str()));
+        self.0.push(':');
+        self.0.push_str(&value);
+        Ok(())
+    }
+}
+
+fn or_unset<T: fmt::Display>(value: Option<T>) -> String {
+    value.map_or_else(|| "unset".to_owned(), |value| value.to_string())
+}
+
+fn path_or_unset(path: Option<&Path>) -> String {
+    or_unset(path.map(Path::display))
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04x}", u32::from(c));
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[derive(Debug, Clone)]
+pub struct Pwm {
+    pub chip: u32,
+    pub channel: u32,
+    pub period: u32,
+    pub sysfs_path: Option<PathBuf>,
+    pub max_value: u32,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FanBackend {
+    #[default]
+    CoolingDevice,
+    PwmChip,
+    Hwmon,
+}
+
+impl FromStr for FanBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "cooling_device" => Ok(Self::CoolingDevice),
+            "pwmchip" => Ok(Self::PwmChip),
+            "hwmon" => Ok(Self::Hwmon),
+            _ => Err(format!("unknown fan backend: {s}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DbusBus {
+    System,
+    Session,
+}
+
+impl FromStr for DbusBus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "system" => Ok(Self::System),
+            "session" => Ok(Self::Session),
+            _ => Err(format!("unknown D-Bus bus: {s}")),
+        }
+    }
+}
+
+impl FromStr for BoundaryBias {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "low" => Ok(Self::Low),
+            "high" => Ok(Self::High),
+            _ => Err(format!("unknown boundary bias: {s}")),
+        }
+    }
+}
+
+impl FromStr for CurveMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "step" => Ok(Self::Step),
+            "linear" => Ok(Self::Linear),
+            _ => Err(format!("unknown curve mode: {s}")),
+        }
+    }
+}
+
+// What happens once a fan device that went away is found again.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OnDeviceLoss {
+    #[default]
+    Hold,
+    // Max state is written right away, before following the curve again
+    Failsafe,
+}
+
+impl FromStr for OnDeviceLoss {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "hold" => Ok(Self::Hold),
+            "failsafe" => Ok(Self::Failsafe),
+            _ => Err(format!("unknown device loss action: {s}")),
+        }
+    }
+}
+
+// What happens to the fan while the temperature can't be read.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OnTempFailure {
+    // The fan stays at its last state
+    #[default]
+    Hold,
+    // `FAILSAFE_STATE` is written until the temperature can be read again
+    Failsafe,
+}
+
+impl FromStr for OnTempFailure {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "hold" => Ok(Self::Hold),
+            "failsafe" => Ok(Self::Failsafe),
+            _ => Err(format!("unknown temperature failure action: {s}")),
+        }
+    }
+}
+
+// Daily window in minutes since local midnight, crossing midnight when
+// `end` is before `start`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuietHours {
+    pub start: u16,
+    pub end: u16,
+}
+
+impl QuietHours {
+    #[must_use]
+    pub const fn contains(self, minutes: u16) -> bool {
+        if self.start <= self.end {
+            self.start <= minutes && minutes < self.end
+        } else {
+            minutes >= self.start || minutes < self.end
+        }
+    }
+
+    fn parse_time(s: &str) -> Option<u16> {
+        let (hours, minutes) = s.trim().split_once(':')?;
+        let (hours, minutes) = (hours.parse::<u16>().ok()?, minutes.parse::<u16>().ok()?);
+        (hours < 24 && minutes < 60).then_some(hours * 60 + minutes)
+    }
+}
+
+impl FromStr for QuietHours {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split_once('-')
+            .and_then(|(start, end)| {
+                Some(Self {
+                    start: Self::parse_time(start)?,
+                    end: Self::parse_time(end)?,
+                })
+            })
+            .ok_or_else(|| format!("expected HH:MM-HH:MM, got '{s}'"))
+    }
+}
+
+impl fmt::Display for QuietHours {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:02}:{:02}-{:02}:{:02}",
+            self.start / 60,
+            self.start % 60,
+            self.end / 60,
+            self.end % 60
+        )
+    }
+}
+
+// Named sets of thresholds and states, individual settings still override them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FanProfile {
+    // Low states, the fan only speeds up once the board is quite warm
+    Quiet,
+    // Same as the defaults
+    Balanced,
+    // Always spinning, full speed well before the board gets warm
+    Aggressive,
+}
+
+impl FromStr for FanProfile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "quiet" => Ok(Self::Quiet),
+            "balanced" => Ok(Self::Balanced),
+            "aggressive" => Ok(Self::Aggressive),
+            _ => Err(format!("unknown fan profile: {s}")),
+        }
+    }
+}
+
+impl FromStr for TempAggregation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "first" => Ok(Self::First),
+            "max" => Ok(Self::Max),
+            "mean" => Ok(Self::Mean),
+            _ => Err(format!("unknown temperature aggregation: {s}")),
+        }
+    }
+}
+
 impl Default for Config {
     fn default() -> Self {
-        Self::new()
+        Self {
+            threshold: Threshold {
+                max: DEFAULT_UPPER_TEMP_THRESHOLD,
+                min: DEFAULT_LOWER_TEMP_THRESHOLD,
+            },
+            state: State {
+                max: None,
+                min: DEFAULT_MIN_STATE,
+            },
+            sleep_time: DEFAULT_SLEEP_TIME,
+            hysteresis: DEFAULT_HYSTERESIS,
+            hysteresis_percent: None,
+            temp_rise_rate: None,
+            decision_rounding: None,
+            temp_aggregation: TempAggregation::default(),
+            shutdown_state: None,
+            curve_mode: CurveMode::default(),
+            boundary_bias: BoundaryBias::default(),
+            on_device_loss: OnDeviceLoss::default(),
+            on_temp_failure: OnTempFailure::default(),
+            failsafe_state: None,
+            fan_profile: None,
+            thermal_zone_path: None,
+            temp_file_path: None,
+            temp_file_divisor: DEFAULT_TEMP_FILE_DIVISOR,
+            fan_device_path: None,
+            dry_run: false,
+            thermal_zone_types: Vec::new(),
+            fan_backend: FanBackend::default(),
+            pwm: Pwm {
+                chip: 0,
+                channel: 0,
+                period: DEFAULT_PWM_PERIOD,
+                sysfs_path: None,
+                max_value: DEFAULT_PWM_MAX_VALUE,
+            },
+            tach_path: None,
+            stall_rpm: 0,
+            stall_checks: DEFAULT_STALL_CHECKS,
+            run_once: false,
+            temp_ema_alpha: DEFAULT_TEMP_EMA_ALPHA,
+            state_file: None,
+            critical_temp: None,
+            critical_trip: false,
+            slots: None,
+            min_sleep_time: DEFAULT_SLEEP_TIME,
+            max_sleep_time: DEFAULT_SLEEP_TIME,
+            sleep_jitter: 0,
+            temp_scale: TempScale::default(),
+            temp_offset: 0.0,
+            cooling_device: None,
+            fan_device_name: None,
+            ramp_step: None,
+            min_on_time: 0,
+            warmup_time: 0,
+            warmup_state: None,
+            dwell_time: 0,
+            max_writes_per_min: 0,
+            deadband_states: 0,
+            spinup_kick: false,
+            spinup_ms: DEFAULT_SPINUP_MS,
+            temp_unit: TempUnit::default(),
+            mqtt_host: None,
+            mqtt_topic_prefix: DEFAULT_MQTT_TOPIC_PREFIX.to_owned(),
+            fan_off_temp: None,
+            temp_samples: 1,
+            event_socket: None,
+            curve_file: None,
+            http_addr: None,
+            invert_state: false,
+            take_over_governor: false,
+            stats_interval: 0,
+            rescan_interval: 0,
+            default_max_state: None,
+            temp_zones: None,
+            fixed_state: None,
+            history_size: DEFAULT_HISTORY_SIZE,
+            dbus_bus: None,
+            pid_file: None,
+            pause_file: None,
+            on_change_cmd: None,
+            sysfs_root: PathBuf::from(DEFAULT_SYSFS_ROOT),
+            alarm_input_path: None,
+            quiet_hours: None,
+            quiet_threshold_offset: DEFAULT_QUIET_THRESHOLD_OFFSET,
+            load_bias: 0,
+        }
     }
 }
 
 impl Config {
-    const fn get_level_color(level: Option<Level>) -> &'static str {
+    // See https://no-color.org, an empty NO_COLOR doesn't disable colors
+    fn color_enabled(no_color: Option<&OsStr>, is_terminal: bool) -> bool {
+        is_terminal && no_color.is_none_or(OsStr::is_empty)
+    }
+    const fn get_level_color(level: Option<Level>, color: bool) -> &'static str {
+        if !color {
+            return "";
+        }
         match level {
             Some(Level::Error) => RED,
             Some(Level::Warn) => YELLOW,
@@ -50,7 +756,26 @@ impl Config {
             None => RESET,
         }
     }
-    fn setup_logging(debug_mode: bool) {
+    fn format_json(f: &mut Formatter, record: &Record) -> std::io::Result<()> {
+        let mut line = format!(
+            r#"{{"level":"{}","message":{},"timestamp":"{}""#,
+            record.level(),
+            json_string(&record.args().to_string()),
+            f.timestamp()
+        );
+        // Key-values can't fail to visit since the visitor never errors
+        let _ = record.key_values().visit(&mut JsonFields(&mut line));
+        LogContext::push_json(&mut line);
+        line.push('}');
+        writeln!(f, "{line}")
+    }
+    fn setup_logging(
+        debug_mode: bool,
+        log_format: LogFormat,
+        log_timestamps: LogTimestamps,
+        log_target: LogTarget,
+        banner: bool,
+    ) {
         let level_filter = match env::var("LOG_LEVEL")
             .unwrap_or_else(|_| "info".into())
             .to_ascii_lowercase()
@@ -62,27 +787,71 @@ impl Config {
             "error" => LevelFilter::Error,
             _ => LevelFilter::Debug,
         };
+        let msg = format!(
+            "Starting PWM Config Control Service v{}",
+            env!("CARGO_PKG_VERSION")
+        );
+
+        let mut syslog_error = None;
+        if log_target == LogTarget::Syslog {
+            #[cfg(feature = "syslog")]
+            match crate::syslog::Syslog::init(level_filter) {
+                Ok(()) => {
+                    if banner {
+                        info!("Log level set to: {level_filter}");
+                        info!("{msg}");
+                    }
+                    return;
+                }
+                Err(err) => syslog_error = Some(format!("Can't log to syslog: {err}")),
+            }
+            #[cfg(not(feature = "syslog"))]
+            {
+                syslog_error = Some("Built without the syslog feature".to_owned());
+            }
+        }
+
+        let no_color = env::var_os("NO_COLOR");
+        // Logs go to stderr, the startup lines below to stdout
+        let log_color = Self::color_enabled(no_color.as_deref(), io::stderr().is_terminal());
+        let print_color = Self::color_enabled(no_color.as_deref(), io::stdout().is_terminal());
 
         let mut builder = Builder::new();
 
-        if !debug_mode {
-            builder.format(|f, r| {
-                let color = Self::get_level_color(Some(r.level()));
-                writeln!(f, "{}{}{}", color, r.args(), RESET)
+        if log_format == LogFormat::Json {
+            builder.format(Self::format_json);
+        } else if !debug_mode {
+            builder.format(move |f, r| {
+                let color = Self::get_level_color(Some(r.level()), log_color);
+                let reset = Self::get_level_color(None, log_color);
+                match log_timestamps {
+                    LogTimestamps::Off => {}
+                    LogTimestamps::Seconds => write!(f, "{} ", f.timestamp_seconds())?,
+                    LogTimestamps::Millis => write!(f, "{} ", f.timestamp_millis())?,
+                }
+                writeln!(f, "{}{}{}{}", color, LogContext::prefix(), r.args(), reset)
             });
         }
 
         builder.filter_level(level_filter).init();
+        if let Some(err) = syslog_error {
+            warn!("{err}, logging to stderr");
+        }
+        if !banner {
+            return;
+        }
 
-        println!(
-            "Log level set to: {}{}{RESET}",
-            Self::get_level_color(level_filter.to_level()),
-            level_filter
-        );
+        if log_format == LogFormat::Json {
+            info!("Log level set to: {level_filter}");
+            info!("{msg}");
+            return;
+        }
 
-        let msg = format!(
-            "Starting PWM Config Control Service v{}",
-            env!("CARGO_PKG_VERSION")
+        println!(
+            "Log level set to: {}{}{}",
+            Self::get_level_color(level_filter.to_level(), print_color),
+            level_filter,
+            Self::get_level_color(None, print_color)
         );
 
         if debug_mode {
@@ -91,89 +860,845 @@ impl Config {
             println!("{msg}");
         }
     }
-    fn get_env<T: FromStr>(key: &str, fallback: T) -> T {
-        env::var(key)
+    fn var(prefix: &str, key: &str) -> Option<String> {
+        env::var(format!("{prefix}{key}"))
+            .or_else(|_| env::var(key))
             .ok()
+    }
+    fn var_os(prefix: &str, key: &str) -> Option<PathBuf> {
+        env::var_os(format!("{prefix}{key}"))
+            .or_else(|| env::var_os(key))
+            .map(PathBuf::from)
+    }
+    fn get_env<T: FromStr>(prefix: &str, key: &str, fallback: T) -> T {
+        Self::var(prefix, key)
             .and_then(|s| s.parse().ok())
             .unwrap_or(fallback)
     }
     #[must_use]
     pub fn new() -> Self {
-        let debug = Self::get_env("DEBUG", false);
-        Self::setup_logging(debug);
-        let sleep_time = Self::get_env("SLEEP_TIME", DEFAULT_SLEEP_TIME);
-        let max_threshold = Self::get_env("MAX_THRESHOLD", DEFAULT_UPPER_TEMP_THRESHOLD);
-        let min_threshold = Self::get_env("MIN_THRESHOLD", DEFAULT_LOWER_TEMP_THRESHOLD);
-        let min_state = Self::get_env("MIN_STATE", DEFAULT_MIN_STATE);
-
-        let max_state = env::var("MAX_STATE")
-            .ok()
-            .and_then(|s| s.parse::<u8>().ok());
+        Self::with_args(&Args::default())
+    }
+
+    #[must_use]
+    pub fn with_args(args: &Args) -> Self {
+        Self::with_logging(args, true)
+    }
+
+    // For the `--print-config` like reports, the only output on stdout so it
+    // can be piped. Logs still go to stderr.
+    #[must_use]
+    pub fn for_report(args: &Args) -> Self {
+        Self::with_logging(args, false)
+    }
+
+    fn with_logging(args: &Args, banner: bool) -> Self {
+        let debug = args.debug || Self::get_env("", "DEBUG", false);
+        let log_format = Self::get_env("", "LOG_FORMAT", LogFormat::default());
+        let log_timestamps = Self::get_env("", "LOG_TIMESTAMPS", LogTimestamps::default());
+        let log_target = Self::get_env("", "LOG_TARGET", LogTarget::default());
+        let log_precision = Self::get_env("", "LOG_PRECISION", DEFAULT_LOG_PRECISION);
+        LOG_PRECISION.store(log_precision.min(MAX_LOG_PRECISION), Ordering::Relaxed);
+        Self::setup_logging(debug, log_format, log_timestamps, log_target, banner);
+        Self::from_args(args, Self::from_env(""))
+    }
+
+    // Command line arguments take precedence over the values of `config`.
+    #[must_use]
+    pub fn from_args(args: &Args, config: Self) -> Self {
+        let sleep_time =
+            Self::clamp_sleep_time("SLEEP_TIME", args.sleep_time.unwrap_or(config.sleep_time));
+        // Sleep bounds that weren't set separately follow the sleep time
+        let follow_sleep_time = |key: &str, bound: u64| {
+            if bound == config.sleep_time {
+                sleep_time
+            } else {
+                Self::clamp_sleep_time(key, bound)
+            }
+        };
+
         Self {
-            sleep_time,
             threshold: Threshold {
-                max: max_threshold,
-                min: min_threshold,
+                max: args
+                    .max_threshold
+                    .map_or(config.threshold.max, |t| config.temp_unit.to_celsius(t)),
+                min: args
+                    .min_threshold
+                    .map_or(config.threshold.min, |t| config.temp_unit.to_celsius(t)),
             },
             state: State {
-                max: max_state,
-                min: min_state,
+                max: args.max_state.or(config.state.max),
+                min: args.min_state.unwrap_or(config.state.min),
             },
+            min_sleep_time: follow_sleep_time("MIN_SLEEP_TIME", config.min_sleep_time),
+            max_sleep_time: follow_sleep_time("MAX_SLEEP_TIME", config.max_sleep_time),
+            sleep_time,
+            run_once: args.once || config.run_once,
+            ..config
+        }
+    }
+
+    fn clamp_sleep_time(key: &str, value: u64) -> u64 {
+        let clamped = value.clamp(*SLEEP_TIME_RANGE.start(), *SLEEP_TIME_RANGE.end());
+        if clamped != value {
+            warn!(
+                "{key} of {value}s is outside of {}s..={}s, using {clamped}s",
+                SLEEP_TIME_RANGE.start(),
+                SLEEP_TIME_RANGE.end()
+            );
+        }
+        clamped
+    }
+
+    // Per-fan configs come from `FAN0_*`, `FAN1_*`, ... variables, each
+    // falling back to the unprefixed variable. Empty when none are set.
+    #[must_use]
+    pub fn fan_configs() -> Vec<Self> {
+        let keys: Vec<String> = env::vars_os()
+            .filter_map(|(key, _)| key.into_string().ok())
+            .collect();
+
+        (0..=u8::MAX)
+            .map(|index| format!("FAN{index}_"))
+            .take_while(|prefix| keys.iter().any(|key| key.starts_with(prefix.as_str())))
+            .map(|prefix| Self::from_env(&prefix))
+            .collect()
+    }
+
+    fn from_env(prefix: &str) -> Self {
+        let mut config = Self {
+            temp_unit: Self::get_env(prefix, "TEMP_UNIT", TempUnit::default()),
+            ..Self::default()
+        };
+        // The profile only replaces the defaults, the variables still win
+        if let Some(profile) = Self::var_parsed(prefix, "FAN_PROFILE", str::parse) {
+            config.apply_profile(profile);
+        }
+        config.thresholds_from_env(prefix);
+        config.curve_from_env(prefix);
+        config.temp_from_env(prefix);
+        config.fan_from_env(prefix);
+        config.timing_from_env(prefix);
+        config.integrations_from_env(prefix);
+        config
+    }
+
+    // None and an error logged when the variable is set but can't be parsed.
+    fn var_parsed<T>(
+        prefix: &str,
+        key: &str,
+        parse: impl FnOnce(&str) -> Result<T, String>,
+    ) -> Option<T> {
+        let value = Self::var(prefix, key)?;
+        parse(&value)
+            .map_err(|err| error!("Invalid {key} value: {err}"))
+            .ok()
+    }
+
+    // A temperature in `temp_unit`, converted to Celsius.
+    fn temp_var(&self, prefix: &str, key: &str) -> Option<f32> {
+        Self::var(prefix, key)
+            .and_then(|s| s.parse::<f32>().ok())
+            .map(|value| self.temp_unit.to_celsius(value))
+    }
+
+    // A temperature difference in `temp_unit`, converted to Celsius.
+    fn delta_var(&self, prefix: &str, key: &str) -> Option<f32> {
+        Self::var(prefix, key)
+            .and_then(|s| s.parse::<f32>().ok())
+            .map(|delta| self.temp_unit.delta_to_celsius(delta))
+    }
+
+    fn thresholds_from_env(&mut self, prefix: &str) {
+        self.threshold.max = self
+            .temp_var(prefix, "MAX_THRESHOLD")
+            .unwrap_or(self.threshold.max);
+        self.threshold.min = self
+            .temp_var(prefix, "MIN_THRESHOLD")
+            .unwrap_or(self.threshold.min);
+        self.state.min = Self::get_env(prefix, "MIN_STATE", self.state.min);
+        self.state.max = Self::var(prefix, "MAX_STATE")
+            .and_then(|s| s.parse::<u8>().ok())
+            .or(self.state.max);
+        let fixed_hysteresis = self.delta_var(prefix, "HYSTERESIS");
+        self.hysteresis = fixed_hysteresis.unwrap_or(DEFAULT_HYSTERESIS);
+        self.hysteresis_percent = Self::var(prefix, "HYSTERESIS_PERCENT")
+            .and_then(|s| s.parse::<f32>().ok())
+            .filter(|percent| *percent >= 0.0);
+        if self.hysteresis_percent.is_some() && fixed_hysteresis.is_some() {
+            warn!("HYSTERESIS and HYSTERESIS_PERCENT are both set, using HYSTERESIS");
+            self.hysteresis_percent = None;
         }
+        self.temp_rise_rate = self
+            .delta_var(prefix, "TEMP_RISE_RATE")
+            .filter(|rate| *rate > 0.0);
+        self.decision_rounding = self
+            .delta_var(prefix, "DECISION_ROUNDING")
+            .filter(|step| *step > 0.0);
+        self.critical_temp = self.temp_var(prefix, "CRITICAL_TEMP");
+        self.critical_trip = Self::get_env(prefix, "CRITICAL_TRIP", false);
+        self.fan_off_temp = self.temp_var(prefix, "FAN_OFF_TEMP");
+        self.quiet_hours = Self::var_parsed(prefix, "QUIET_HOURS", str::parse);
+        self.quiet_threshold_offset = self
+            .delta_var(prefix, "QUIET_THRESHOLD_OFFSET")
+            .unwrap_or(DEFAULT_QUIET_THRESHOLD_OFFSET);
+    }
+
+    fn curve_from_env(&mut self, prefix: &str) {
+        let temp_unit = self.temp_unit;
+        self.curve_mode = Self::get_env(prefix, "CURVE_MODE", CurveMode::default());
+        self.boundary_bias = Self::get_env(prefix, "BOUNDARY_BIAS", BoundaryBias::default());
+        self.curve_file = Self::var_os(prefix, "CURVE_FILE");
+        self.slots = Self::var_parsed(prefix, "SLOTS", Self::parse_slots).map(|slots| {
+            slots
+                .into_iter()
+                .map(|(state, value)| (state, temp_unit.to_celsius(value)))
+                .collect()
+        });
+        self.fixed_state = Self::var(prefix, "FIXED_STATE").and_then(|s| s.parse::<u8>().ok());
+        self.deadband_states = Self::get_env(prefix, "DEADBAND_STATES", 0);
+        self.load_bias = Self::get_env(prefix, "LOAD_BIAS", 0);
+    }
+
+    fn temp_from_env(&mut self, prefix: &str) {
+        self.thermal_zone_path = Self::var_os(prefix, "THERMAL_ZONE_PATH");
+        self.thermal_zone_types = Self::var(prefix, "THERMAL_ZONE_TYPE")
+            .map(|s| Self::parse_zone_types(&s))
+            .unwrap_or_default();
+        self.temp_zones = Self::var_parsed(prefix, "TEMP_ZONES", Self::parse_temp_zones);
+        self.temp_file_path = Self::var_os(prefix, "TEMP_FILE_PATH");
+        self.temp_file_divisor =
+            Self::var(prefix, "TEMP_FILE_DIVISOR").map_or(DEFAULT_TEMP_FILE_DIVISOR, |s| {
+                match s.parse::<u32>() {
+                    Ok(divisor) if divisor != 0 => divisor,
+                    _ => {
+                        error!("Invalid TEMP_FILE_DIVISOR value: {s}, expected a positive integer");
+                        DEFAULT_TEMP_FILE_DIVISOR
+                    }
+                }
+            });
+        self.temp_scale = Self::get_env(prefix, "TEMP_SCALE", TempScale::default());
+        self.temp_offset = self.delta_var(prefix, "TEMP_OFFSET").unwrap_or(0.0);
+        self.temp_aggregation =
+            Self::get_env(prefix, "TEMP_AGGREGATION", TempAggregation::default());
+        self.temp_ema_alpha = Self::get_env(prefix, "TEMP_EMA_ALPHA", DEFAULT_TEMP_EMA_ALPHA);
+        self.temp_samples = Self::get_env(prefix, "TEMP_SAMPLES", 1);
+        self.alarm_input_path = Self::var_os(prefix, "ALARM_INPUT_PATH");
+        self.on_temp_failure = Self::get_env(prefix, "ON_TEMP_FAILURE", OnTempFailure::default());
+    }
+
+    fn fan_from_env(&mut self, prefix: &str) {
+        self.sysfs_root =
+            Self::var_os(prefix, "SYSFS_ROOT").unwrap_or_else(|| PathBuf::from(DEFAULT_SYSFS_ROOT));
+        self.fan_device_path = Self::var_os(prefix, "FAN_DEVICE_PATH");
+        self.fan_device_name = Self::var(prefix, "FAN_DEVICE_NAME");
+        self.cooling_device =
+            Self::var(prefix, "COOLING_DEVICE").and_then(|s| s.parse::<u32>().ok());
+        self.default_max_state =
+            Self::var(prefix, "DEFAULT_MAX_STATE").and_then(|s| s.parse::<u8>().ok());
+        self.fan_backend = Self::get_env(prefix, "FAN_BACKEND", FanBackend::default());
+        self.pwm = Pwm {
+            chip: Self::get_env(prefix, "PWMCHIP", 0),
+            channel: Self::get_env(prefix, "PWM_CHANNEL", 0),
+            period: Self::get_env(prefix, "PWM_PERIOD", DEFAULT_PWM_PERIOD),
+            sysfs_path: Self::var_os(prefix, "PWM_SYSFS_PATH"),
+            max_value: Self::get_env(prefix, "PWM_MAX_VALUE", DEFAULT_PWM_MAX_VALUE),
+        };
+        self.tach_path = Self::var_os(prefix, "FAN_TACH_PATH");
+        self.stall_rpm = Self::get_env(prefix, "STALL_RPM", 0);
+        self.stall_checks = Self::get_env(prefix, "STALL_CHECKS", DEFAULT_STALL_CHECKS);
+        self.invert_state = Self::get_env(prefix, "INVERT_STATE", false);
+        self.take_over_governor = Self::get_env(prefix, "TAKE_OVER_GOVERNOR", false);
+        self.spinup_kick = Self::get_env(prefix, "SPINUP_KICK", false);
+        self.spinup_ms = Self::get_env(prefix, "SPINUP_MS", DEFAULT_SPINUP_MS);
+        self.dry_run = Self::get_env(prefix, "DRY_RUN", false);
+        self.on_device_loss = Self::get_env(prefix, "ON_DEVICE_LOSS", OnDeviceLoss::default());
+        self.failsafe_state =
+            Self::var(prefix, "FAILSAFE_STATE").and_then(|s| s.parse::<u8>().ok());
+        self.shutdown_state =
+            Self::var(prefix, "SHUTDOWN_STATE").and_then(|s| s.parse::<u8>().ok());
+    }
+
+    fn timing_from_env(&mut self, prefix: &str) {
+        self.sleep_time = Self::get_env(prefix, "SLEEP_TIME", DEFAULT_SLEEP_TIME);
+        self.min_sleep_time = Self::get_env(prefix, "MIN_SLEEP_TIME", self.sleep_time);
+        self.max_sleep_time = Self::get_env(prefix, "MAX_SLEEP_TIME", self.sleep_time);
+        self.sleep_jitter = Self::get_env(prefix, "SLEEP_JITTER", 0);
+        self.ramp_step = Self::var(prefix, "RAMP_STEP")
+            .and_then(|s| s.parse::<u8>().ok())
+            .filter(|step| *step > 0);
+        self.min_on_time = Self::get_env(prefix, "MIN_ON_TIME", 0);
+        self.warmup_time = Self::get_env(prefix, "WARMUP_TIME", 0);
+        self.warmup_state = Self::var(prefix, "WARMUP_STATE").and_then(|s| s.parse::<u8>().ok());
+        self.dwell_time = Self::get_env(prefix, "DWELL_TIME", 0);
+        self.max_writes_per_min = Self::get_env(prefix, "MAX_WRITES_PER_MIN", 0);
+        self.stats_interval = Self::get_env(prefix, "STATS_INTERVAL", 0);
+        self.rescan_interval = Self::get_env(prefix, "RESCAN_INTERVAL", 0);
+        self.run_once = Self::get_env(prefix, "RUN_ONCE", false);
+    }
+
+    fn integrations_from_env(&mut self, prefix: &str) {
+        self.state_file = Self::var_os(prefix, "STATE_FILE");
+        self.mqtt_host = Self::var(prefix, "MQTT_HOST");
+        self.mqtt_topic_prefix = Self::var(prefix, "MQTT_TOPIC_PREFIX")
+            .unwrap_or_else(|| DEFAULT_MQTT_TOPIC_PREFIX.to_owned());
+        self.event_socket = Self::var_os(prefix, "EVENT_SOCKET");
+        self.http_addr = Self::var(prefix, "HTTP_ADDR");
+        self.history_size = Self::get_env(prefix, "HISTORY_SIZE", DEFAULT_HISTORY_SIZE);
+        self.dbus_bus = Self::var_parsed(prefix, "DBUS_BUS", str::parse);
+        self.pid_file = Self::var_os(prefix, "PID_FILE");
+        self.pause_file = Self::var_os(prefix, "PAUSE_FILE");
+        self.on_change_cmd =
+            Self::var(prefix, "ON_CHANGE_CMD").filter(|cmd| !cmd.trim().is_empty());
+    }
+
+    #[must_use]
+    pub fn thermal_dir(&self) -> PathBuf {
+        self.sysfs_root.join(THERMAL_DIR)
+    }
+
+    #[must_use]
+    pub fn pwm_dir(&self) -> PathBuf {
+        self.sysfs_root.join(PWM_DIR)
+    }
+
+    // `HYSTERESIS_PERCENT` of the computed slot step when set, so the band
+    // follows the thresholds, `HYSTERESIS` otherwise.
+    #[must_use]
+    pub fn effective_hysteresis(&self, device_max_state: u8) -> f32 {
+        self.hysteresis_percent.map_or(self.hysteresis, |percent| {
+            let max_state = self.state.max.unwrap_or(device_max_state);
+            // An on/off fan has no step between slots, the whole range is
+            let step = if max_state.saturating_sub(self.state.min) == 1 {
+                self.threshold.max - self.threshold.min
+            } else {
+                policy::slot_step(
+                    self.threshold.min,
+                    self.threshold.max,
+                    self.state.min,
+                    max_state,
+                )
+            };
+            step * percent / 100.0
+        })
+    }
+
+    // Rounds to the nearest multiple of `DECISION_ROUNDING` so a sensor
+    // wobbling around a slot threshold doesn't flip the state.
+    #[must_use]
+    pub fn round_for_decision(&self, temp: f32) -> f32 {
+        self.decision_rounding
+            .map_or(temp, |step| (temp / step).round() * step)
+    }
+
+    // Zone types in order of preference, empty entries are skipped.
+    #[must_use]
+    pub fn parse_zone_types(s: &str) -> Vec<String> {
+        s.split(',')
+            .map(str::trim)
+            .filter(|zone_type| !zone_type.is_empty())
+            .map(str::to_owned)
+            .collect()
+    }
+
+    pub fn parse_slots(s: &str) -> Result<Vec<(u8, f32)>, String> {
+        s.split(',')
+            .map(|slot| {
+                let (temp, state) = slot
+                    .trim()
+                    .split_once(':')
+                    .ok_or_else(|| format!("expected temp:state, got '{slot}'"))?;
+                let temp = temp
+                    .trim()
+                    .parse::<f32>()
+                    .map_err(|e| format!("invalid temperature '{temp}': {e}"))?;
+                let state = state
+                    .trim()
+                    .parse::<u8>()
+                    .map_err(|e| format!("invalid state '{state}': {e}"))?;
+                Ok((state, temp))
+            })
+            .collect()
+    }
+
+    pub fn parse_temp_zones(s: &str) -> Result<Vec<(String, f32)>, String> {
+        s.split(',')
+            .map(|zone| {
+                let (zone_type, weight) = zone
+                    .trim()
+                    .split_once(':')
+                    .ok_or_else(|| format!("expected type:weight, got '{zone}'"))?;
+                let weight = weight
+                    .trim()
+                    .parse::<f32>()
+                    .map_err(|e| format!("invalid weight '{weight}': {e}"))?;
+                if !(weight.is_finite() && weight > 0.0) {
+                    return Err(format!("weight of {zone_type} must be positive"));
+                }
+                Ok((zone_type.trim().to_owned(), weight))
+            })
+            .collect()
+    }
+
+    // Sets the thresholds and state range of a profile, `from_env` applies it
+    // before reading them so explicit values take precedence.
+    pub const fn apply_profile(&mut self, profile: FanProfile) {
+        let (min, max, min_state, max_state) = match profile {
+            FanProfile::Quiet => (50.0, 75.0, 0, Some(3)),
+            FanProfile::Balanced => (
+                DEFAULT_LOWER_TEMP_THRESHOLD,
+                DEFAULT_UPPER_TEMP_THRESHOLD,
+                DEFAULT_MIN_STATE,
+                None,
+            ),
+            FanProfile::Aggressive => (35.0, 55.0, 1, None),
+        };
+        self.threshold = Threshold { max, min };
+        self.state = State {
+            max: max_state,
+            min: min_state,
+        };
+        self.fan_profile = Some(profile);
+    }
+
+    pub fn check_config(&self, fan_max_state: u8) -> Result<(), ConfigError> {
+        if self.threshold.min >= self.threshold.max {
+            return Err(ConfigError::ThresholdOrder {
+                min: self.threshold.min,
+                max: self.threshold.max,
+            });
+        }
+
+        if let Some(fan_off_temp) = self.fan_off_temp
+            && fan_off_temp > self.threshold.min
+        {
+            return Err(ConfigError::FanOffTempAboveMin {
+                fan_off_temp,
+                min: self.threshold.min,
+            });
+        }
+
+        if let Some(max) = self.state.max {
+            if self.state.min >= max {
+                return Err(ConfigError::StateOrder {
+                    min: self.state.min,
+                    max,
+                });
+            }
+
+            if max > fan_max_state {
+                return Err(ConfigError::MaxStateExceedsDevice {
+                    max,
+                    device_max: fan_max_state,
+                });
+            }
+        }
+
+        if self.state.min > fan_max_state {
+            return Err(ConfigError::MinStateExceedsDevice {
+                min: self.state.min,
+                device_max: fan_max_state,
+            });
+        }
+
+        if self.hysteresis < 0.0 {
+            return Err(ConfigError::NegativeHysteresis(self.hysteresis));
+        }
+
+        if self.min_sleep_time > self.sleep_time || self.sleep_time > self.max_sleep_time {
+            return Err(ConfigError::SleepTimeOrder {
+                min: self.min_sleep_time,
+                default: self.sleep_time,
+                max: self.max_sleep_time,
+            });
+        }
+
+        if self.temp_ema_alpha <= 0.0 || self.temp_ema_alpha > 1.0 {
+            return Err(ConfigError::EmaAlphaOutOfRange(self.temp_ema_alpha));
+        }
+
+        for warning in self.warnings(fan_max_state) {
+            warn!("{warning}");
+        }
+
+        Ok(())
+    }
+
+    // Settings that are valid but most likely not what was meant.
+    #[must_use]
+    pub fn warnings(&self, fan_max_state: u8) -> Vec<String> {
+        let unit = self.temp_unit;
+        let mut warnings = Vec::new();
+        if self.threshold.max < LOW_MAX_THRESHOLD {
+            warnings.push(format!(
+                "MAX_THRESHOLD of {} is low, the fan will most likely stay at its max state",
+                unit.display(self.threshold.max)
+            ));
+        }
+        if let Some(critical_temp) = self.critical_temp
+            && critical_temp <= self.threshold.max
+        {
+            warnings.push(format!(
+                "CRITICAL_TEMP of {} is not above MAX_THRESHOLD, the highest slots are never used",
+                unit.display(critical_temp)
+            ));
+        }
+        let unreachable = self.unreachable_states(fan_max_state);
+        if !unreachable.is_empty() {
+            let states = unreachable
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ");
+            warnings.push(format!(
+                "Slot states {states} are never reached between {} and {}",
+                unit.display(self.threshold.min),
+                unit.display(self.threshold.max)
+            ));
+        }
+        warnings
+    }
+
+    // States of the slot table that no temperature selects, because their
+    // slot is outside of the thresholds or narrower than `DECISION_ROUNDING`.
+    fn unreachable_states(&self, fan_max_state: u8) -> Vec<u8> {
+        if self.curve_mode == CurveMode::Linear || self.curve_file.is_some() {
+            return Vec::new();
+        }
+        let max_state = self.state.max.unwrap_or(fan_max_state);
+        let slots: Vec<(u8, f32)> = self.slots.clone().unwrap_or_else(|| {
+            Fan::calculate_slots(self, max_state)
+                .into_iter()
+                .flatten()
+                .collect()
+        });
+        if slots.windows(2).any(|pair| pair[0].1 >= pair[1].1) {
+            // Rejected when the fan is set up, the computed slots are used instead
+            return Vec::new();
+        }
+
+        slots
+            .iter()
+            .enumerate()
+            .filter(|&(i, &(state, temp))| {
+                // Reached anyway below and above the thresholds
+                if state == self.state.min || state == max_state {
+                    return false;
+                }
+                let next = slots.get(i + 1).map_or(f32::INFINITY, |&(_, next)| next);
+                let lowest = temp.max(self.threshold.min);
+                let lowest = self
+                    .decision_rounding
+                    .map_or(lowest, |step| (lowest / step).ceil() * step);
+                !(lowest < next && lowest <= self.threshold.max)
+            })
+            .map(|(_, &(state, _))| state)
+            .collect()
+    }
+
+    // Replaces the settings validated by `check_config` with their defaults,
+    // which are valid for any device.
+    pub fn reset_to_defaults(&mut self) {
+        let defaults = Self::default();
+        self.threshold = defaults.threshold;
+        self.state = defaults.state;
+        self.hysteresis = defaults.hysteresis;
+        self.min_sleep_time = self.sleep_time;
+        self.max_sleep_time = self.sleep_time;
+        self.temp_ema_alpha = defaults.temp_ema_alpha;
+        self.fan_off_temp = defaults.fan_off_temp;
+    }
+
+    #[must_use]
+    pub fn describe(&self) -> String {
+        let mut description = String::new();
+        for (name, value) in [
+            self.decision_settings(),
+            self.timing_settings(),
+            self.fan_settings(),
+            self.temp_settings(),
+            self.service_settings(),
+        ]
+        .into_iter()
+        .flatten()
+        {
+            Self::describe_setting(&mut description, name, &value);
+        }
+        description
+    }
+
+    fn decision_settings(&self) -> Vec<(&'static str, String)> {
+        let unit = self.temp_unit;
+        let slots = self.slots.as_ref().map(|slots| {
+            slots
+                .iter()
+                .map(|&(state, temp)| format!("{state}@{}", unit.display(temp)))
+                .collect::<Vec<_>>()
+                .join(", ")
+        });
+
+        vec![
+            ("min threshold", unit.display(self.threshold.min)),
+            ("max threshold", unit.display(self.threshold.max)),
+            (
+                "critical temp",
+                or_unset(self.critical_temp.map(|t| unit.display(t))),
+            ),
+            ("critical trip", self.critical_trip.to_string()),
+            (
+                "fan off temp",
+                or_unset(self.fan_off_temp.map(|t| unit.display(t))),
+            ),
+            ("hysteresis", self.temp_unit.display_delta(self.hysteresis)),
+            (
+                "hysteresis percent",
+                or_unset(self.hysteresis_percent.map(|percent| format!("{percent}%"))),
+            ),
+            (
+                "temp rise rate",
+                or_unset(
+                    self.temp_rise_rate
+                        .map(|rate| format!("{}/s", self.temp_unit.display_delta(rate))),
+                ),
+            ),
+            (
+                "decision rounding",
+                or_unset(
+                    self.decision_rounding
+                        .map(|step| self.temp_unit.display_delta(step)),
+                ),
+            ),
+            ("min state", self.state.min.to_string()),
+            ("max state", or_unset(self.state.max)),
+            ("shutdown state", or_unset(self.shutdown_state)),
+            ("fixed state", or_unset(self.fixed_state)),
+            ("curve mode", format!("{:?}", self.curve_mode)),
+            ("boundary bias", format!("{:?}", self.boundary_bias)),
+            ("on device loss", format!("{:?}", self.on_device_loss)),
+            ("on temp failure", format!("{:?}", self.on_temp_failure)),
+            ("failsafe state", or_unset(self.failsafe_state)),
+            (
+                "fan profile",
+                or_unset(self.fan_profile.map(|profile| format!("{profile:?}"))),
+            ),
+            ("slots", or_unset(slots)),
+            ("curve file", path_or_unset(self.curve_file.as_deref())),
+        ]
+    }
+
+    fn timing_settings(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("ramp step", or_unset(self.ramp_step)),
+            ("min on time", format!("{}s", self.min_on_time)),
+            ("warmup time", format!("{}s", self.warmup_time)),
+            ("warmup state", or_unset(self.warmup_state)),
+            ("dwell time", format!("{}s", self.dwell_time)),
+            ("max writes per minute", self.max_writes_per_min.to_string()),
+            ("deadband states", self.deadband_states.to_string()),
+            ("spin-up kick", self.spinup_kick.to_string()),
+            ("spin-up duration", format!("{}ms", self.spinup_ms)),
+            ("sleep time", format!("{}s", self.sleep_time)),
+            ("min sleep time", format!("{}s", self.min_sleep_time)),
+            ("max sleep time", format!("{}s", self.max_sleep_time)),
+            ("sleep jitter", format!("{}s", self.sleep_jitter)),
+        ]
+    }
+
+    fn fan_settings(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("fan backend", format!("{:?}", self.fan_backend)),
+            ("invert state", self.invert_state.to_string()),
+            ("take over governor", self.take_over_governor.to_string()),
+            ("cooling device", or_unset(self.cooling_device)),
+            ("fan device name", or_unset(self.fan_device_name.as_ref())),
+            (
+                "fan device path",
+                path_or_unset(self.fan_device_path.as_deref()),
+            ),
+            (
+                "pwm",
+                format!(
+                    "chip {}, channel {}, period {}ns",
+                    self.pwm.chip, self.pwm.channel, self.pwm.period
+                ),
+            ),
+            (
+                "pwm sysfs path",
+                path_or_unset(self.pwm.sysfs_path.as_deref()),
+            ),
+            ("pwm max value", self.pwm.max_value.to_string()),
+            ("tach path", path_or_unset(self.tach_path.as_deref())),
+            ("stall rpm", self.stall_rpm.to_string()),
+            ("stall checks", self.stall_checks.to_string()),
+        ]
+    }
+
+    fn temp_settings(&self) -> Vec<(&'static str, String)> {
+        vec![
+            (
+                "thermal zone path",
+                path_or_unset(self.thermal_zone_path.as_deref()),
+            ),
+            (
+                "temp file path",
+                path_or_unset(self.temp_file_path.as_deref()),
+            ),
+            ("temp file divisor", self.temp_file_divisor.to_string()),
+            (
+                "thermal zone type",
+                or_unset(
+                    (!self.thermal_zone_types.is_empty())
+                        .then(|| self.thermal_zone_types.join(", ")),
+                ),
+            ),
+            (
+                "temp zones",
+                or_unset(self.temp_zones.as_ref().map(|zones| {
+                    zones
+                        .iter()
+                        .map(|(zone_type, weight)| format!("{zone_type}:{weight}"))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                })),
+            ),
+            ("temp aggregation", format!("{:?}", self.temp_aggregation)),
+            ("temp scale", format!("{:?}", self.temp_scale)),
+            (
+                "temp offset",
+                self.temp_unit.display_delta(self.temp_offset),
+            ),
+            ("temp unit", format!("{:?}", self.temp_unit)),
+            ("temp samples", self.temp_samples.to_string()),
+            ("temp smoothing", self.temp_ema_alpha.to_string()),
+        ]
+    }
+
+    fn service_settings(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("state file", path_or_unset(self.state_file.as_deref())),
+            ("pid file", path_or_unset(self.pid_file.as_deref())),
+            ("pause file", path_or_unset(self.pause_file.as_deref())),
+            ("on change command", or_unset(self.on_change_cmd.as_ref())),
+            ("sysfs root", self.sysfs_root.display().to_string()),
+            (
+                "alarm input path",
+                path_or_unset(self.alarm_input_path.as_deref()),
+            ),
+            ("quiet hours", or_unset(self.quiet_hours)),
+            (
+                "quiet threshold offset",
+                self.temp_unit.display_delta(self.quiet_threshold_offset),
+            ),
+            ("load bias", self.load_bias.to_string()),
+            ("event socket", path_or_unset(self.event_socket.as_deref())),
+            ("http address", or_unset(self.http_addr.as_ref())),
+            (
+                "dbus bus",
+                or_unset(self.dbus_bus.map(|bus| format!("{bus:?}"))),
+            ),
+            ("mqtt host", or_unset(self.mqtt_host.as_ref())),
+            ("mqtt topic prefix", self.mqtt_topic_prefix.clone()),
+            ("stats interval", self.stats_interval.to_string()),
+            ("rescan interval", self.rescan_interval.to_string()),
+            ("default max state", or_unset(self.default_max_state)),
+            ("history size", self.history_size.to_string()),
+            ("dry run", self.dry_run.to_string()),
+            ("run once", self.run_once.to_string()),
+        ]
+    }
+
+    pub(crate) fn describe_setting(description: &mut String, name: &str, value: &str) {
+        // Names longer than the column still get a space before the value
+        let width = DESCRIBE_WIDTH.max(name.len() + 1);
+        let _ = writeln!(description, "{name:<width$}{value}");
+    }
+}
+
+// Fluent construction of a `Config` from the defaults, for embedding the
+// crate or writing tests without going through the environment.
+#[derive(Clone, Default)]
+pub struct ConfigBuilder {
+    config: Config,
+}
+
+impl ConfigBuilder {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
     }
 
-    pub fn check_config(&self, fan_max_state: u8) {
-        assert!(
-            self.threshold.min < self.threshold.max,
-            "min threshold can't be >= max threshold: {} >= {}",
-            self.threshold.min,
-            self.threshold.max
-        );
-        if let Some(max) = self.state.max {
-            assert!(
-                (self.state.min < max),
-                "min state can't be >= max state: {} >= {max}",
-                self.state.min
-            );
+    #[must_use]
+    pub const fn min_threshold(mut self, celsius: f32) -> Self {
+        self.config.threshold.min = celsius;
+        self
+    }
 
-            assert!(
-                (max <= fan_max_state),
-                "Configured max state {max} exceeds device max state {fan_max_state}"
-            );
-        }
+    #[must_use]
+    pub const fn max_threshold(mut self, celsius: f32) -> Self {
+        self.config.threshold.max = celsius;
+        self
+    }
 
-        assert!(
-            (self.state.min <= fan_max_state),
-            "Configured min state {} exceeds device max state {}",
-            self.state.min,
-            fan_max_state
-        );
+    #[must_use]
+    pub const fn min_state(mut self, state: u8) -> Self {
+        self.config.state.min = state;
+        self
+    }
+
+    #[must_use]
+    pub const fn max_state(mut self, state: u8) -> Self {
+        self.config.state.max = Some(state);
+        self
+    }
+
+    // The sleep bounds follow, like when only `SLEEP_TIME` is set.
+    #[must_use]
+    pub const fn sleep_time(mut self, seconds: u64) -> Self {
+        self.config.sleep_time = seconds;
+        self.config.min_sleep_time = seconds;
+        self.config.max_sleep_time = seconds;
+        self
+    }
+
+    // Device limits aren't known yet, they are checked again when the fan
+    // is opened.
+    pub fn build(self) -> Result<Config, ConfigError> {
+        self.config.check_config(u8::MAX)?;
+        Ok(self.config)
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::float_cmp)]
 mod tests {
-    use std::panic;
+    use crate::config::{
+        Config, DEFAULT_LOWER_TEMP_THRESHOLD, DEFAULT_MAX_STATE, DEFAULT_MIN_STATE,
+        DEFAULT_SLEEP_TIME, DEFAULT_UPPER_TEMP_THRESHOLD,
+    };
 
-    use crate::config::{Config, DEFAULT_MAX_STATE, DEFAULT_SLEEP_TIME};
+    use std::ffi::OsStr;
 
-    use super::{State, Threshold};
+    use clap::Parser;
+    use log::{
+        Level,
+        kv::{Source, Value},
+    };
 
-    fn assert_panics<F: FnOnce() + panic::UnwindSafe>(f: F, msg_contains: &str) {
-        let result = panic::catch_unwind(f);
-        assert!(
-            result.is_err(),
-            "Expected panic, but function did not panic"
-        );
-        let err = result.unwrap_err();
-        let err_msg = err
-            .downcast_ref::<String>()
-            .map(std::string::String::as_str)
-            .or_else(|| err.downcast_ref::<&str>().map(|s| *s))
-            .unwrap_or("<non-string panic>");
+    use crate::cli::Args;
+
+    use super::{
+        BoundaryBias, ConfigBuilder, ConfigError, CurveMode, DbusBus, FanBackend, FanProfile,
+        JsonFields, LogContext, LogFormat, LogTarget, LogTimestamps, OnDeviceLoss, OnTempFailure,
+        QuietHours, State, TempAggregation, TempUnit, Threshold, json_string,
+    };
+
+    fn assert_invalid(config: &Config, fan_max_state: u8, msg_contains: &str) {
+        let err = config
+            .check_config(fan_max_state)
+            .expect_err("Expected an invalid configuration");
         assert!(
-            err_msg.contains(msg_contains),
-            "Panic message did not contain '{msg_contains}': got '{err_msg}'"
+            err.to_string().contains(msg_contains),
+            "Error did not contain '{msg_contains}': got '{err}'"
         );
     }
 
@@ -192,8 +1717,9 @@ mod tests {
                 min: min_state,
             },
             sleep_time: DEFAULT_SLEEP_TIME,
+            ..Config::default()
         };
-        config.check_config(5);
+        assert!(config.check_config(5).is_ok());
     }
 
     #[test]
@@ -210,14 +1736,15 @@ mod tests {
                 min: min_state,
             },
             sleep_time: DEFAULT_SLEEP_TIME,
+            ..Config::default()
         };
         let msg_contains =
             format!("Configured min state {min_state} exceeds device max state {max_state}");
-        assert_panics(|| config.check_config(5), &msg_contains);
+        assert_invalid(&config, 5, &msg_contains);
     }
 
     #[test]
-    fn test_min_state_greater_than_or_equal_to_max_panics() {
+    fn test_min_state_greater_than_or_equal_to_max_is_invalid() {
         let max_state = Some(3);
         let min_state = 3;
 
@@ -231,13 +1758,14 @@ mod tests {
                 min: min_state,
             },
             sleep_time: DEFAULT_SLEEP_TIME,
+            ..Config::default()
         };
 
-        assert_panics(|| config.check_config(5), "min state can't be >=");
+        assert_invalid(&config, 5, "min state can't be >=");
     }
 
     #[test]
-    fn test_max_state_exceeds_device_max_panics() {
+    fn test_max_state_exceeds_device_max_is_invalid() {
         let max_state = Some(6);
         let min_state = 3;
 
@@ -251,13 +1779,14 @@ mod tests {
                 min: min_state,
             },
             sleep_time: DEFAULT_SLEEP_TIME,
+            ..Config::default()
         };
 
-        assert_panics(|| config.check_config(5), "exceeds device max state");
+        assert_invalid(&config, 5, "exceeds device max state");
     }
 
     #[test]
-    fn threshold_min_exceeds_threshold_max_panics() {
+    fn threshold_min_exceeds_threshold_max_is_invalid() {
         let max_state = Some(DEFAULT_MAX_STATE);
         let min_state = 0;
 
@@ -271,9 +1800,10 @@ mod tests {
                 min: min_state,
             },
             sleep_time: DEFAULT_SLEEP_TIME,
+            ..Config::default()
         };
 
-        assert_panics(|| config.check_config(5), "min threshold can't be >=");
+        assert_invalid(&config, 5, "min threshold can't be >=");
     }
 
     #[test]
@@ -291,13 +1821,14 @@ mod tests {
                 min: min_state,
             },
             sleep_time: DEFAULT_SLEEP_TIME,
+            ..Config::default()
         };
 
-        config.check_config(5);
+        assert!(config.check_config(5).is_ok());
     }
 
     #[test]
-    fn test_threshold_min_equals_max_panics() {
+    fn test_threshold_min_equals_max_is_invalid() {
         let config: Config = Config {
             threshold: Threshold {
                 max: 50.0,
@@ -308,9 +1839,10 @@ mod tests {
                 min: 0,
             },
             sleep_time: DEFAULT_SLEEP_TIME,
+            ..Config::default()
         };
 
-        assert_panics(|| config.check_config(5), "min threshold can't be >=");
+        assert_invalid(&config, 5, "min threshold can't be >=");
     }
 
     #[test]
@@ -322,9 +1854,10 @@ mod tests {
             },
             state: State { max: None, min: 5 },
             sleep_time: DEFAULT_SLEEP_TIME,
+            ..Config::default()
         };
 
-        config.check_config(5);
+        assert!(config.check_config(5).is_ok());
     }
 
     #[test]
@@ -339,9 +1872,10 @@ mod tests {
                 min: 0,
             },
             sleep_time: DEFAULT_SLEEP_TIME,
+            ..Config::default()
         };
 
-        config.check_config(5);
+        assert!(config.check_config(5).is_ok());
     }
 
     #[test]
@@ -356,9 +1890,10 @@ mod tests {
                 min: 0,
             },
             sleep_time: DEFAULT_SLEEP_TIME,
+            ..Config::default()
         };
 
-        config.check_config(5);
+        assert!(config.check_config(5).is_ok());
     }
 
     #[test]
@@ -373,9 +1908,10 @@ mod tests {
                 min: 0,
             },
             sleep_time: DEFAULT_SLEEP_TIME,
+            ..Config::default()
         };
 
-        config.check_config(5);
+        assert!(config.check_config(5).is_ok());
     }
 
     #[test]
@@ -390,13 +1926,14 @@ mod tests {
                 min: 1,
             },
             sleep_time: DEFAULT_SLEEP_TIME,
+            ..Config::default()
         };
 
-        config.check_config(5);
+        assert!(config.check_config(5).is_ok());
     }
 
     #[test]
-    fn test_device_max_state_zero_panics() {
+    fn test_device_max_state_zero_is_invalid() {
         let config: Config = Config {
             threshold: Threshold {
                 max: 60.0,
@@ -404,11 +1941,706 @@ mod tests {
             },
             state: State { max: None, min: 1 },
             sleep_time: DEFAULT_SLEEP_TIME,
+            ..Config::default()
         };
 
-        assert_panics(
-            || config.check_config(0),
+        assert_invalid(
+            &config,
+            0,
             "Configured min state 1 exceeds device max state 0",
         );
     }
+
+    #[test]
+    fn test_negative_hysteresis_is_invalid() {
+        let config: Config = Config {
+            threshold: Threshold {
+                max: 60.0,
+                min: 40.0,
+            },
+            state: State {
+                max: Some(DEFAULT_MAX_STATE),
+                min: 0,
+            },
+            sleep_time: DEFAULT_SLEEP_TIME,
+            hysteresis: -1.0,
+            ..Config::default()
+        };
+
+        assert_invalid(&config, 5, "hysteresis can't be negative");
+    }
+
+    #[test]
+    fn test_parse_temp_aggregation() {
+        assert_eq!("first".parse(), Ok(TempAggregation::First));
+        assert_eq!("MAX".parse(), Ok(TempAggregation::Max));
+        assert_eq!("mean".parse(), Ok(TempAggregation::Mean));
+        assert!("median".parse::<TempAggregation>().is_err());
+    }
+
+    #[test]
+    fn test_parse_on_device_loss() {
+        assert_eq!("hold".parse(), Ok(OnDeviceLoss::Hold));
+        assert_eq!("FailSafe".parse(), Ok(OnDeviceLoss::Failsafe));
+        assert!("max".parse::<OnDeviceLoss>().is_err());
+    }
+
+    #[test]
+    fn test_effective_hysteresis_percent_of_slot_step() {
+        let config = Config {
+            hysteresis_percent: Some(50.0),
+            ..Config::default()
+        };
+        // 5 slots from 45°C to 65°C are 5°C apart
+        assert_eq!(config.effective_hysteresis(DEFAULT_MAX_STATE), 2.5);
+
+        // Follows the thresholds and the states
+        let config = Config {
+            threshold: Threshold {
+                max: 75.0,
+                min: 45.0,
+            },
+            state: State {
+                max: Some(4),
+                min: 1,
+            },
+            ..config
+        };
+        assert_eq!(config.effective_hysteresis(DEFAULT_MAX_STATE), 7.5);
+
+        // Percentage of the whole range for an on/off fan
+        let on_off = Config {
+            hysteresis_percent: Some(10.0),
+            ..Config::default()
+        };
+        assert_eq!(on_off.effective_hysteresis(1), 2.0);
+
+        let config = Config {
+            hysteresis_percent: None,
+            ..config
+        };
+        assert_eq!(config.effective_hysteresis(DEFAULT_MAX_STATE), 2.0);
+    }
+
+    #[test]
+    fn test_parse_on_temp_failure() {
+        assert_eq!("hold".parse(), Ok(OnTempFailure::Hold));
+        assert_eq!("FAILSAFE".parse(), Ok(OnTempFailure::Failsafe));
+        assert!("max".parse::<OnTempFailure>().is_err());
+    }
+
+    #[test]
+    fn test_parse_fan_profile() {
+        assert_eq!("quiet".parse(), Ok(FanProfile::Quiet));
+        assert_eq!("Balanced".parse(), Ok(FanProfile::Balanced));
+        assert_eq!("AGGRESSIVE".parse(), Ok(FanProfile::Aggressive));
+        assert!("silent".parse::<FanProfile>().is_err());
+    }
+
+    #[test]
+    fn test_quiet_hours() {
+        let night: QuietHours = "22:00-07:00".parse().unwrap();
+        assert_eq!(
+            night,
+            QuietHours {
+                start: 1320,
+                end: 420
+            }
+        );
+        assert_eq!(night.to_string(), "22:00-07:00");
+        for (minutes, quiet) in [
+            (1319, false),
+            (1320, true),
+            (0, true),
+            (419, true),
+            (420, false),
+        ] {
+            assert_eq!(night.contains(minutes), quiet, "{minutes}");
+        }
+
+        let lunch: QuietHours = " 12:30 - 13:15".parse().unwrap();
+        assert!(lunch.contains(12 * 60 + 30));
+        assert!(!lunch.contains(13 * 60 + 15));
+        assert!(!lunch.contains(23 * 60));
+
+        for invalid in ["22:00", "24:00-07:00", "22:60-07:00", "ten-seven"] {
+            assert!(invalid.parse::<QuietHours>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_apply_profile_quiet() {
+        let mut config = Config::default();
+        config.apply_profile(FanProfile::Quiet);
+        assert_eq!(config.fan_profile, Some(FanProfile::Quiet));
+        assert_eq!(config.threshold.min, 50.0);
+        assert_eq!(config.threshold.max, 75.0);
+        assert_eq!(config.state.min, 0);
+        assert_eq!(config.state.max, Some(3));
+    }
+
+    #[test]
+    fn test_apply_profile_balanced() {
+        let mut config = Config::default();
+        config.apply_profile(FanProfile::Balanced);
+        assert_eq!(config.fan_profile, Some(FanProfile::Balanced));
+        assert_eq!(config.threshold.min, DEFAULT_LOWER_TEMP_THRESHOLD);
+        assert_eq!(config.threshold.max, DEFAULT_UPPER_TEMP_THRESHOLD);
+        assert_eq!(config.state.min, DEFAULT_MIN_STATE);
+        assert_eq!(config.state.max, None);
+    }
+
+    #[test]
+    fn test_apply_profile_aggressive() {
+        let mut config = Config::default();
+        config.apply_profile(FanProfile::Aggressive);
+        assert_eq!(config.fan_profile, Some(FanProfile::Aggressive));
+        assert_eq!(config.threshold.min, 35.0);
+        assert_eq!(config.threshold.max, 55.0);
+        assert_eq!(config.state.min, 1);
+        assert_eq!(config.state.max, None);
+    }
+
+    #[test]
+    fn test_profiles_pass_check_config() {
+        for profile in [
+            FanProfile::Quiet,
+            FanProfile::Balanced,
+            FanProfile::Aggressive,
+        ] {
+            let mut config = Config::default();
+            config.apply_profile(profile);
+            assert!(
+                config.check_config(DEFAULT_MAX_STATE).is_ok(),
+                "{profile:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_default_config_has_no_warnings() {
+        assert!(Config::default().warnings(DEFAULT_MAX_STATE).is_empty());
+    }
+
+    #[test]
+    fn test_warnings_slots_collapsed_by_rounding() {
+        // Slots every 5°C from 45°C, temperatures rounded to 10°C skip 45 and 55
+        let config = Config {
+            decision_rounding: Some(10.0),
+            ..Config::default()
+        };
+        assert_eq!(config.unreachable_states(DEFAULT_MAX_STATE), vec![1, 3]);
+        let warnings = config.warnings(DEFAULT_MAX_STATE);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("Slot states 1, 3 are never reached"));
+        assert!(config.check_config(DEFAULT_MAX_STATE).is_ok());
+    }
+
+    #[test]
+    fn test_warnings_slots_above_max_threshold() {
+        let config = Config {
+            slots: Some(vec![(1, 50.0), (2, 60.0), (3, 70.0), (4, 80.0)]),
+            ..Config::default()
+        };
+        assert_eq!(config.unreachable_states(DEFAULT_MAX_STATE), vec![3, 4]);
+    }
+
+    #[test]
+    fn test_warnings_linear_curve_has_no_unreachable_states() {
+        let config = Config {
+            decision_rounding: Some(10.0),
+            curve_mode: CurveMode::Linear,
+            ..Config::default()
+        };
+        assert!(config.warnings(DEFAULT_MAX_STATE).is_empty());
+    }
+
+    #[test]
+    fn test_warnings_low_max_threshold() {
+        let config = Config {
+            threshold: Threshold {
+                max: 38.0,
+                min: 30.0,
+            },
+            ..Config::default()
+        };
+        let warnings = config.warnings(DEFAULT_MAX_STATE);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("MAX_THRESHOLD of 38.00°C is low"));
+    }
+
+    #[test]
+    fn test_warnings_critical_temp_below_max_threshold() {
+        let config = Config {
+            critical_temp: Some(60.0),
+            ..Config::default()
+        };
+        let warnings = config.warnings(DEFAULT_MAX_STATE);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("CRITICAL_TEMP of 60.00°C"));
+    }
+
+    #[test]
+    fn test_parse_curve_mode() {
+        assert_eq!("step".parse(), Ok(CurveMode::Step));
+        assert_eq!("Linear".parse(), Ok(CurveMode::Linear));
+        assert!("cubic".parse::<CurveMode>().is_err());
+        assert_eq!("low".parse(), Ok(BoundaryBias::Low));
+        assert_eq!("HIGH".parse(), Ok(BoundaryBias::High));
+        assert!("middle".parse::<BoundaryBias>().is_err());
+    }
+
+    #[test]
+    fn test_parse_dbus_bus() {
+        assert_eq!("system".parse(), Ok(DbusBus::System));
+        assert_eq!("Session".parse(), Ok(DbusBus::Session));
+        assert!("user".parse::<DbusBus>().is_err());
+    }
+
+    #[test]
+    fn test_parse_fan_backend() {
+        assert_eq!("cooling_device".parse(), Ok(FanBackend::CoolingDevice));
+        assert_eq!("PWMCHIP".parse(), Ok(FanBackend::PwmChip));
+        assert_eq!("hwmon".parse(), Ok(FanBackend::Hwmon));
+        assert!("gpio".parse::<FanBackend>().is_err());
+    }
+
+    #[test]
+    fn test_parse_log_format() {
+        assert_eq!("text".parse(), Ok(LogFormat::Text));
+        assert_eq!("JSON".parse(), Ok(LogFormat::Json));
+        assert!("logfmt".parse::<LogFormat>().is_err());
+    }
+
+    #[test]
+    fn test_parse_log_timestamps() {
+        assert_eq!("none".parse(), Ok(LogTimestamps::Off));
+        assert_eq!("Seconds".parse(), Ok(LogTimestamps::Seconds));
+        assert_eq!("millis".parse(), Ok(LogTimestamps::Millis));
+        assert!("nanos".parse::<LogTimestamps>().is_err());
+    }
+
+    #[test]
+    fn test_parse_log_target() {
+        assert_eq!("stderr".parse(), Ok(LogTarget::Stderr));
+        assert_eq!("Syslog".parse(), Ok(LogTarget::Syslog));
+        assert!("journald".parse::<LogTarget>().is_err());
+    }
+
+    #[test]
+    fn test_json_string_escapes() {
+        assert_eq!(json_string("45.00°C"), r#""45.00°C""#);
+        assert_eq!(
+            json_string("path \"a\\b\"\n\u{1b}"),
+            r#""path \"a\\b\"\n\u001b""#
+        );
+    }
+
+    #[test]
+    fn test_json_fields_keep_numbers_unquoted() {
+        let mut line = String::new();
+        let fields = [
+            ("temp", Value::from(45.5f64)),
+            ("state", Value::from(3u8)),
+            ("offset", Value::from(-2i32)),
+            ("low", Value::from(-10.5f64)),
+            ("device", Value::from("cur_state")),
+        ];
+        fields.visit(&mut JsonFields(&mut line)).unwrap();
+
+        assert_eq!(
+            line,
+            r#","temp":45.5,"state":3,"offset":-2,"low":-10.5,"device":"cur_state""#
+        );
+    }
+
+    #[test]
+    fn test_log_context() {
+        assert_eq!(LogContext::prefix(), "");
+        {
+            let _outer = LogContext {
+                device: "cooling_device0".to_owned(),
+                zone: Some("thermal_zone0".to_owned()),
+            }
+            .enter();
+            assert_eq!(LogContext::prefix(), "[cooling_device0] ");
+
+            let mut line = String::new();
+            LogContext::push_json(&mut line);
+            assert_eq!(
+                line,
+                r#","device":"cooling_device0","zone":"thermal_zone0""#
+            );
+
+            {
+                let _inner = LogContext {
+                    device: "pwm0".to_owned(),
+                    zone: None,
+                }
+                .enter();
+                assert_eq!(LogContext::prefix(), "[pwm0] ");
+            }
+            assert_eq!(LogContext::prefix(), "[cooling_device0] ");
+        }
+        assert_eq!(LogContext::current(), None);
+    }
+
+    #[test]
+    fn test_temp_ema_alpha_out_of_range_is_invalid() {
+        for temp_ema_alpha in [0.0, -0.5, 1.5] {
+            let config = Config {
+                temp_ema_alpha,
+                ..Config::default()
+            };
+
+            assert_invalid(&config, 5, "temperature smoothing factor must be in (0, 1]");
+        }
+    }
+
+    #[test]
+    fn test_parse_slots() {
+        assert_eq!(
+            Config::parse_slots("45:1,55:2, 65:4 ,70:5"),
+            Ok(vec![(1, 45.0), (2, 55.0), (4, 65.0), (5, 70.0)])
+        );
+        assert!(Config::parse_slots("45:1,55").is_err());
+        assert!(Config::parse_slots("45:1,hot:2").is_err());
+        assert!(Config::parse_slots("45:1,55:-2").is_err());
+        assert!(Config::parse_slots("").is_err());
+    }
+
+    #[test]
+    fn test_parse_temp_zones() {
+        assert_eq!(
+            Config::parse_temp_zones("soc-thermal:1.0, gpu-thermal:0.5,npu-thermal:2"),
+            Ok(vec![
+                ("soc-thermal".to_owned(), 1.0),
+                ("gpu-thermal".to_owned(), 0.5),
+                ("npu-thermal".to_owned(), 2.0)
+            ])
+        );
+        assert!(Config::parse_temp_zones("soc-thermal").is_err());
+        assert!(Config::parse_temp_zones("soc-thermal:high").is_err());
+        assert!(Config::parse_temp_zones("soc-thermal:0").is_err());
+        assert!(Config::parse_temp_zones("soc-thermal:-1").is_err());
+        assert!(Config::parse_temp_zones("soc-thermal:inf").is_err());
+    }
+
+    #[test]
+    fn test_sleep_time_bounds_is_invalid() {
+        let config = Config {
+            sleep_time: 5,
+            min_sleep_time: 10,
+            max_sleep_time: 30,
+            ..Config::default()
+        };
+        assert_invalid(&config, 5, "sleep times must satisfy min <= default <= max");
+
+        let config = Config {
+            sleep_time: 5,
+            min_sleep_time: 1,
+            max_sleep_time: 3,
+            ..Config::default()
+        };
+        assert_invalid(&config, 5, "sleep times must satisfy min <= default <= max");
+    }
+
+    #[test]
+    fn test_fan_off_temp_above_min_threshold_is_invalid() {
+        let config = Config {
+            fan_off_temp: Some(50.0),
+            ..Config::default()
+        };
+        assert_invalid(&config, 5, "fan off temperature can't be > min threshold");
+
+        let config = Config {
+            fan_off_temp: Some(40.0),
+            ..Config::default()
+        };
+        assert!(config.check_config(5).is_ok());
+    }
+
+    #[test]
+    fn test_reset_to_defaults_makes_config_valid() {
+        let mut config = Config {
+            threshold: Threshold {
+                max: 40.0,
+                min: 60.0,
+            },
+            state: State {
+                max: Some(9),
+                min: 2,
+            },
+            hysteresis: -1.0,
+            sleep_time: 10,
+            min_sleep_time: 20,
+            temp_ema_alpha: 0.0,
+            ..Config::default()
+        };
+        assert!(config.check_config(5).is_err());
+
+        config.reset_to_defaults();
+        assert!(config.check_config(5).is_ok());
+        assert_eq!(config.sleep_time, 10);
+    }
+
+    #[test]
+    fn test_from_args_overrides_config() {
+        let args = Args {
+            min_threshold: Some(40.0),
+            max_threshold: Some(75.0),
+            sleep_time: Some(10),
+            min_state: Some(1),
+            max_state: Some(4),
+            debug: false,
+            once: true,
+            print_config: false,
+            validate: false,
+            list_devices: false,
+        };
+        let base = Config {
+            hysteresis: 3.0,
+            ..Config::default()
+        };
+
+        let config = Config::from_args(&args, base);
+        assert_eq!(config.threshold.min, 40.0);
+        assert_eq!(config.threshold.max, 75.0);
+        assert_eq!(config.sleep_time, 10);
+        assert_eq!(config.min_sleep_time, 10);
+        assert_eq!(config.max_sleep_time, 10);
+        assert_eq!(config.state.min, 1);
+        assert_eq!(config.state.max, Some(4));
+        assert!(config.run_once);
+        assert_eq!(config.hysteresis, 3.0);
+    }
+
+    #[test]
+    fn test_no_color_disables_colors() {
+        assert!(Config::color_enabled(None, true));
+        assert!(Config::color_enabled(Some(OsStr::new("")), true));
+        assert!(!Config::color_enabled(Some(OsStr::new("1")), true));
+        assert!(!Config::color_enabled(None, false));
+
+        assert_eq!(
+            Config::get_level_color(Some(Level::Error), true),
+            "\x1b[31m"
+        );
+        assert_eq!(Config::get_level_color(Some(Level::Error), false), "");
+        assert_eq!(Config::get_level_color(None, false), "");
+    }
+
+    #[test]
+    fn test_describe_lists_resolved_settings() {
+        let config = Config {
+            critical_temp: Some(80.0),
+            slots: Some(vec![(1, 40.0), (2, 50.0)]),
+            ..Config::default()
+        };
+
+        let description = config.describe();
+        assert!(description.contains("min threshold      45.00°C\n"));
+        assert!(description.contains("critical temp      80.00°C\n"));
+        assert!(description.contains("max state          unset\n"));
+        assert!(description.contains("slots              1@40.00°C, 2@50.00°C\n"));
+        assert!(description.contains("sleep time         5s\n"));
+    }
+
+    #[test]
+    fn test_describe_deltas_follow_temp_unit() {
+        let config = Config {
+            temp_unit: TempUnit::Fahrenheit,
+            temp_rise_rate: Some(0.5),
+            temp_offset: -2.5,
+            ..Config::default()
+        };
+
+        let description = config.describe();
+        assert!(description.contains("min threshold      113.00°F\n"));
+        assert!(description.contains("hysteresis         3.60°F\n"));
+        assert!(description.contains("temp rise rate     0.90°F/s\n"));
+        assert!(description.contains("temp offset        -4.50°F\n"));
+        assert!(description.contains("quiet threshold offset 9.00°F\n"));
+    }
+
+    #[test]
+    fn test_from_args_clamps_sleep_time() {
+        let base = Config {
+            sleep_time: 0,
+            min_sleep_time: 0,
+            max_sleep_time: 100_000,
+            ..Config::default()
+        };
+
+        let config = Config::from_args(&Args::default(), base);
+        assert_eq!(config.sleep_time, 1);
+        assert_eq!(config.min_sleep_time, 1);
+        assert_eq!(config.max_sleep_time, 3600);
+
+        let args = Args {
+            sleep_time: Some(0),
+            ..Args::default()
+        };
+        let config = Config::from_args(&args, Config::default());
+        assert_eq!(config.sleep_time, 1);
+        assert_eq!(config.min_sleep_time, 1);
+        assert_eq!(config.max_sleep_time, 1);
+    }
+
+    #[test]
+    fn test_from_args_keeps_config_when_unset() {
+        let base = Config {
+            threshold: Threshold {
+                max: 80.0,
+                min: 50.0,
+            },
+            state: State {
+                max: Some(3),
+                min: 1,
+            },
+            sleep_time: 5,
+            min_sleep_time: 2,
+            max_sleep_time: 30,
+            ..Config::default()
+        };
+
+        let config = Config::from_args(&Args::default(), base);
+        assert_eq!(config.threshold.min, 50.0);
+        assert_eq!(config.threshold.max, 80.0);
+        assert_eq!(config.state.min, 1);
+        assert_eq!(config.state.max, Some(3));
+        assert_eq!(config.sleep_time, 5);
+        assert_eq!(config.min_sleep_time, 2);
+        assert_eq!(config.max_sleep_time, 30);
+        assert!(!config.run_once);
+    }
+
+    #[test]
+    fn test_args_parse() {
+        let args = Args::try_parse_from([
+            "cm3588-fan",
+            "--min-threshold",
+            "42.5",
+            "--max-state",
+            "4",
+            "--debug",
+        ])
+        .unwrap();
+
+        assert_eq!(args.min_threshold, Some(42.5));
+        assert_eq!(args.max_state, Some(4));
+        assert!(args.debug);
+        assert!(!args.once);
+        assert!(Args::try_parse_from(["cm3588-fan", "--min-state", "-1"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_temp_unit() {
+        assert_eq!("C".parse(), Ok(TempUnit::Celsius));
+        assert_eq!("f".parse(), Ok(TempUnit::Fahrenheit));
+        assert_eq!("Fahrenheit".parse(), Ok(TempUnit::Fahrenheit));
+        assert!("K".parse::<TempUnit>().is_err());
+    }
+
+    #[test]
+    fn test_temp_unit_conversions() {
+        let f = TempUnit::Fahrenheit;
+        assert_eq!(f.to_celsius(32.0), 0.0);
+        assert_eq!(f.to_celsius(212.0), 100.0);
+        assert_eq!(f.to_celsius(113.0), 45.0);
+        assert_eq!(f.to_celsius(149.0), 65.0);
+        assert_eq!(f.to_celsius(-40.0), -40.0);
+        assert_eq!(f.from_celsius(45.0), 113.0);
+        assert_eq!(f.from_celsius(-40.0), -40.0);
+        assert_eq!(f.delta_to_celsius(9.0), 5.0);
+        assert_eq!(f.delta_from_celsius(5.0), 9.0);
+        assert_eq!(f.display(65.0), "149.00°F");
+        assert_eq!(f.display_delta(2.0), "3.60°F");
+
+        let c = TempUnit::Celsius;
+        assert_eq!(c.to_celsius(45.0), 45.0);
+        assert_eq!(c.delta_to_celsius(2.0), 2.0);
+        assert_eq!(c.display(45.5), "45.50°C");
+        assert_eq!(c.display_with(45.46, 1), "45.5°C");
+        assert_eq!(c.display_with(45.46, 0), "45°C");
+        assert_eq!(c.display_delta(2.0), "2.00°C");
+    }
+
+    #[test]
+    fn test_temp_unit_display_sub_zero() {
+        let c = TempUnit::Celsius;
+        assert_eq!(c.display(-10.0), "-10.00°C");
+        assert_eq!(c.display_with(-0.5, 1), "-0.5°C");
+        assert_eq!(c.display(-0.001), "0.00°C");
+        assert_eq!(c.display_with(-0.4, 0), "0°C");
+
+        let f = TempUnit::Fahrenheit;
+        assert_eq!(f.display(-10.0), "14.00°F");
+        assert_eq!(f.display(-40.0), "-40.00°F");
+    }
+
+    #[test]
+    fn test_config_builder() {
+        let config = ConfigBuilder::new()
+            .min_threshold(40.0)
+            .max_threshold(70.0)
+            .min_state(1)
+            .max_state(4)
+            .sleep_time(10)
+            .build()
+            .unwrap();
+        assert_eq!(config.threshold.min, 40.0);
+        assert_eq!(config.threshold.max, 70.0);
+        assert_eq!(config.state.min, 1);
+        assert_eq!(config.state.max, Some(4));
+        assert_eq!(
+            (
+                config.min_sleep_time,
+                config.sleep_time,
+                config.max_sleep_time
+            ),
+            (10, 10, 10)
+        );
+
+        assert!(matches!(
+            ConfigBuilder::new()
+                .min_threshold(70.0)
+                .max_threshold(40.0)
+                .build(),
+            Err(ConfigError::ThresholdOrder { .. })
+        ));
+        assert!(matches!(
+            ConfigBuilder::new().min_state(3).max_state(3).build(),
+            Err(ConfigError::StateOrder { min: 3, max: 3 })
+        ));
+    }
+
+    #[test]
+    fn test_round_for_decision() {
+        let config = Config {
+            decision_rounding: Some(0.5),
+            ..Config::default()
+        };
+        assert_eq!(config.round_for_decision(54.8), 55.0);
+        assert_eq!(config.round_for_decision(54.7), 54.5);
+        assert_eq!(config.round_for_decision(55.1), 55.0);
+        assert_eq!(Config::default().round_for_decision(54.8), 54.8);
+    }
+
+    #[test]
+    fn test_from_args_thresholds_follow_temp_unit() {
+        let args = Args {
+            min_threshold: Some(113.0),
+            max_threshold: Some(158.0),
+            ..Args::default()
+        };
+        let base = Config {
+            temp_unit: TempUnit::Fahrenheit,
+            ..Config::default()
+        };
+
+        let config = Config::from_args(&args, base);
+        assert_eq!(config.threshold.min, 45.0);
+        assert_eq!(config.threshold.max, 70.0);
+    }
 }
@@ -0,0 +1,209 @@
+use std::{
+    sync::{
+        Arc, Mutex, PoisonError,
+        mpsc::{self, Receiver, SyncSender, TrySendError},
+    },
+    thread,
+};
+
+use log::{debug, error, info, warn};
+use zbus::{blocking::connection, fdo, interface, object_server::SignalEmitter};
+
+use crate::config::DbusBus;
+
+const NAME: &str = "io.github.martabal.Cm3588Fan";
+const PATH: &str = "/io/github/martabal/Cm3588Fan";
+
+#[derive(Debug, Default)]
+struct Shared {
+    temp: Option<f32>,
+    state: Option<u8>,
+    fixed_state: Option<u8>,
+}
+
+struct StateChanged {
+    fan: usize,
+    state: u8,
+    temp: f32,
+}
+
+struct Interface {
+    shared: Arc<Mutex<Shared>>,
+}
+
+#[interface(name = "io.github.martabal.Cm3588Fan")]
+impl Interface {
+    fn get_temperature(&self) -> fdo::Result<f64> {
+        let shared = self.shared.lock().unwrap_or_else(PoisonError::into_inner);
+        shared
+            .temp
+            .map(f64::from)
+            .ok_or_else(|| fdo::Error::Failed("No temperature read yet".to_owned()))
+    }
+
+    fn get_state(&self) -> fdo::Result<u8> {
+        let shared = self.shared.lock().unwrap_or_else(PoisonError::into_inner);
+        shared
+            .state
+            .ok_or_else(|| fdo::Error::Failed("No state set yet".to_owned()))
+    }
+
+    fn set_fixed_state(&self, state: u8) {
+        info!("Fixed state {state} requested over D-Bus");
+        self.shared
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .fixed_state = Some(state);
+    }
+
+    fn clear_fixed_state(&self) {
+        info!("Fixed state cleared over D-Bus");
+        self.shared
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .fixed_state = None;
+    }
+
+    #[zbus(signal)]
+    async fn state_changed(
+        emitter: &SignalEmitter<'_>,
+        fan: u32,
+        state: u8,
+        temp: f64,
+    ) -> zbus::Result<()>;
+}
+
+// Connects and serves from a background thread so an unavailable bus never
+// delays the control loop. Signals are dropped while the thread is busy.
+pub struct Service {
+    shared: Arc<Mutex<Shared>>,
+    sender: SyncSender<StateChanged>,
+}
+
+impl Service {
+    #[must_use]
+    pub fn new(bus: DbusBus) -> Self {
+        let shared = Arc::new(Mutex::new(Shared::default()));
+        let (sender, receiver) = mpsc::sync_channel(16);
+
+        let interface = Interface {
+            shared: Arc::clone(&shared),
+        };
+        thread::spawn(move || run(bus, interface, &receiver));
+
+        Self { shared, sender }
+    }
+
+    // State requested with `SetFixedState`, until `ClearFixedState`.
+    #[must_use]
+    pub fn fixed_state(&self) -> Option<u8> {
+        self.shared
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .fixed_state
+    }
+
+    pub fn update(&self, fan: usize, temp: f32, state: Option<u8>, previous_state: Option<u8>) {
+        if fan == 0 {
+            let mut shared = self.shared.lock().unwrap_or_else(PoisonError::into_inner);
+            shared.temp = Some(temp);
+            shared.state = state;
+        }
+
+        let Some(state) = state.filter(|&state| previous_state != Some(state)) else {
+            return;
+        };
+        match self.sender.try_send(StateChanged { fan, state, temp }) {
+            Ok(()) | Err(TrySendError::Disconnected(_)) => {}
+            Err(TrySendError::Full(_)) => debug!("D-Bus service busy, dropping signal"),
+        }
+    }
+}
+
+fn run(bus: DbusBus, interface: Interface, receiver: &Receiver<StateChanged>) {
+    let builder = match bus {
+        DbusBus::System => connection::Builder::system(),
+        DbusBus::Session => connection::Builder::session(),
+    };
+    let connection = match builder
+        .and_then(|builder| builder.name(NAME))
+        .and_then(|builder| builder.serve_at(PATH, interface))
+        .and_then(connection::Builder::build)
+    {
+        Ok(connection) => connection,
+        Err(err) => {
+            warn!("Can't register {NAME} on the {bus:?} bus: {err}");
+            return;
+        }
+    };
+    let interface = match connection.object_server().interface::<_, Interface>(PATH) {
+        Ok(interface) => interface,
+        Err(err) => {
+            error!("Can't find the D-Bus interface served at {PATH}: {err}");
+            return;
+        }
+    };
+    info!("Serving {NAME} on the {bus:?} bus");
+
+    for event in receiver {
+        let fan = u32::try_from(event.fan).unwrap_or(u32::MAX);
+        let signal = Interface::state_changed(
+            interface.signal_emitter(),
+            fan,
+            event.state,
+            f64::from(event.temp),
+        );
+        if let Err(err) = zbus::block_on(signal) {
+            error!("Can't emit StateChanged signal: {err}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixed_state_override() {
+        let (sender, _receiver) = mpsc::sync_channel(1);
+        let service = Service {
+            shared: Arc::new(Mutex::new(Shared::default())),
+            sender,
+        };
+        let interface = Interface {
+            shared: Arc::clone(&service.shared),
+        };
+
+        assert_eq!(service.fixed_state(), None);
+        interface.set_fixed_state(3);
+        assert_eq!(service.fixed_state(), Some(3));
+        interface.clear_fixed_state();
+        assert_eq!(service.fixed_state(), None);
+    }
+
+    #[test]
+    fn test_update_reports_first_fan_and_signals_changes() {
+        let (sender, receiver) = mpsc::sync_channel(4);
+        let service = Service {
+            shared: Arc::new(Mutex::new(Shared::default())),
+            sender,
+        };
+        let interface = Interface {
+            shared: Arc::clone(&service.shared),
+        };
+        assert!(interface.get_state().is_err());
+        assert!(interface.get_temperature().is_err());
+
+        service.update(0, 52.5, Some(2), None);
+        service.update(0, 53.0, Some(2), Some(2));
+        service.update(1, 60.0, Some(4), Some(3));
+        assert_eq!(interface.get_state().unwrap(), 2);
+        assert!((interface.get_temperature().unwrap() - 53.0).abs() < f64::EPSILON);
+
+        let signals: Vec<(usize, u8)> = receiver
+            .try_iter()
+            .map(|event| (event.fan, event.state))
+            .collect();
+        assert_eq!(signals, vec![(0, 2), (1, 4)]);
+    }
+}
@@ -0,0 +1,124 @@
+use std::{
+    fmt, io,
+    num::{ParseFloatError, ParseIntError},
+    str::Utf8Error,
+};
+
+#[derive(Debug)]
+pub enum FanError {
+    Io(io::Error),
+    NoThermalZone,
+    NoFanDevice,
+    ParseTemp(ParseFloatError),
+    ParseState(ParseIntError),
+    Utf8(Utf8Error),
+    Config(ConfigError),
+    InvalidCurve(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigError {
+    ThresholdOrder { min: f32, max: f32 },
+    FanOffTempAboveMin { fan_off_temp: f32, min: f32 },
+    StateOrder { min: u8, max: u8 },
+    MaxStateExceedsDevice { max: u8, device_max: u8 },
+    MinStateExceedsDevice { min: u8, device_max: u8 },
+    NegativeHysteresis(f32),
+    SleepTimeOrder { min: u64, default: u64, max: u64 },
+    EmaAlphaOutOfRange(f32),
+}
+
+impl From<io::Error> for FanError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<ParseFloatError> for FanError {
+    fn from(err: ParseFloatError) -> Self {
+        Self::ParseTemp(err)
+    }
+}
+
+impl From<ParseIntError> for FanError {
+    fn from(err: ParseIntError) -> Self {
+        Self::ParseState(err)
+    }
+}
+
+impl From<Utf8Error> for FanError {
+    fn from(err: Utf8Error) -> Self {
+        Self::Utf8(err)
+    }
+}
+
+impl From<ConfigError> for FanError {
+    fn from(err: ConfigError) -> Self {
+        Self::Config(err)
+    }
+}
+
+impl fmt::Display for FanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "IO error: {e}"),
+            Self::NoThermalZone => write!(f, "No valid thermal zone found"),
+            Self::NoFanDevice => write!(f, "No PWM fan device found"),
+            Self::ParseTemp(e) => write!(f, "Can't parse temperature: {e}"),
+            Self::ParseState(e) => write!(f, "Can't parse state: {e}"),
+            Self::Utf8(e) => write!(f, "UTF-8 error: {e}"),
+            Self::Config(e) => write!(f, "Invalid configuration: {e}"),
+            Self::InvalidCurve(e) => write!(f, "Invalid curve: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for FanError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::ParseTemp(e) => Some(e),
+            Self::ParseState(e) => Some(e),
+            Self::Utf8(e) => Some(e),
+            Self::Config(e) => Some(e),
+            Self::NoThermalZone | Self::NoFanDevice | Self::InvalidCurve(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ThresholdOrder { min, max } => {
+                write!(f, "min threshold can't be >= max threshold: {min} >= {max}")
+            }
+            Self::FanOffTempAboveMin { fan_off_temp, min } => write!(
+                f,
+                "fan off temperature can't be > min threshold: {fan_off_temp} > {min}"
+            ),
+            Self::StateOrder { min, max } => {
+                write!(f, "min state can't be >= max state: {min} >= {max}")
+            }
+            Self::MaxStateExceedsDevice { max, device_max } => write!(
+                f,
+                "Configured max state {max} exceeds device max state {device_max}"
+            ),
+            Self::MinStateExceedsDevice { min, device_max } => write!(
+                f,
+                "Configured min state {min} exceeds device max state {device_max}"
+            ),
+            Self::NegativeHysteresis(hysteresis) => {
+                write!(f, "hysteresis can't be negative: {hysteresis}")
+            }
+            Self::SleepTimeOrder { min, default, max } => write!(
+                f,
+                "sleep times must satisfy min <= default <= max: {min} <= {default} <= {max}"
+            ),
+            Self::EmaAlphaOutOfRange(alpha) => {
+                write!(f, "temperature smoothing factor must be in (0, 1]: {alpha}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
@@ -0,0 +1,184 @@
+use std::{
+    fs,
+    io::{self, Write},
+    os::unix::{
+        fs::FileTypeExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::{debug, info, warn};
+
+#[derive(Debug)]
+pub struct StateChange {
+    pub fan: usize,
+    pub previous_state: Option<u8>,
+    pub state: u8,
+    pub temp: f32,
+}
+
+impl StateChange {
+    #[must_use]
+    pub fn to_json(&self) -> String {
+        let previous_state = self
+            .previous_state
+            .map_or_else(|| "null".to_owned(), |state| state.to_string());
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+
+        format!(
+            r#"{{"event":"state_change","fan":{},"previous_state":{previous_state},"state":{},"temp":{:.2},"timestamp":{timestamp}}}"#,
+            self.fan, self.state, self.temp
+        )
+    }
+}
+
+// Sends one JSON line per event to every connected client. Clients are
+// non-blocking: one that can't take a whole event right away is dropped
+// instead of stalling the control loop.
+pub struct EventServer {
+    path: PathBuf,
+    listener: UnixListener,
+    clients: Vec<UnixStream>,
+}
+
+impl EventServer {
+    pub fn bind(path: &Path) -> io::Result<Self> {
+        if fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_socket()) {
+            fs::remove_file(path)?;
+        }
+
+        let listener = UnixListener::bind(path)?;
+        listener.set_nonblocking(true)?;
+        info!("Publishing events on {}", path.display());
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            listener,
+            clients: Vec::new(),
+        })
+    }
+
+    fn accept_clients(&mut self) {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    if let Err(err) = stream.set_nonblocking(true) {
+                        warn!("Can't set event client non-blocking: {err}");
+                        continue;
+                    }
+                    debug!("Event client connected");
+                    self.clients.push(stream);
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) => {
+                    warn!("Can't accept event client: {err}");
+                    break;
+                }
+            }
+        }
+    }
+
+    pub fn send(&mut self, event: &StateChange) {
+        self.accept_clients();
+
+        let mut line = event.to_json();
+        line.push('\n');
+        self.clients
+            .retain_mut(|client| match client.write(line.as_bytes()) {
+                Ok(n) if n == line.len() => true,
+                Ok(_) => {
+                    debug!("Event client too slow, disconnecting it");
+                    false
+                }
+                Err(err) => {
+                    debug!("Event client disconnected: {err}");
+                    false
+                }
+            });
+    }
+}
+
+impl Drop for EventServer {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader};
+
+    use super::*;
+
+    #[test]
+    fn test_state_change_to_json() {
+        let event = StateChange {
+            fan: 0,
+            previous_state: None,
+            state: 3,
+            temp: 55.004,
+        };
+
+        assert!(event.to_json().starts_with(
+            r#"{"event":"state_change","fan":0,"previous_state":null,"state":3,"temp":55.00,"timestamp":"#
+        ));
+    }
+
+    #[test]
+    fn test_clients_receive_events() {
+        let dir = std::env::temp_dir().join("test_events_clients");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("events.sock");
+
+        let mut server = EventServer::bind(&path).unwrap();
+        let first = UnixStream::connect(&path).unwrap();
+        let second = UnixStream::connect(&path).unwrap();
+
+        server.send(&StateChange {
+            fan: 1,
+            previous_state: Some(2),
+            state: 4,
+            temp: 61.5,
+        });
+
+        for client in [first, second] {
+            let mut line = String::new();
+            BufReader::new(client).read_line(&mut line).unwrap();
+            assert!(line.starts_with(
+                r#"{"event":"state_change","fan":1,"previous_state":2,"state":4,"temp":61.50,"#
+            ));
+            assert!(line.ends_with("}\n"));
+        }
+
+        drop(server);
+        assert!(!path.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_disconnected_client_is_dropped() {
+        let dir = std::env::temp_dir().join("test_events_disconnected");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("events.sock");
+
+        let mut server = EventServer::bind(&path).unwrap();
+        drop(UnixStream::connect(&path).unwrap());
+
+        let event = StateChange {
+            fan: 0,
+            previous_state: Some(1),
+            state: 2,
+            temp: 50.0,
+        };
+        server.send(&event);
+        server.send(&event);
+        assert!(server.clients.is_empty());
+
+        drop(server);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,138 +1,337 @@
-use crate::{THERMAL_DIR, config::Config, temp::MAX_LEVEL};
-use log::{error, info, trace};
+use crate::{
+    config::{Config, CurveMode, DEFAULT_MAX_STATE, FanBackend, TempUnit},
+    error::FanError,
+    policy::{self, MAX_LEVEL, Policy},
+    sysfs::SysfsAccess,
+};
+use log::{debug, error, info, trace, warn};
 use std::{
-    fmt, fs,
-    io::{self, Read},
-    num::ParseIntError,
+    fs, io,
     path::{Path, PathBuf},
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
 };
 
 const FILE_NAME_CUR_STATE: &str = "cur_state";
+const FILE_NAME_DUTY_CYCLE: &str = "duty_cycle";
 const DEVICE_NAME_COOLING: &str = "cooling_device";
 const DEVICE_TYPE_PWM_FAN: &str = "pwm-fan";
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    CoolingDevice,
+    PwmChip { period: u32 },
+    Hwmon { max_value: u32 },
+}
+
+// Progress of the stall detection, driven by the tachometer readings.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Stall {
+    #[default]
+    Spinning,
+    // Consecutive checks without RPM while the fan should be running
+    Suspected(u32),
+    // Max state written to get the fan going again
+    Recovering,
+    Stalled,
+}
+
 pub struct Fan {
     pub path: PathBuf,
     pub state: PathBuf,
+    pub backend: Backend,
+    pub tach: Option<PathBuf>,
     pub max_state: u8,
     pub temp_slots: [Option<(u8, f32)>; MAX_LEVEL],
     pub last_state: Option<u8>,
+    pub spun_up_at: Option<Instant>,
+    pub changed_at: Option<Instant>,
+    pub stall: Stall,
+    pub curve: Option<Vec<(f32, u8)>>,
+    pub inverted: bool,
+    pub sysfs: Arc<dyn SysfsAccess>,
 }
 
-#[derive(Debug)]
-pub enum Error {
-    Io(io::Error),
-    Parse(ParseIntError),
-}
-
-impl From<io::Error> for Error {
-    fn from(err: io::Error) -> Self {
-        Self::Io(err)
+impl Fan {
+    fn get_device_max_state(sysfs: &dyn SysfsAccess, device: &Path) -> Result<u8, FanError> {
+        let content = sysfs.read_to_string(&device.join("max_state"))?;
+        Ok(content.trim().parse::<u8>()?)
     }
-}
 
-impl From<ParseIntError> for Error {
-    fn from(err: ParseIntError) -> Self {
-        Self::Parse(err)
+    // Max state currently reported by the device, the PWM backends have a
+    // fixed one.
+    pub fn read_max_state(&self) -> Result<u8, FanError> {
+        match self.backend {
+            Backend::CoolingDevice => Self::get_device_max_state(self.sysfs.as_ref(), &self.path),
+            Backend::PwmChip { .. } | Backend::Hwmon { .. } => Ok(self.max_state),
+        }
     }
-}
 
-impl fmt::Display for Error {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Self::Io(e) => write!(f, "IO error: {e}"),
-            Self::Parse(e) => write!(f, "Parse error: {e}"),
+    pub fn new_fan_device(
+        state: PathBuf,
+        path: PathBuf,
+        config: &Config,
+        sysfs: Arc<dyn SysfsAccess>,
+    ) -> Result<Self, FanError> {
+        // Some drivers register the device before `max_state` is populated
+        let max_state = Self::get_device_max_state(sysfs.as_ref(), &path).or_else(|err| {
+            let Some(max_state) = config.default_max_state else {
+                return Err(err);
+            };
+            warn!(
+                "Can't read the max state of {}: {err}, assuming {max_state}",
+                path.display()
+            );
+            Ok(max_state)
+        })?;
+        config.check_config(max_state)?;
+
+        let temp_slots = Self::get_temperature_slots(config, max_state);
+        let mut fan = Self {
+            path,
+            state,
+            backend: Backend::CoolingDevice,
+            tach: config.tach_path.clone(),
+            max_state,
+            temp_slots,
+            last_state: None,
+            spun_up_at: None,
+            changed_at: None,
+            stall: Stall::Spinning,
+            curve: None,
+            inverted: config.invert_state,
+            sysfs,
+        };
+
+        // Seeded from the device so a first check that keeps the current
+        // state doesn't write it again.
+        match fan.read_state() {
+            Ok(state) => {
+                trace!("Current state {state}");
+                fan.last_state = Some(state);
+            }
+            Err(err) => debug!("Can't read current state: {err}"),
         }
+        Ok(fan)
     }
-}
 
-impl Fan {
-    fn get_device_max_state(device: &Path) -> Result<u8, Error> {
-        let path = device.join("max_state");
-
-        let mut file = fs::File::open(&path)?;
-        let mut buf = [0u8; 3]; // u8 max is "255" — 3 bytes
-        let n = file.read(&mut buf)?;
+    fn new_cooling_device(config: &Config, sysfs: Arc<dyn SysfsAccess>) -> Result<Self, FanError> {
+        if let Some(path) = &config.fan_device_path {
+            match Self::from_device_path(path, config, sysfs.clone()) {
+                Ok(fan) => return Ok(fan),
+                // The scan would find a device the config doesn't fit either
+                Err(err @ FanError::Config(_)) => return Err(err),
+                Err(err) => warn!(
+                    "Invalid fan device path {}: {err}, falling back to auto-detection",
+                    path.display()
+                ),
+            }
+        }
 
-        let s = std::str::from_utf8(&buf[..n])
-            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
-            .trim();
+        let (state, path) =
+            Self::get_fan_device(config, sysfs.as_ref()).ok_or(FanError::NoFanDevice)?;
+        Self::new_fan_device(state, path, config, sysfs)
+    }
 
-        s.parse::<u8>()
-            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e).into())
+    // Cooling device directory given by `FAN_DEVICE_PATH`, used whatever its
+    // type as long as both states can be read.
+    fn from_device_path(
+        path: &Path,
+        config: &Config,
+        sysfs: Arc<dyn SysfsAccess>,
+    ) -> Result<Self, FanError> {
+        let state = path.join(FILE_NAME_CUR_STATE);
+        sysfs.read_to_string(&state)?.trim().parse::<u8>()?;
+        Self::new_fan_device(state, path.to_path_buf(), config, sysfs)
     }
 
-    #[must_use]
-    pub fn new_fan_device(state: PathBuf, path: PathBuf, config: &Config) -> Self {
-        let max_state = Self::get_device_max_state(&path).unwrap();
-        config.check_config(max_state);
+    // The channel is only exported by `activate`, the chip has to exist.
+    pub fn new_pwm_chip(config: &Config, sysfs: Arc<dyn SysfsAccess>) -> Result<Self, FanError> {
+        let chip = Self::pwm_chip_dir(config);
+        if !sysfs.exists(&chip) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} not found", chip.display()),
+            )
+            .into());
+        }
+        let path = chip.join(format!("pwm{}", config.pwm.channel));
+        let max_state = config.state.max.unwrap_or(DEFAULT_MAX_STATE);
+        config.check_config(max_state)?;
 
         let temp_slots = Self::get_temperature_slots(config, max_state);
-        Self {
+        Ok(Self {
+            state: path.join(FILE_NAME_DUTY_CYCLE),
             path,
-            state,
+            backend: Backend::PwmChip {
+                period: config.pwm.period,
+            },
+            tach: config.tach_path.clone(),
             max_state,
             temp_slots,
             last_state: None,
-        }
+            spun_up_at: None,
+            changed_at: None,
+            stall: Stall::Spinning,
+            curve: None,
+            inverted: config.invert_state,
+            sysfs,
+        })
     }
 
-    fn calculate_slots(config: &Config, max_state: u8) -> [Option<(u8, f32)>; MAX_LEVEL] {
-        let num_slots: usize = (config.state.max.unwrap_or(max_state) - config.state.min).into();
+    // `pwmN` attribute of a hwmon device, switched to manual control by
+    // `activate`.
+    pub fn new_hwmon(config: &Config, sysfs: Arc<dyn SysfsAccess>) -> Result<Self, FanError> {
+        let state = config.pwm.sysfs_path.clone().ok_or(FanError::NoFanDevice)?;
+        let max_state = config.state.max.unwrap_or(DEFAULT_MAX_STATE);
+        config.check_config(max_state)?;
 
-        let step = if num_slots <= 1 {
-            0.0
-        } else {
-            (config.threshold.max - config.threshold.min) / (num_slots - 1) as f32
-        };
+        let temp_slots = Self::get_temperature_slots(config, max_state);
+        Ok(Self {
+            path: state.clone(),
+            state,
+            backend: Backend::Hwmon {
+                max_value: config.pwm.max_value,
+            },
+            tach: config.tach_path.clone(),
+            max_state,
+            temp_slots,
+            last_state: None,
+            spun_up_at: None,
+            changed_at: None,
+            stall: Stall::Spinning,
+            curve: None,
+            inverted: config.invert_state,
+            sysfs,
+        })
+    }
 
-        trace!(
-            "Calculate slots, min_state: {}, num_slots: {}, step: {}",
-            config.state.min, num_slots, step
-        );
+    fn pwm_chip_dir(config: &Config) -> PathBuf {
+        config.pwm_dir().join(format!("pwmchip{}", config.pwm.chip))
+    }
 
-        let mut results = [None; MAX_LEVEL];
+    // `pwmN_enable` of a hwmon `pwmN` attribute, not every driver has one.
+    fn hwmon_enable_path(state: &Path) -> PathBuf {
+        let mut enable = state.as_os_str().to_owned();
+        enable.push("_enable");
+        PathBuf::from(enable)
+    }
 
-        for (i, result) in results
-            .iter_mut()
-            .enumerate()
-            .take(num_slots.min(MAX_LEVEL))
-        {
-            let state = config
-                .state
-                .min
-                .saturating_add(u8::try_from(i).unwrap() + 1);
+    // Exports and enables the PWM channel, or switches the hwmon attribute
+    // to manual control. A cooling device is ready as soon as it's found.
+    fn activate(&self, config: &Config) -> Result<(), FanError> {
+        match self.backend {
+            Backend::CoolingDevice => {}
+            Backend::PwmChip { period } => {
+                Self::setup_pwm_channel(
+                    self.sysfs.as_ref(),
+                    &Self::pwm_chip_dir(config),
+                    config.pwm.channel,
+                    period,
+                )?;
+            }
+            Backend::Hwmon { .. } => {
+                let enable = Self::hwmon_enable_path(&self.state);
+                if self.sysfs.exists(&enable) {
+                    self.sysfs.write(&enable, "1")?;
+                }
+            }
+        }
+        Ok(())
+    }
 
-            let value = if num_slots <= 1 {
-                config.threshold.min
-            } else {
-                (i as f32).mul_add(step, config.threshold.min)
-            };
+    // Attribute that has to be writable to control the fan, the chip's
+    // `export` while the PWM channel isn't exported yet.
+    #[must_use]
+    pub fn write_access_path(&self) -> PathBuf {
+        match self.backend {
+            Backend::PwmChip { .. } if !self.sysfs.exists(&self.path) => {
+                self.path.with_file_name("export")
+            }
+            _ => self.state.clone(),
+        }
+    }
 
-            *result = Some((state, value));
+    fn setup_pwm_channel(
+        sysfs: &dyn SysfsAccess,
+        chip: &Path,
+        channel: u32,
+        period: u32,
+    ) -> Result<PathBuf, FanError> {
+        let path = chip.join(format!("pwm{channel}"));
+        if !sysfs.exists(&path) {
+            trace!("Exporting PWM channel {channel} of {}", chip.display());
+            sysfs.write(&chip.join("export"), &channel.to_string())?;
         }
 
-        results
+        let period_path = path.join("period");
+        if sysfs.read_to_string(&period_path)?.trim() != period.to_string() {
+            sysfs.write(&period_path, &period.to_string())?;
+        }
+        sysfs.write(&path.join("enable"), "1")?;
+
+        Ok(path)
+    }
+
+    pub(crate) fn calculate_slots(
+        config: &Config,
+        max_state: u8,
+    ) -> [Option<(u8, f32)>; MAX_LEVEL] {
+        policy::calculate_slots(
+            config.threshold.min,
+            config.threshold.max,
+            config.state.min,
+            config.state.max.unwrap_or(max_state),
+        )
     }
 
     #[must_use]
-    pub fn get_fan_device() -> Option<(PathBuf, PathBuf)> {
-        fs::read_dir(THERMAL_DIR).ok()?.flatten().find_map(|entry| {
-            let entry_path = entry.path();
-            if !entry_path
-                .file_name()?
-                .to_str()?
-                .starts_with(DEVICE_NAME_COOLING)
-            {
+    pub fn get_fan_device(config: &Config, sysfs: &dyn SysfsAccess) -> Option<(PathBuf, PathBuf)> {
+        Self::find_fan_device(
+            sysfs,
+            &config.thermal_dir(),
+            config.cooling_device,
+            config.fan_device_name.as_deref(),
+        )
+    }
+
+    // An explicit index wins, otherwise the first `pwm-fan` cooling device,
+    // restricted to the one whose directory or `name` attribute matches
+    // `device_name` when set.
+    fn find_fan_device(
+        sysfs: &dyn SysfsAccess,
+        dir: &Path,
+        cooling_device: Option<u32>,
+        device_name: Option<&str>,
+    ) -> Option<(PathBuf, PathBuf)> {
+        if let Some(index) = cooling_device {
+            let path = dir.join(format!("{DEVICE_NAME_COOLING}{index}"));
+            let state = path.join(FILE_NAME_CUR_STATE);
+            return sysfs.exists(&state).then_some((state, path));
+        }
+
+        let mut entries = sysfs.read_dir(dir).ok()?;
+        entries.sort();
+
+        entries.into_iter().find_map(|entry_path| {
+            let file_name = entry_path.file_name()?.to_str()?;
+            if !file_name.starts_with(DEVICE_NAME_COOLING) {
                 return None;
             }
 
-            let mut file = fs::File::open(entry_path.join("type")).ok()?;
-            let mut buf = [0u8; 32]; // enough for any thermal device type name
-            let n = file.read(&mut buf).ok()?;
-            let content = std::str::from_utf8(&buf[..n]).ok()?.trim();
+            if let Some(name) = device_name
+                && file_name != name
+                && sysfs
+                    .read_to_string(&entry_path.join("name"))
+                    .ok()
+                    .is_none_or(|content| content.trim() != name)
+            {
+                return None;
+            }
 
-            if content == DEVICE_TYPE_PWM_FAN {
+            let content = sysfs.read_to_string(&entry_path.join("type")).ok()?;
+            if content.trim() == DEVICE_TYPE_PWM_FAN {
                 Some((entry_path.join(FILE_NAME_CUR_STATE), entry_path))
             } else {
                 None
@@ -140,6 +339,43 @@ impl Fan {
         })
     }
 
+    // Every cooling device with its type and max state, not only the fans.
+    #[must_use]
+    pub fn list_cooling_devices(
+        sysfs: &dyn SysfsAccess,
+        dir: &Path,
+    ) -> Vec<(PathBuf, Option<String>, Option<u8>)> {
+        let Ok(entries) = sysfs.read_dir(dir) else {
+            return Vec::new();
+        };
+        let mut devices: Vec<PathBuf> = entries
+            .into_iter()
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|s| s.to_str())
+                    .is_some_and(|s| s.starts_with(DEVICE_NAME_COOLING))
+            })
+            .collect();
+        devices.sort();
+
+        devices
+            .into_iter()
+            .map(|device| {
+                let device_type = sysfs
+                    .read_to_string(&device.join("type"))
+                    .ok()
+                    .map(|content| content.trim().to_owned());
+                let max_state = Self::get_device_max_state(sysfs, &device).ok();
+                (device, device_type, max_state)
+            })
+            .collect()
+    }
+
+    // After the thresholds changed at runtime
+    pub fn refresh_slots(&mut self, config: &Config) {
+        self.temp_slots = Self::get_temperature_slots(config, self.max_state);
+    }
+
     fn get_temperature_slots(config: &Config, max_state: u8) -> [Option<(u8, f32)>; MAX_LEVEL] {
         let max_state = config.state.max.unwrap_or(max_state);
         trace!("max_state: {max_state}");
@@ -147,58 +383,403 @@ impl Fan {
             error!("max_state could not be determined");
             return [None; MAX_LEVEL];
         }
-        let slots = Self::calculate_slots(config, max_state);
+        let slots = config.slots.as_ref().map_or_else(
+            || Self::calculate_slots(config, max_state),
+            |custom| {
+                Self::custom_slots(custom, config.state.min, max_state).unwrap_or_else(|err| {
+                    error!("Invalid custom slots: {err}, using computed slots");
+                    Self::calculate_slots(config, max_state)
+                })
+            },
+        );
         trace!("Slots: {slots:?}");
         slots
     }
 
-    #[must_use]
-    pub fn new(config: &Config) -> Option<Self> {
-        if let Some((state, path)) = Self::get_fan_device() {
-            info!("Fan device: {}", path.display());
-            Some(Self::new_fan_device(state, path, config))
+    fn custom_slots(
+        custom: &[(u8, f32)],
+        min_state: u8,
+        max_state: u8,
+    ) -> Result<[Option<(u8, f32)>; MAX_LEVEL], String> {
+        if custom.len() > MAX_LEVEL {
+            return Err(format!(
+                "{} slots configured, at most {MAX_LEVEL} are supported",
+                custom.len()
+            ));
+        }
+
+        if let Some((state, _)) = custom
+            .iter()
+            .find(|(state, _)| !(min_state..=max_state).contains(state))
+        {
+            return Err(format!(
+                "state {state} is outside of [{min_state}, {max_state}]"
+            ));
+        }
+
+        if let Some(pair) = custom.windows(2).find(|pair| pair[0].1 >= pair[1].1) {
+            return Err(format!(
+                "temperatures must be ascending: {} >= {}",
+                pair[0].1, pair[1].1
+            ));
+        }
+
+        let mut slots = [None; MAX_LEVEL];
+        for (slot, value) in slots.iter_mut().zip(custom) {
+            *slot = Some(*value);
+        }
+        Ok(slots)
+    }
+
+    pub fn new(config: &Config, sysfs: Arc<dyn SysfsAccess>) -> Result<Self, FanError> {
+        let fan = Self::probe(config, sysfs)?;
+        fan.activate(config)?;
+        Ok(fan)
+    }
+
+    // Finds the device like `new` without exporting, enabling or writing
+    // anything, for the reports that must leave the hardware alone.
+    pub fn probe(config: &Config, sysfs: Arc<dyn SysfsAccess>) -> Result<Self, FanError> {
+        let mut fan = match config.fan_backend {
+            FanBackend::CoolingDevice => Self::new_cooling_device(config, sysfs)?,
+            FanBackend::PwmChip => Self::new_pwm_chip(config, sysfs)?,
+            FanBackend::Hwmon => Self::new_hwmon(config, sysfs)?,
+        };
+        info!("Fan device: {}", fan.path.display());
+
+        if let Some(path) = &config.curve_file {
+            match fan.load_curve_from_csv(path, config.temp_unit) {
+                Ok(()) => info!("Fan curve: {}", path.display()),
+                Err(err) => error!("{err}, using slots"),
+            }
+        }
+        Ok(fan)
+    }
+
+    // Reads `temp,state` points, one per line, after an optional header on
+    // the first line. Empty lines and `#` comments are skipped.
+    pub fn load_curve_from_csv(
+        &mut self,
+        path: &Path,
+        temp_unit: TempUnit,
+    ) -> Result<(), FanError> {
+        let content = fs::read_to_string(path)?;
+        let invalid = |msg: String| FanError::InvalidCurve(format!("{}: {msg}", path.display()));
+
+        let mut curve: Vec<(f32, u8)> = Vec::new();
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (temp, state) = line
+                .split_once(',')
+                .ok_or_else(|| invalid(format!("line {} isn't `temp,state`", index + 1)))?;
+            let Ok(temp) = temp.trim().parse::<f32>() else {
+                if curve.is_empty() && index == 0 {
+                    continue;
+                }
+                return Err(invalid(format!(
+                    "invalid temperature on line {}",
+                    index + 1
+                )));
+            };
+            let state = state
+                .trim()
+                .parse::<u8>()
+                .map_err(|err| invalid(format!("invalid state on line {}: {err}", index + 1)))?;
+            if state > self.max_state {
+                return Err(invalid(format!(
+                    "state {state} on line {} exceeds max state {}",
+                    index + 1,
+                    self.max_state
+                )));
+            }
+
+            let temp = temp_unit.to_celsius(temp);
+            if let Some(&(previous, _)) = curve.last()
+                && previous >= temp
+            {
+                return Err(invalid(format!(
+                    "temperatures must be ascending, line {} isn't",
+                    index + 1
+                )));
+            }
+            curve.push((temp, state));
+        }
+
+        if curve.is_empty() {
+            return Err(invalid("no points".to_owned()));
+        }
+        trace!("Curve: {curve:?}");
+        self.curve = Some(curve);
+        Ok(())
+    }
+
+    // Higher states mean lower speeds on inverted devices, the mapping is
+    // its own inverse.
+    const fn raw_state(&self, state: u8) -> u8 {
+        if self.inverted {
+            self.max_state.saturating_sub(state)
         } else {
-            error!("No PWM fan device found");
-            None
+            state
+        }
+    }
+
+    pub fn read_state(&self) -> Result<u8, FanError> {
+        let state = match self.backend {
+            Backend::CoolingDevice => self
+                .sysfs
+                .read_to_string(&self.state)?
+                .trim()
+                .parse::<u8>()?,
+            Backend::PwmChip { period: max_duty }
+            | Backend::Hwmon {
+                max_value: max_duty,
+            } => policy::duty_to_state(self.read_duty()?, max_duty, self.max_state),
+        };
+        Ok(self.raw_state(state))
+    }
+
+    pub fn write_state(&self, state: u8) -> io::Result<()> {
+        let state = self.raw_state(state);
+        match self.backend {
+            Backend::CoolingDevice => self.sysfs.write(&self.state, &state.to_string()),
+            Backend::PwmChip { period: max_duty }
+            | Backend::Hwmon {
+                max_value: max_duty,
+            } => self.write_duty(policy::state_to_duty(state, max_duty, self.max_state)),
+        }
+    }
+
+    // Raw value of the PWM backends, nanoseconds for `pwmchip` and 0 to
+    // `PWM_MAX_VALUE` for `hwmon`.
+    pub fn read_duty(&self) -> Result<u32, FanError> {
+        let content = self.sysfs.read_to_string(&self.state)?;
+        Ok(content.trim().parse::<u32>()?)
+    }
+
+    pub fn write_duty(&self, duty: u32) -> io::Result<()> {
+        self.sysfs.write(&self.state, &duty.to_string())
+    }
+
+    #[must_use]
+    pub const fn needs_kick(current_speed: u8, desired_speed: u8, config: &Config) -> bool {
+        config.spinup_kick && current_speed <= config.state.min && desired_speed > current_speed
+    }
+
+    pub fn kick(&self, duration: Duration) -> io::Result<()> {
+        self.write_state(self.max_state)?;
+        thread::sleep(duration);
+        Ok(())
+    }
+
+    #[must_use]
+    pub fn read_rpm(&self) -> Option<u32> {
+        let tach = self.tach.as_ref()?;
+        match self.sysfs.read_to_string(tach) {
+            Ok(content) => match content.trim().parse::<u32>() {
+                Ok(rpm) => Some(rpm),
+                Err(err) => {
+                    error!("Can't parse fan RPM from {}: {err}", tach.display());
+                    None
+                }
+            },
+            Err(err) => {
+                error!("Can't read fan RPM from {}: {err}", tach.display());
+                None
+            }
+        }
+    }
+
+    #[must_use]
+    pub fn is_critical(current_temp: f32, config: &Config) -> bool {
+        config
+            .critical_temp
+            .is_some_and(|critical_temp| current_temp >= critical_temp)
+    }
+
+    // Decision inputs of this fan, its slots and curve with the config.
+    #[must_use]
+    pub fn policy(&self, config: &Config) -> Policy<'_> {
+        Policy {
+            min_temp: config.threshold.min,
+            max_temp: config.threshold.max,
+            min_state: config.state.min,
+            max_state: config.state.max.unwrap_or(self.max_state),
+            device_max_state: self.max_state,
+            fan_off_temp: config.fan_off_temp,
+            critical_temp: config.critical_temp,
+            fixed_state: config.fixed_state,
+            hysteresis: config.effective_hysteresis(self.max_state),
+            curve_mode: config.curve_mode,
+            boundary_bias: config.boundary_bias,
+            slots: &self.temp_slots,
+            curve: self.curve.as_deref(),
         }
     }
 
     #[must_use]
     pub fn choose_speed(&self, current_temp: f32, config: &Config) -> u8 {
-        match current_temp {
-            t if t < config.threshold.min => {
-                trace!("Min state desired");
-                config.state.min
+        if let Some(critical_temp) = config.critical_temp
+            && current_temp >= critical_temp
+        {
+            warn!(
+                "Critical temperature reached ({} >= {}), forcing max state {}",
+                config.temp_unit.display(current_temp),
+                config.temp_unit.display(critical_temp),
+                self.max_state
+            );
+        }
+        self.policy(config)
+            .choose_speed(current_temp, self.last_state)
+    }
+
+    // `(low, high, state)` ranges covering every temperature, the state is
+    // chosen from `low` included to `high` excluded while heating up and
+    // kept until `hysteresis` below `low` while cooling down.
+    #[must_use]
+    pub fn curve_table(&self, config: &Config) -> Vec<(f32, f32, u8)> {
+        let mut breakpoints = vec![config.threshold.min, config.threshold.max];
+        breakpoints.extend(config.critical_temp);
+        breakpoints.extend(config.fan_off_temp);
+        breakpoints.extend(self.temp_slots.iter().flatten().map(|&(_, temp)| temp));
+        if let Some(curve) = &self.curve {
+            for pair in curve.windows(2) {
+                let ((low_temp, low_state), (high_temp, high_state)) = (pair[0], pair[1]);
+                // Rounding switches state halfway between two states
+                let steps = high_state.abs_diff(low_state);
+                for step in 0..steps {
+                    let ratio = (f32::from(step) + 0.5) / f32::from(steps);
+                    breakpoints.push(ratio.mul_add(high_temp - low_temp, low_temp));
+                }
             }
-            t if t <= config.threshold.max => {
-                trace!("Desired state in slots");
-                self.temp_slots
-                    .iter()
-                    .flatten()
-                    .rev()
-                    .find(|(_, temp)| *temp <= current_temp)
-                    .map_or(config.state.min, |(state, _)| *state)
+            breakpoints.extend(curve.iter().map(|&(temp, _)| temp));
+        } else if config.curve_mode == CurveMode::Linear {
+            let span = config
+                .state
+                .max
+                .unwrap_or(self.max_state)
+                .saturating_sub(config.state.min);
+            let range = config.threshold.max - config.threshold.min;
+            for step in 0..span {
+                let ratio = (f32::from(step) + 0.5) / f32::from(span);
+                breakpoints.push(ratio.mul_add(range, config.threshold.min));
             }
-            _ => {
-                trace!("Max state desired {}", self.max_state);
-                config.state.max.unwrap_or(self.max_state)
+        }
+        // Some comparisons include their bound, so both sides are probed
+        let next: Vec<f32> = breakpoints.iter().map(|temp| temp.next_up()).collect();
+        breakpoints.extend(next);
+        breakpoints.sort_by(f32::total_cmp);
+        breakpoints.dedup();
+
+        let policy = self.policy(config);
+        let state_at = |temp: f32| {
+            policy
+                .pinned_state(temp)
+                .unwrap_or_else(|| policy.speed_for_temp(temp))
+        };
+        let mut table: Vec<(f32, f32, u8)> = Vec::new();
+        let mut low = f32::NEG_INFINITY;
+        let mut state = state_at(breakpoints.first().map_or(0.0, |temp| temp.next_down()));
+        for &temp in &breakpoints {
+            let next_state = state_at(temp);
+            if next_state != state {
+                table.push((low, temp, state));
+                (low, state) = (temp, next_state);
+            }
+        }
+        table.push((low, f32::INFINITY, state));
+        table
+    }
+
+    pub fn record_state(&mut self, state: u8, min_state: u8) {
+        let now = Instant::now();
+        let was_running = self.last_state.is_some_and(|last| last > min_state);
+        if state <= min_state {
+            self.spun_up_at = None;
+        } else if !was_running {
+            self.spun_up_at = Some(now);
+        }
+        if self.last_state != Some(state) {
+            self.changed_at = Some(now);
+        }
+        self.last_state = Some(state);
+    }
+
+    // Keeps a running fan at the lowest running state until it has been
+    // spinning for `MIN_ON_TIME`.
+    #[must_use]
+    pub fn hold_min_on_time(&self, desired_speed: u8, config: &Config) -> u8 {
+        let min_state = config.state.min;
+        if desired_speed > min_state || config.min_on_time == 0 {
+            return desired_speed;
+        }
+
+        match self.spun_up_at {
+            Some(spun_up_at) if spun_up_at.elapsed() < Duration::from_secs(config.min_on_time) => {
+                trace!("Holding fan on, minimum on-time not elapsed");
+                min_state.saturating_add(1).min(self.max_state)
             }
+            _ => desired_speed,
+        }
+    }
+
+    // Keeps the fan at its last state until it has been there for
+    // `DWELL_TIME`, except to speed up once past the max threshold.
+    #[must_use]
+    pub fn hold_dwell(&self, desired_speed: u8, current_temp: f32, config: &Config) -> u8 {
+        let (Some(last_state), Some(changed_at)) = (self.last_state, self.changed_at) else {
+            return desired_speed;
+        };
+        if config.dwell_time == 0
+            || desired_speed == last_state
+            || (desired_speed > last_state && current_temp >= config.threshold.max)
+        {
+            return desired_speed;
+        }
+
+        if changed_at.elapsed() < Duration::from_secs(config.dwell_time) {
+            trace!("Holding state {last_state}, dwell time not elapsed");
+            last_state
+        } else {
+            desired_speed
         }
     }
+
+    // Limits how many states the fan moves per check when `RAMP_STEP` is set.
+    #[must_use]
+    pub fn ramp(&self, desired_speed: u8, config: &Config) -> u8 {
+        let (Some(step), Some(last_state)) = (config.ramp_step, self.last_state) else {
+            return desired_speed;
+        };
+
+        let speed = if desired_speed > last_state {
+            desired_speed.min(last_state.saturating_add(step))
+        } else {
+            desired_speed.max(last_state.saturating_sub(step))
+        };
+        if speed != desired_speed {
+            trace!("Ramping to state {speed} toward {desired_speed}");
+        }
+        speed
+    }
 }
 
 #[cfg(test)]
 mod tests {
 
-    use crate::config::{DEFAULT_MAX_STATE, DEFAULT_SLEEP_TIME, State, Threshold};
+    use crate::config::{DEFAULT_SLEEP_TIME, Pwm, State, Threshold};
 
     use super::*;
+    use crate::sysfs::RealSysfs;
 
     fn rest_is_none(slots: [Option<(u8, f32)>; MAX_LEVEL], index: usize) {
         assert!(
             slots
                 .get(index..)
-                .map_or(true, |rest| rest.iter().all(|x| x.is_none()))
+                .is_none_or(|rest| rest.iter().all(Option::is_none))
         );
     }
 
@@ -213,12 +794,7 @@ mod tests {
         let max_state = Some(2);
         let min_state = 3;
 
-        let mut panic_occurred = false;
-        if let Some(max) = max_state
-            && min_state >= max
-        {
-            panic_occurred = true;
-        }
+        let panic_occurred = max_state.is_some_and(|max| min_state >= max);
 
         assert!(panic_occurred);
     }
@@ -237,6 +813,7 @@ mod tests {
                 max: Some(DEFAULT_MAX_STATE),
                 min: 0,
             },
+            ..Config::default()
         };
 
         let slots = Fan::calculate_slots(&fan, DEFAULT_MAX_STATE);
@@ -266,6 +843,7 @@ mod tests {
                 max: Some(DEFAULT_MAX_STATE),
                 min: min_state,
             },
+            ..Config::default()
         };
 
         let slots = Fan::calculate_slots(&fan, DEFAULT_MAX_STATE);
@@ -289,6 +867,7 @@ mod tests {
                 max: Some(DEFAULT_MAX_STATE),
                 min: min_state,
             },
+            ..Config::default()
         };
 
         let slots = Fan::calculate_slots(&fan, DEFAULT_MAX_STATE);
@@ -308,6 +887,7 @@ mod tests {
                 max: Some(DEFAULT_MAX_STATE),
                 min: 0,
             },
+            ..Config::default()
         };
 
         let current_temp = 60.0;
@@ -317,9 +897,17 @@ mod tests {
         let fan = Fan {
             temp_slots: slots,
             max_state: DEFAULT_MAX_STATE,
+            backend: Backend::CoolingDevice,
+            tach: None,
             path: "cooling_device".into(),
             state: "cooling_device/cur_state".into(),
             last_state: None,
+            spun_up_at: None,
+            changed_at: None,
+            stall: Stall::Spinning,
+            curve: None,
+            inverted: false,
+            sysfs: Arc::new(RealSysfs),
         };
         let desired_state = fan.choose_speed(current_temp, &config);
 
@@ -337,6 +925,7 @@ mod tests {
                 max: Some(DEFAULT_MAX_STATE),
             },
             sleep_time: DEFAULT_SLEEP_TIME,
+            ..Config::default()
         }
     }
 
@@ -353,9 +942,17 @@ mod tests {
         Fan {
             temp_slots,
             max_state: DEFAULT_MAX_STATE,
+            backend: Backend::CoolingDevice,
+            tach: None,
             path: "cooling_device".into(),
             state: "cooling_device/cur_state".into(),
             last_state: None,
+            spun_up_at: None,
+            changed_at: None,
+            stall: Stall::Spinning,
+            curve: None,
+            inverted: false,
+            sysfs: Arc::new(RealSysfs),
         }
     }
 
@@ -416,14 +1013,23 @@ mod tests {
                 max: Some(2),
             },
             sleep_time: DEFAULT_SLEEP_TIME,
+            ..Config::default()
         };
 
         let fan = Fan {
             temp_slots: [None; MAX_LEVEL],
             max_state: DEFAULT_MAX_STATE,
+            backend: Backend::CoolingDevice,
+            tach: None,
             path: "cooling_device".into(),
             state: "cooling_device/cur_state".into(),
             last_state: None,
+            spun_up_at: None,
+            changed_at: None,
+            stall: Stall::Spinning,
+            curve: None,
+            inverted: false,
+            sysfs: Arc::new(RealSysfs),
         };
 
         let result = fan.choose_speed(80.0, &config);
@@ -478,6 +1084,7 @@ mod tests {
                 min: 0,
             },
             sleep_time: DEFAULT_SLEEP_TIME,
+            ..Config::default()
         };
 
         let slots = Fan::calculate_slots(&config, 5);
@@ -499,6 +1106,7 @@ mod tests {
                 min: 2,
             },
             sleep_time: DEFAULT_SLEEP_TIME,
+            ..Config::default()
         };
 
         let slots = Fan::calculate_slots(&config, 5);
@@ -518,6 +1126,7 @@ mod tests {
             },
             state: State { min: 0, max: None },
             sleep_time: DEFAULT_SLEEP_TIME,
+            ..Config::default()
         };
 
         let fan = Fan {
@@ -530,9 +1139,17 @@ mod tests {
                 None,
             ],
             max_state: DEFAULT_MAX_STATE,
+            backend: Backend::CoolingDevice,
+            tach: None,
             path: "cooling_device".into(),
             state: "cooling_device/cur_state".into(),
             last_state: None,
+            spun_up_at: None,
+            changed_at: None,
+            stall: Stall::Spinning,
+            curve: None,
+            inverted: false,
+            sysfs: Arc::new(RealSysfs),
         };
 
         let result = fan.choose_speed(80.0, &config);
@@ -557,6 +1174,23 @@ mod tests {
         assert_eq!(result, config.state.min);
     }
 
+    #[test]
+    fn test_choose_speed_with_negative_temp_and_min_state() {
+        let config = Config {
+            state: State {
+                min: 1,
+                max: Some(DEFAULT_MAX_STATE),
+            },
+            ..setup_test_config()
+        };
+        let mut fan = setup_test_fan();
+
+        assert_eq!(fan.choose_speed(-10.0, &config), 1);
+        // Cooling down below 0 from a running state
+        fan.last_state = Some(3);
+        assert_eq!(fan.choose_speed(-10.0, &config), 1);
+    }
+
     #[test]
     fn test_choose_speed_boundary_between_slots() {
         let config = Config {
@@ -569,6 +1203,7 @@ mod tests {
                 max: Some(3),
             },
             sleep_time: DEFAULT_SLEEP_TIME,
+            ..Config::default()
         };
 
         let fan = Fan {
@@ -581,9 +1216,17 @@ mod tests {
                 None,
             ],
             max_state: DEFAULT_MAX_STATE,
+            backend: Backend::CoolingDevice,
+            tach: None,
             path: "cooling_device".into(),
             state: "cooling_device/cur_state".into(),
             last_state: None,
+            spun_up_at: None,
+            changed_at: None,
+            stall: Stall::Spinning,
+            curve: None,
+            inverted: false,
+            sysfs: Arc::new(RealSysfs),
         };
 
         let result = fan.choose_speed(49.0, &config);
@@ -595,4 +1238,763 @@ mod tests {
         let result = fan.choose_speed(51.0, &config);
         assert_eq!(result, 2);
     }
+
+    #[test]
+    fn test_hysteresis_holds_state_within_band() {
+        let config = setup_test_config();
+        let mut fan = setup_test_fan();
+        fan.last_state = Some(3);
+
+        assert_eq!(fan.choose_speed(59.0, &config), 3);
+        assert_eq!(fan.choose_speed(58.1, &config), 3);
+    }
+
+    #[test]
+    fn test_hysteresis_steps_down_below_band() {
+        let config = setup_test_config();
+        let mut fan = setup_test_fan();
+        fan.last_state = Some(3);
+
+        assert_eq!(fan.choose_speed(57.9, &config), 2);
+        assert_eq!(fan.choose_speed(40.0, &config), config.state.min);
+    }
+
+    #[test]
+    fn test_hysteresis_steps_up_immediately() {
+        let config = setup_test_config();
+        let mut fan = setup_test_fan();
+        fan.last_state = Some(2);
+
+        assert_eq!(fan.choose_speed(60.0, &config), 3);
+        assert_eq!(fan.choose_speed(80.0, &config), DEFAULT_MAX_STATE);
+    }
+
+    #[test]
+    fn test_hysteresis_percent_of_slot_step() {
+        // 6.25°C between slots, 40% of it is 2.5°C against the fixed 2°C
+        let config = Config {
+            hysteresis_percent: Some(40.0),
+            ..setup_test_config()
+        };
+        let mut fan = setup_test_fan();
+        fan.last_state = Some(3);
+
+        assert_eq!(fan.choose_speed(57.6, &setup_test_config()), 2);
+        assert_eq!(fan.choose_speed(57.6, &config), 3);
+        assert_eq!(fan.choose_speed(57.4, &config), 2);
+    }
+
+    #[test]
+    fn test_on_off_fan_is_a_thermostat() {
+        let config = Config {
+            state: State { min: 0, max: None },
+            curve_mode: CurveMode::Linear,
+            hysteresis_percent: Some(10.0),
+            ..setup_test_config()
+        };
+        let mut fan = Fan {
+            max_state: 1,
+            temp_slots: Fan::calculate_slots(&config, 1),
+            ..setup_test_fan()
+        };
+
+        assert_eq!(fan.choose_speed(44.9, &config), 0);
+        assert_eq!(fan.choose_speed(45.0, &config), 1);
+        assert_eq!(fan.choose_speed(80.0, &config), 1);
+        // 10% of the 25°C between the thresholds
+        fan.last_state = Some(1);
+        assert_eq!(fan.choose_speed(42.6, &config), 1);
+        assert_eq!(fan.choose_speed(42.4, &config), 0);
+    }
+
+    #[test]
+    fn test_hysteresis_disabled() {
+        let config = Config {
+            hysteresis: 0.0,
+            ..setup_test_config()
+        };
+        let mut fan = setup_test_fan();
+        fan.last_state = Some(3);
+
+        assert_eq!(fan.choose_speed(59.9, &config), 2);
+    }
+
+    #[test]
+    fn test_linear_and_step_modes_at_same_temp() {
+        let step = setup_test_config();
+        let linear = Config {
+            curve_mode: CurveMode::Linear,
+            ..setup_test_config()
+        };
+        let fan = setup_test_fan();
+
+        assert_eq!(fan.choose_speed(54.0, &step), 1);
+        assert_eq!(fan.choose_speed(54.0, &linear), 2);
+
+        assert_eq!(fan.choose_speed(52.0, &step), 1);
+        assert_eq!(fan.choose_speed(52.0, &linear), 1);
+    }
+
+    #[test]
+    fn test_linear_mode_bounds() {
+        let config = Config {
+            curve_mode: CurveMode::Linear,
+            ..setup_test_config()
+        };
+        let fan = setup_test_fan();
+
+        assert_eq!(fan.choose_speed(44.0, &config), config.state.min);
+        assert_eq!(fan.choose_speed(45.0, &config), config.state.min);
+        assert_eq!(fan.choose_speed(57.5, &config), 3);
+        assert_eq!(fan.choose_speed(70.0, &config), DEFAULT_MAX_STATE);
+    }
+
+    #[test]
+    fn test_linear_mode_respects_min_state() {
+        let config = Config {
+            threshold: Threshold {
+                min: 40.0,
+                max: 60.0,
+            },
+            state: State {
+                min: 2,
+                max: Some(4),
+            },
+            curve_mode: CurveMode::Linear,
+            ..Config::default()
+        };
+        let fan = setup_test_fan();
+
+        assert_eq!(fan.choose_speed(40.0, &config), 2);
+        assert_eq!(fan.choose_speed(50.0, &config), 3);
+        assert_eq!(fan.choose_speed(60.0, &config), 4);
+    }
+
+    #[test]
+    fn test_pwm_read_write_state() {
+        let dir = std::env::temp_dir().join("test_fan_pwm_read_write");
+        fs::create_dir_all(&dir).unwrap();
+        let fan = Fan {
+            temp_slots: [None; MAX_LEVEL],
+            max_state: DEFAULT_MAX_STATE,
+            backend: Backend::PwmChip { period: 40_000 },
+            tach: None,
+            state: dir.join(FILE_NAME_DUTY_CYCLE),
+            path: dir.clone(),
+            last_state: None,
+            spun_up_at: None,
+            changed_at: None,
+            stall: Stall::Spinning,
+            curve: None,
+            inverted: false,
+            sysfs: Arc::new(RealSysfs),
+        };
+
+        fan.write_state(3).unwrap();
+        assert_eq!(fs::read_to_string(&fan.state).unwrap(), "24000");
+        assert_eq!(fan.read_state().unwrap(), 3);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_hwmon_read_write_state() {
+        let dir = std::env::temp_dir().join("test_fan_hwmon_read_write");
+        fs::create_dir_all(&dir).unwrap();
+        let pwm = dir.join("pwm1");
+        fs::write(&pwm, "0\n").unwrap();
+        fs::write(dir.join("pwm1_enable"), "2\n").unwrap();
+
+        let config = Config {
+            fan_backend: FanBackend::Hwmon,
+            pwm: Pwm {
+                sysfs_path: Some(pwm.clone()),
+                ..Config::default().pwm
+            },
+            ..Config::default()
+        };
+        Fan::probe(&config, Arc::new(RealSysfs)).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("pwm1_enable")).unwrap(), "2\n");
+        let fan = Fan::new(&config, Arc::new(RealSysfs)).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("pwm1_enable")).unwrap(), "1");
+
+        fan.write_state(2).unwrap();
+        assert_eq!(fs::read_to_string(&pwm).unwrap(), "102");
+        assert_eq!(fan.read_duty().unwrap(), 102);
+        assert_eq!(fan.read_state().unwrap(), 2);
+
+        fan.write_duty(255).unwrap();
+        assert_eq!(fan.read_state().unwrap(), DEFAULT_MAX_STATE);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_inverted_read_write_state() {
+        let dir = std::env::temp_dir().join("test_fan_inverted_read_write");
+        fs::create_dir_all(&dir).unwrap();
+        let fan = Fan {
+            state: dir.join(FILE_NAME_CUR_STATE),
+            path: dir.clone(),
+            inverted: true,
+            sysfs: Arc::new(RealSysfs),
+            ..setup_test_fan()
+        };
+
+        fan.write_state(DEFAULT_MAX_STATE).unwrap();
+        assert_eq!(fs::read_to_string(&fan.state).unwrap(), "0");
+        assert_eq!(fan.read_state().unwrap(), DEFAULT_MAX_STATE);
+
+        fan.write_state(1).unwrap();
+        assert_eq!(fs::read_to_string(&fan.state).unwrap(), "4");
+        assert_eq!(fan.read_state().unwrap(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_setup_pwm_channel() {
+        let chip = std::env::temp_dir().join("test_fan_pwm_setup");
+        let channel = chip.join("pwm1");
+        fs::create_dir_all(&channel).unwrap();
+        fs::write(channel.join("period"), "0\n").unwrap();
+
+        let path = Fan::setup_pwm_channel(&RealSysfs, &chip, 1, 40_000).unwrap();
+        assert_eq!(path, channel);
+        assert_eq!(fs::read_to_string(channel.join("period")).unwrap(), "40000");
+        assert_eq!(fs::read_to_string(channel.join("enable")).unwrap(), "1");
+        assert!(!chip.join("export").exists());
+
+        fs::remove_dir_all(&chip).unwrap();
+    }
+
+    #[test]
+    fn test_ramp_limits_state_change() {
+        let config = Config {
+            ramp_step: Some(2),
+            ..setup_test_config()
+        };
+
+        let mut fan = setup_test_fan();
+        assert_eq!(fan.ramp(5, &config), 5);
+
+        fan.last_state = Some(0);
+        assert_eq!(fan.ramp(5, &config), 2);
+        assert_eq!(fan.ramp(1, &config), 1);
+
+        fan.last_state = Some(5);
+        assert_eq!(fan.ramp(0, &config), 3);
+        assert_eq!(fan.ramp(5, &setup_test_config()), 5);
+        assert_eq!(fan.ramp(0, &setup_test_config()), 0);
+    }
+
+    #[test]
+    fn test_record_state_tracks_spin_up() {
+        let mut fan = setup_test_fan();
+
+        fan.record_state(0, 0);
+        assert!(fan.spun_up_at.is_none());
+
+        fan.record_state(2, 0);
+        let spun_up_at = fan.spun_up_at.unwrap();
+
+        fan.record_state(4, 0);
+        assert_eq!(fan.spun_up_at, Some(spun_up_at));
+
+        fan.record_state(0, 0);
+        assert!(fan.spun_up_at.is_none());
+    }
+
+    #[test]
+    fn test_hold_min_on_time() {
+        let config = Config {
+            min_on_time: 60,
+            ..setup_test_config()
+        };
+
+        let mut fan = setup_test_fan();
+        fan.record_state(3, 0);
+        assert_eq!(fan.hold_min_on_time(0, &config), 1);
+        assert_eq!(fan.hold_min_on_time(2, &config), 2);
+        assert_eq!(fan.hold_min_on_time(0, &setup_test_config()), 0);
+
+        fan.spun_up_at = Instant::now().checked_sub(Duration::from_secs(61));
+        assert_eq!(fan.hold_min_on_time(0, &config), 0);
+    }
+
+    #[test]
+    fn test_hold_dwell() {
+        let config = Config {
+            dwell_time: 30,
+            ..setup_test_config()
+        };
+
+        let mut fan = setup_test_fan();
+        assert_eq!(fan.hold_dwell(4, 62.0, &config), 4);
+
+        fan.record_state(3, 0);
+        assert_eq!(fan.hold_dwell(4, 62.0, &config), 3);
+        assert_eq!(fan.hold_dwell(1, 50.0, &config), 3);
+        assert_eq!(fan.hold_dwell(5, 71.0, &config), 5);
+        assert_eq!(fan.hold_dwell(4, 62.0, &setup_test_config()), 4);
+
+        fan.changed_at = Instant::now().checked_sub(Duration::from_secs(31));
+        assert_eq!(fan.hold_dwell(1, 50.0, &config), 1);
+    }
+
+    #[test]
+    fn test_needs_kick_only_when_starting() {
+        let config = Config {
+            spinup_kick: true,
+            ..setup_test_config()
+        };
+
+        assert!(Fan::needs_kick(0, 1, &config));
+        assert!(Fan::needs_kick(0, 5, &config));
+        assert!(!Fan::needs_kick(1, 3, &config));
+        assert!(!Fan::needs_kick(3, 0, &config));
+        assert!(!Fan::needs_kick(0, 0, &config));
+        assert!(!Fan::needs_kick(0, 3, &setup_test_config()));
+
+        let config = Config {
+            state: State {
+                min: 1,
+                max: Some(DEFAULT_MAX_STATE),
+            },
+            ..config
+        };
+        assert!(Fan::needs_kick(0, 1, &config));
+        assert!(Fan::needs_kick(1, 3, &config));
+        assert!(!Fan::needs_kick(1, 1, &config));
+    }
+
+    #[test]
+    fn test_fan_off_temp_bands() {
+        let config = Config {
+            fan_off_temp: Some(40.0),
+            state: State {
+                min: 1,
+                max: Some(DEFAULT_MAX_STATE),
+            },
+            hysteresis: 0.0,
+            ..setup_test_config()
+        };
+        let fan = setup_test_fan();
+
+        assert_eq!(fan.choose_speed(35.0, &config), 0);
+        assert_eq!(fan.choose_speed(40.0, &config), 1);
+        assert_eq!(fan.choose_speed(44.9, &config), 1);
+        assert_eq!(fan.choose_speed(55.0, &config), 2);
+        assert_eq!(fan.choose_speed(71.0, &config), DEFAULT_MAX_STATE);
+
+        let config = Config {
+            fan_off_temp: None,
+            ..config
+        };
+        assert_eq!(fan.choose_speed(35.0, &config), 1);
+    }
+
+    #[test]
+    fn test_fan_off_temp_with_hysteresis() {
+        let config = Config {
+            fan_off_temp: Some(40.0),
+            state: State {
+                min: 1,
+                max: Some(DEFAULT_MAX_STATE),
+            },
+            ..setup_test_config()
+        };
+        let mut fan = setup_test_fan();
+        fan.last_state = Some(1);
+
+        assert_eq!(fan.choose_speed(39.0, &config), 1);
+        assert_eq!(fan.choose_speed(37.9, &config), 0);
+    }
+
+    #[test]
+    fn test_new_fan_device_errors() {
+        let dir = std::env::temp_dir().join("test_fan_new_device_errors");
+        fs::create_dir_all(&dir).unwrap();
+        let state = dir.join("cur_state");
+
+        let result = Fan::new_fan_device(
+            state.clone(),
+            dir.clone(),
+            &setup_test_config(),
+            Arc::new(RealSysfs),
+        );
+        assert!(matches!(result, Err(FanError::Io(_))));
+
+        let config = Config {
+            default_max_state: Some(DEFAULT_MAX_STATE),
+            ..setup_test_config()
+        };
+        let fan =
+            Fan::new_fan_device(state.clone(), dir.clone(), &config, Arc::new(RealSysfs)).unwrap();
+        assert_eq!(fan.max_state, DEFAULT_MAX_STATE);
+
+        fs::write(dir.join("max_state"), "3\n").unwrap();
+        let result = Fan::new_fan_device(
+            state.clone(),
+            dir.clone(),
+            &setup_test_config(),
+            Arc::new(RealSysfs),
+        );
+        assert!(matches!(result, Err(FanError::Config(_))));
+
+        let config = Config {
+            state: State { min: 0, max: None },
+            ..setup_test_config()
+        };
+        let fan =
+            Fan::new_fan_device(state.clone(), dir.clone(), &config, Arc::new(RealSysfs)).unwrap();
+        assert_eq!(fan.max_state, 3);
+        assert_eq!(fan.last_state, None);
+
+        fs::write(&state, "2\n").unwrap();
+        let fan = Fan::new_fan_device(state, dir.clone(), &config, Arc::new(RealSysfs)).unwrap();
+        assert_eq!(fan.last_state, Some(2));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_fan_device_path() {
+        let dir = std::env::temp_dir().join("test_fan_device_path");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("max_state"), "5\n").unwrap();
+        let config = Config {
+            fan_device_path: Some(dir.clone()),
+            ..setup_test_config()
+        };
+
+        let result = Fan::from_device_path(&dir, &config, Arc::new(RealSysfs));
+        assert!(matches!(result, Err(FanError::Io(_))));
+
+        fs::write(dir.join("cur_state"), "fast\n").unwrap();
+        let result = Fan::from_device_path(&dir, &config, Arc::new(RealSysfs));
+        assert!(matches!(result, Err(FanError::ParseState(_))));
+
+        fs::write(dir.join("cur_state"), "1\n").unwrap();
+        let fan = Fan::new_cooling_device(&config, Arc::new(RealSysfs)).unwrap();
+        assert_eq!(fan.path, dir);
+        assert_eq!(fan.state, dir.join("cur_state"));
+        assert_eq!(fan.last_state, Some(1));
+
+        // A device the config doesn't fit isn't replaced by a scanned one
+        fs::write(dir.join("max_state"), "3\n").unwrap();
+        let result = Fan::new_cooling_device(&config, Arc::new(RealSysfs));
+        assert!(matches!(result, Err(FanError::Config(_))));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_find_fan_device() {
+        let dir = std::env::temp_dir().join("test_fan_find_device");
+        for (name, device_type) in [
+            ("cooling_device0", "cpufreq-cpu0"),
+            ("cooling_device1", "pwm-fan"),
+            ("cooling_device2", "pwm-fan"),
+        ] {
+            let device = dir.join(name);
+            fs::create_dir_all(&device).unwrap();
+            fs::write(device.join("type"), format!("{device_type}\n")).unwrap();
+            fs::write(device.join("cur_state"), "0").unwrap();
+        }
+
+        let first = dir.join("cooling_device1");
+        assert_eq!(
+            Fan::find_fan_device(&RealSysfs, &dir, None, None),
+            Some((first.join("cur_state"), first))
+        );
+
+        let second = dir.join("cooling_device2");
+        assert_eq!(
+            Fan::find_fan_device(&RealSysfs, &dir, Some(2), None),
+            Some((second.join("cur_state"), second))
+        );
+        assert_eq!(Fan::find_fan_device(&RealSysfs, &dir, Some(3), None), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_find_fan_device_by_name() {
+        let dir = std::env::temp_dir().join("test_fan_find_device_by_name");
+        for (name, device_name) in [
+            ("cooling_device0", "fan-case"),
+            ("cooling_device1", "fan-nvme"),
+        ] {
+            let device = dir.join(name);
+            fs::create_dir_all(&device).unwrap();
+            fs::write(device.join("type"), "pwm-fan\n").unwrap();
+            fs::write(device.join("name"), format!("{device_name}\n")).unwrap();
+            fs::write(device.join("cur_state"), "0").unwrap();
+        }
+
+        let first = dir.join("cooling_device0");
+        let second = dir.join("cooling_device1");
+        assert_eq!(
+            Fan::find_fan_device(&RealSysfs, &dir, None, None),
+            Some((first.join("cur_state"), first))
+        );
+        assert_eq!(
+            Fan::find_fan_device(&RealSysfs, &dir, None, Some("fan-nvme")),
+            Some((second.join("cur_state"), second.clone()))
+        );
+        assert_eq!(
+            Fan::find_fan_device(&RealSysfs, &dir, None, Some("cooling_device1")),
+            Some((second.join("cur_state"), second))
+        );
+        assert_eq!(
+            Fan::find_fan_device(&RealSysfs, &dir, None, Some("fan-gpu")),
+            None
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_read_rpm() {
+        let dir = std::env::temp_dir().join("test_fan_read_rpm");
+        fs::create_dir_all(&dir).unwrap();
+        let tach = dir.join("fan1_input");
+
+        let mut fan = setup_test_fan();
+        assert_eq!(fan.read_rpm(), None);
+
+        fan.tach = Some(tach.clone());
+        assert_eq!(fan.read_rpm(), None);
+
+        fs::write(&tach, "2450\n").unwrap();
+        assert_eq!(fan.read_rpm(), Some(2450));
+
+        fs::write(&tach, "invalid").unwrap();
+        assert_eq!(fan.read_rpm(), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_critical_temp_forces_max_state() {
+        let config = Config {
+            threshold: Threshold {
+                min: 45.0,
+                max: 70.0,
+            },
+            state: State {
+                min: 0,
+                max: Some(3),
+            },
+            critical_temp: Some(60.0),
+            ..Config::default()
+        };
+        let mut fan = setup_test_fan();
+
+        assert_eq!(fan.choose_speed(59.9, &config), 2);
+        assert_eq!(fan.choose_speed(60.0, &config), DEFAULT_MAX_STATE);
+        assert_eq!(fan.choose_speed(95.0, &config), DEFAULT_MAX_STATE);
+
+        fan.last_state = Some(DEFAULT_MAX_STATE);
+        assert_eq!(fan.choose_speed(60.5, &config), DEFAULT_MAX_STATE);
+    }
+
+    #[test]
+    fn test_fixed_state_is_always_chosen() {
+        let config = Config {
+            fixed_state: Some(2),
+            critical_temp: Some(85.0),
+            ..setup_test_config()
+        };
+        let mut fan = setup_test_fan();
+
+        for temp in [20.0, 45.0, 57.0, 70.0, 84.0] {
+            assert_eq!(fan.choose_speed(temp, &config), 2);
+        }
+        fan.last_state = Some(5);
+        assert_eq!(fan.choose_speed(60.0, &config), 2);
+        assert_eq!(fan.choose_speed(90.0, &config), DEFAULT_MAX_STATE);
+
+        let config = Config {
+            fixed_state: Some(9),
+            ..setup_test_config()
+        };
+        assert_eq!(fan.choose_speed(30.0, &config), DEFAULT_MAX_STATE);
+    }
+
+    #[test]
+    fn test_critical_temp_unset() {
+        let config = Config {
+            state: State {
+                min: 0,
+                max: Some(3),
+            },
+            ..setup_test_config()
+        };
+        let fan = setup_test_fan();
+
+        assert_eq!(fan.choose_speed(120.0, &config), 3);
+    }
+
+    #[test]
+    fn test_custom_slots_override_computed_slots() {
+        let config = Config {
+            slots: Some(vec![(1, 45.0), (2, 55.0), (4, 65.0), (5, 70.0)]),
+            ..setup_test_config()
+        };
+
+        let slots = Fan::get_temperature_slots(&config, DEFAULT_MAX_STATE);
+
+        rest_is_none(slots, 4);
+        assert_eq!(slots[0].unwrap(), (1, 45.0));
+        assert_eq!(slots[1].unwrap(), (2, 55.0));
+        assert_eq!(slots[2].unwrap(), (4, 65.0));
+        assert_eq!(slots[3].unwrap(), (5, 70.0));
+    }
+
+    #[test]
+    fn test_custom_slots_state_out_of_range_falls_back() {
+        let config = Config {
+            state: State {
+                min: 1,
+                max: Some(DEFAULT_MAX_STATE),
+            },
+            slots: Some(vec![(0, 45.0), (2, 55.0)]),
+            ..setup_test_config()
+        };
+
+        let slots = Fan::get_temperature_slots(&config, DEFAULT_MAX_STATE);
+        assert_eq!(slots, Fan::calculate_slots(&config, DEFAULT_MAX_STATE));
+
+        let config = Config {
+            slots: Some(vec![(2, 45.0), (6, 55.0)]),
+            ..setup_test_config()
+        };
+
+        let slots = Fan::get_temperature_slots(&config, DEFAULT_MAX_STATE);
+        assert_eq!(slots, Fan::calculate_slots(&config, DEFAULT_MAX_STATE));
+    }
+
+    #[test]
+    fn test_custom_slots_not_ascending_falls_back() {
+        let config = Config {
+            slots: Some(vec![(1, 55.0), (2, 45.0)]),
+            ..setup_test_config()
+        };
+
+        assert!(Fan::custom_slots(config.slots.as_ref().unwrap(), 0, DEFAULT_MAX_STATE).is_err());
+
+        let slots = Fan::get_temperature_slots(&config, DEFAULT_MAX_STATE);
+        assert_eq!(slots, Fan::calculate_slots(&config, DEFAULT_MAX_STATE));
+    }
+
+    #[test]
+    fn test_choose_speed_with_custom_slots() {
+        let config = Config {
+            slots: Some(vec![(1, 45.0), (2, 55.0), (4, 65.0), (5, 70.0)]),
+            ..setup_test_config()
+        };
+
+        let fan = Fan {
+            temp_slots: Fan::get_temperature_slots(&config, DEFAULT_MAX_STATE),
+            ..setup_test_fan()
+        };
+
+        assert_eq!(fan.choose_speed(50.0, &config), 1);
+        assert_eq!(fan.choose_speed(60.0, &config), 2);
+        assert_eq!(fan.choose_speed(66.0, &config), 4);
+    }
+
+    #[test]
+    fn test_curve_table() {
+        let fan = setup_test_fan();
+        let config = setup_test_config();
+        assert_eq!(
+            fan.curve_table(&config),
+            vec![
+                (f32::NEG_INFINITY, 50.0, 0),
+                (50.0, 55.0, 1),
+                (55.0, 60.0, 2),
+                (60.0, 65.0, 3),
+                (65.0, 70.0, 4),
+                (70.0, f32::INFINITY, 5),
+            ]
+        );
+
+        let config = Config {
+            critical_temp: Some(62.0),
+            ..setup_test_config()
+        };
+        let table = fan.curve_table(&config);
+        assert_eq!(
+            table.last(),
+            Some(&(62.0, f32::INFINITY, DEFAULT_MAX_STATE))
+        );
+        assert_eq!(table[table.len() - 2], (60.0, 62.0, 3));
+
+        let config = Config {
+            curve_mode: CurveMode::Linear,
+            ..setup_test_config()
+        };
+        let table = fan.curve_table(&config);
+        let states: Vec<u8> = table.iter().map(|&(_, _, state)| state).collect();
+        assert_eq!(states, vec![0, 1, 2, 3, 4, 5]);
+        for (&(low, _, _), expected) in table[1..].iter().zip([47.5, 52.5, 57.5, 62.5, 67.5]) {
+            assert!((low - expected).abs() < 0.001, "{low} != {expected}");
+        }
+    }
+
+    #[test]
+    fn test_load_curve_from_csv() {
+        let dir = std::env::temp_dir().join("test_fan_load_curve");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("curve.csv");
+        fs::write(&path, "temp,state\n# quiet until 40\n40,1\n\n50,3\n70,5\n").unwrap();
+
+        let mut fan = setup_test_fan();
+        fan.load_curve_from_csv(&path, TempUnit::Celsius).unwrap();
+        assert_eq!(fan.curve, Some(vec![(40.0, 1), (50.0, 3), (70.0, 5)]));
+
+        let config = setup_test_config();
+        assert_eq!(fan.choose_speed(30.0, &config), 1);
+        assert_eq!(fan.choose_speed(45.0, &config), 2);
+        assert_eq!(fan.choose_speed(50.0, &config), 3);
+        assert_eq!(fan.choose_speed(65.0, &config), 5);
+        assert_eq!(fan.choose_speed(90.0, &config), 5);
+
+        fs::write(&path, "104,2\n122,4\n").unwrap();
+        fan.load_curve_from_csv(&path, TempUnit::Fahrenheit)
+            .unwrap();
+        assert_eq!(fan.curve, Some(vec![(40.0, 2), (50.0, 4)]));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_load_curve_from_csv_rejects_invalid_files() {
+        let dir = std::env::temp_dir().join("test_fan_load_curve_invalid");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("curve.csv");
+
+        for content in [
+            "50,3\n40,1\n",
+            "40,1\n40,2\n",
+            "40,1\n50,9\n",
+            "40,1\nfifty,2\n",
+            "40;1\n",
+            "temp,state\n",
+        ] {
+            fs::write(&path, content).unwrap();
+            let mut fan = setup_test_fan();
+            let result = fan.load_curve_from_csv(&path, TempUnit::Celsius);
+            assert!(
+                matches!(result, Err(FanError::InvalidCurve(_))),
+                "{content:?} should be rejected"
+            );
+            assert!(fan.curve.is_none());
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
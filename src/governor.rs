@@ -0,0 +1,189 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use log::{error, info, warn};
+
+use crate::sysfs::SysfsAccess;
+
+// Governor that leaves the cooling devices alone, only notifying userspace
+// when a trip point is crossed.
+const USER_SPACE_POLICY: &str = "user_space";
+
+// Thermal zones switched to the `user_space` governor so the kernel doesn't
+// fight the daemon, along with the policy to restore on shutdown.
+pub struct Governor {
+    taken: Vec<(PathBuf, String)>,
+    sysfs: Arc<dyn SysfsAccess>,
+}
+
+impl Governor {
+    // Thermal zones with a `cdevN` link to the cooling device at `device`.
+    #[must_use]
+    pub fn bound_zones(sysfs: &dyn SysfsAccess, dir: &Path, device: &Path) -> Vec<PathBuf> {
+        let (Ok(device), Ok(entries)) = (sysfs.canonicalize(device), sysfs.read_dir(dir)) else {
+            return Vec::new();
+        };
+        let mut zones: Vec<PathBuf> = entries
+            .into_iter()
+            .filter(|zone| {
+                sysfs.read_dir(zone).is_ok_and(|links| {
+                    links.iter().any(|link| {
+                        link.file_name()
+                            .and_then(|name| name.to_str())
+                            .and_then(|name| name.strip_prefix("cdev"))
+                            .is_some_and(|index| {
+                                !index.is_empty() && index.bytes().all(|b| b.is_ascii_digit())
+                            })
+                            && sysfs
+                                .canonicalize(link)
+                                .is_ok_and(|target| target == device)
+                    })
+                })
+            })
+            .collect();
+        zones.sort();
+        zones
+    }
+
+    // Zones whose policy can't be read or written are left as they are, all
+    // of them with `dry_run`.
+    #[must_use]
+    pub fn take_over(zones: &[PathBuf], sysfs: Arc<dyn SysfsAccess>, dry_run: bool) -> Self {
+        if zones.is_empty() {
+            warn!("No thermal zone bound to the fan device, the kernel governor is left as is");
+        }
+
+        let mut taken = Vec::new();
+        for zone in zones {
+            let path = zone.join("policy");
+            let policy = match sysfs.read_to_string(&path) {
+                Ok(policy) => policy.trim().to_owned(),
+                Err(err) => {
+                    error!("Can't read the governor of {}: {err}", zone.display());
+                    continue;
+                }
+            };
+            if policy == USER_SPACE_POLICY {
+                continue;
+            }
+            if dry_run {
+                info!(
+                    "Dry run: would switch {} from the {policy} governor to {USER_SPACE_POLICY}",
+                    zone.display()
+                );
+                continue;
+            }
+            match sysfs.write(&path, USER_SPACE_POLICY) {
+                Ok(()) => {
+                    info!(
+                        "Switched {} from the {policy} governor to {USER_SPACE_POLICY}",
+                        zone.display()
+                    );
+                    taken.push((path, policy));
+                }
+                Err(err) => error!("Can't take over the governor of {}: {err}", zone.display()),
+            }
+        }
+        Self { taken, sysfs }
+    }
+
+    pub fn restore(&mut self) {
+        for (path, policy) in self.taken.drain(..) {
+            match self.sysfs.write(&path, &policy) {
+                Ok(()) => info!("Restored the {policy} governor on {}", path.display()),
+                Err(err) => error!("Can't restore the governor on {}: {err}", path.display()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sysfs::MockSysfs;
+
+    #[test]
+    fn test_bound_zones() {
+        let sysfs = MockSysfs::default();
+        let dir = Path::new("/sys/class/thermal");
+        let fan = dir.join("cooling_device1");
+        let other = dir.join("cooling_device0");
+        sysfs.set(fan.join("type"), "pwm-fan\n");
+        sysfs.set(other.join("type"), "cpufreq-cpu0\n");
+        sysfs.link(dir.join("thermal_zone0/cdev0"), &other);
+        sysfs.link(dir.join("thermal_zone1/cdev0"), &fan);
+        sysfs.set(dir.join("thermal_zone1/cdev0_weight"), "0");
+
+        assert_eq!(
+            Governor::bound_zones(&sysfs, dir, &fan),
+            vec![dir.join("thermal_zone1")]
+        );
+        assert!(Governor::bound_zones(&sysfs, dir, &dir.join("cooling_device9")).is_empty());
+    }
+
+    #[test]
+    fn test_take_over_and_restore() {
+        let sysfs = Arc::new(MockSysfs::default());
+        let zones = [
+            PathBuf::from("thermal_zone0"),
+            PathBuf::from("thermal_zone1"),
+            PathBuf::from("thermal_zone2"),
+        ];
+        sysfs.set("thermal_zone0/policy", "step_wise\n");
+        sysfs.set("thermal_zone1/policy", "user_space\n");
+        // thermal_zone2 has no policy file
+
+        let mut governor = Governor::take_over(&zones, sysfs.clone(), false);
+        assert_eq!(
+            sysfs.get(Path::new("thermal_zone0/policy")).as_deref(),
+            Some("user_space")
+        );
+        assert_eq!(
+            sysfs.writes(),
+            [(
+                PathBuf::from("thermal_zone0/policy"),
+                "user_space".to_owned()
+            )]
+        );
+
+        governor.restore();
+        assert_eq!(
+            sysfs.get(Path::new("thermal_zone0/policy")).as_deref(),
+            Some("step_wise")
+        );
+        assert_eq!(sysfs.writes().len(), 2);
+
+        // Restored only once
+        governor.restore();
+        assert_eq!(sysfs.writes().len(), 2);
+    }
+
+    #[test]
+    fn test_take_over_denied() {
+        let sysfs = Arc::new(MockSysfs::default());
+        sysfs.set("thermal_zone0/policy", "step_wise\n");
+        sysfs.set_read_only("thermal_zone0/policy");
+
+        let mut governor =
+            Governor::take_over(&[PathBuf::from("thermal_zone0")], sysfs.clone(), false);
+        governor.restore();
+        assert!(sysfs.writes().is_empty());
+    }
+
+    #[test]
+    fn test_take_over_dry_run() {
+        let sysfs = Arc::new(MockSysfs::default());
+        sysfs.set("thermal_zone0/policy", "step_wise\n");
+
+        let mut governor =
+            Governor::take_over(&[PathBuf::from("thermal_zone0")], sysfs.clone(), true);
+        governor.restore();
+        assert!(sysfs.writes().is_empty());
+        assert_eq!(
+            sysfs.get(Path::new("thermal_zone0/policy")).as_deref(),
+            Some("step_wise\n")
+        );
+    }
+}
@@ -0,0 +1,108 @@
+use std::{
+    collections::VecDeque,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decision {
+    pub timestamp: u64,
+    pub fan: usize,
+    pub temp: f32,
+    pub state: u8,
+}
+
+impl Decision {
+    #[must_use]
+    pub fn to_json(&self) -> String {
+        format!(
+            r#"{{"fan":{},"state":{},"temp":{:.2},"timestamp":{}}}"#,
+            self.fan, self.state, self.temp, self.timestamp
+        )
+    }
+}
+
+// The last `capacity` decisions of the control loop, oldest first, so an
+// intermittent spin-up can be inspected without trace logging always on.
+pub struct History {
+    capacity: usize,
+    decisions: VecDeque<Decision>,
+}
+
+impl History {
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            decisions: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn record(&mut self, fan: usize, temp: f32, state: u8) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        self.push(Decision {
+            timestamp,
+            fan,
+            temp,
+            state,
+        });
+    }
+
+    pub fn push(&mut self, decision: Decision) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.decisions.len() == self.capacity {
+            self.decisions.pop_front();
+        }
+        self.decisions.push_back(decision);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Decision> {
+        self.decisions.iter()
+    }
+
+    #[must_use]
+    pub fn to_json(&self) -> String {
+        let decisions: Vec<String> = self.iter().map(Decision::to_json).collect();
+        format!("[{}]", decisions.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decision(timestamp: u64, state: u8) -> Decision {
+        Decision {
+            timestamp,
+            fan: 0,
+            temp: 50.0,
+            state,
+        }
+    }
+
+    #[test]
+    fn test_history_keeps_last_decisions() {
+        let mut history = History::new(2);
+        history.push(decision(1, 1));
+        history.push(decision(2, 2));
+        history.push(decision(3, 3));
+
+        let states: Vec<u8> = history.iter().map(|decision| decision.state).collect();
+        assert_eq!(states, vec![2, 3]);
+        assert_eq!(
+            history.to_json(),
+            r#"[{"fan":0,"state":2,"temp":50.00,"timestamp":2},{"fan":0,"state":3,"temp":50.00,"timestamp":3}]"#
+        );
+    }
+
+    #[test]
+    fn test_history_disabled() {
+        let mut history = History::new(0);
+        history.record(0, 50.0, 1);
+        assert_eq!(history.iter().count(), 0);
+        assert_eq!(history.to_json(), "[]");
+    }
+}
@@ -0,0 +1,248 @@
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{Arc, Mutex, PoisonError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::{debug, info, warn};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Default)]
+pub struct Health {
+    pub last_success: Option<Instant>,
+    pub last_error: Option<String>,
+    pub devices_present: bool,
+    pub fan_stalled: bool,
+    pub max_age: Duration,
+}
+
+impl Health {
+    // Healthy while every device is present and the last successful
+    // adjustment is more recent than `max_age`.
+    pub fn check(&self, now: Instant) -> Result<(), String> {
+        let reason = if !self.devices_present {
+            "fan or temperature device missing"
+        } else if self.fan_stalled {
+            "fan stalled"
+        } else if self
+            .last_success
+            .is_none_or(|last| now.saturating_duration_since(last) > self.max_age)
+        {
+            "no successful adjustment recently"
+        } else {
+            return Ok(());
+        };
+
+        Err(self
+            .last_error
+            .as_ref()
+            .map_or_else(|| reason.to_owned(), |err| format!("{reason}: {err}")))
+    }
+}
+
+// Serves requests from a background thread so a slow client never delays
+// the control loop.
+pub struct Server {
+    addr: SocketAddr,
+    health: Arc<Mutex<Health>>,
+    history: Arc<Mutex<String>>,
+    metrics: Arc<Mutex<String>>,
+}
+
+impl Server {
+    pub fn bind(addr: &str) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        info!("Serving /healthz, /history and /metrics on {addr}");
+
+        let health = Arc::new(Mutex::new(Health::default()));
+        let history = Arc::new(Mutex::new("[]".to_owned()));
+        let metrics = Arc::new(Mutex::new(String::new()));
+        let shared_health = Arc::clone(&health);
+        let shared_history = Arc::clone(&history);
+        let shared_metrics = Arc::clone(&metrics);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        if let Err(err) =
+                            handle(&stream, &shared_health, &shared_history, &shared_metrics)
+                        {
+                            debug!("Can't answer HTTP request: {err}");
+                        }
+                    }
+                    Err(err) => warn!("Can't accept HTTP connection: {err}"),
+                }
+            }
+        });
+
+        Ok(Self {
+            addr,
+            health,
+            history,
+            metrics,
+        })
+    }
+
+    #[must_use]
+    pub const fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn update(&self, health: Health) {
+        *self.health.lock().unwrap_or_else(PoisonError::into_inner) = health;
+    }
+
+    pub fn update_history(&self, history: String) {
+        *self.history.lock().unwrap_or_else(PoisonError::into_inner) = history;
+    }
+
+    // Prometheus text exposition, served as is at `/metrics`.
+    pub fn update_metrics(&self, metrics: String) {
+        *self.metrics.lock().unwrap_or_else(PoisonError::into_inner) = metrics;
+    }
+}
+
+fn handle(
+    mut stream: &TcpStream,
+    health: &Mutex<Health>,
+    history: &Mutex<String>,
+    metrics: &Mutex<String>,
+) -> io::Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+
+    let mut request_line = String::new();
+    BufReader::new(stream).read_line(&mut request_line)?;
+
+    let (status, content_type, body) = match request_line.split_whitespace().nth(1) {
+        Some("/healthz") => {
+            let health = health.lock().unwrap_or_else(PoisonError::into_inner);
+            match health.check(Instant::now()) {
+                Ok(()) => ("200 OK", "text/plain", "ok".to_owned()),
+                Err(err) => ("503 Service Unavailable", "text/plain", err),
+            }
+        }
+        Some("/history") => {
+            let history = history.lock().unwrap_or_else(PoisonError::into_inner);
+            ("200 OK", "application/json", history.clone())
+        }
+        Some("/metrics") => {
+            let metrics = metrics.lock().unwrap_or_else(PoisonError::into_inner);
+            ("200 OK", "text/plain; version=0.0.4", metrics.clone())
+        }
+        _ => ("404 Not Found", "text/plain", "not found".to_owned()),
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}\n",
+        body.len() + 1
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+
+    fn get(addr: &str, path: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+        write!(stream, "GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_health_check() {
+        let now = Instant::now();
+        let healthy = Health {
+            last_success: Some(now),
+            last_error: None,
+            devices_present: true,
+            fan_stalled: false,
+            max_age: Duration::from_secs(10),
+        };
+        assert!(healthy.check(now).is_ok());
+
+        let stale = now + Duration::from_secs(11);
+        assert_eq!(
+            healthy.check(stale),
+            Err("no successful adjustment recently".to_owned())
+        );
+
+        let missing = Health {
+            devices_present: false,
+            last_error: Some("Still no fan device available".to_owned()),
+            ..healthy
+        };
+        assert_eq!(
+            missing.check(now),
+            Err("fan or temperature device missing: Still no fan device available".to_owned())
+        );
+
+        let stalled = Health {
+            fan_stalled: true,
+            ..healthy
+        };
+        assert_eq!(stalled.check(now), Err("fan stalled".to_owned()));
+
+        assert!(Health::default().check(now).is_err());
+    }
+
+    #[test]
+    fn test_server_answers_healthz() {
+        let server = Server::bind("127.0.0.1:0").unwrap();
+        let addr = server.addr().to_string();
+        let response = get(&addr, "/healthz");
+        assert!(response.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+        assert!(response.ends_with("\r\n\r\nfan or temperature device missing\n"));
+
+        server.update(Health {
+            last_success: Some(Instant::now()),
+            last_error: None,
+            devices_present: true,
+            fan_stalled: false,
+            max_age: Duration::from_secs(10),
+        });
+        let response = get(&addr, "/healthz");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("\r\n\r\nok\n"));
+
+        assert!(get(&addr, "/").starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+
+    #[test]
+    fn test_server_answers_history() {
+        let server = Server::bind("127.0.0.1:0").unwrap();
+        let addr = server.addr().to_string();
+        assert!(get(&addr, "/history").ends_with("\r\n\r\n[]\n"));
+
+        let history = r#"[{"fan":0,"state":2,"temp":50.00,"timestamp":1}]"#;
+        server.update_history(history.to_owned());
+        let response = get(&addr, "/history");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("Content-Type: application/json\r\n"));
+        assert!(response.ends_with(&format!("\r\n\r\n{history}\n")));
+    }
+
+    #[test]
+    fn test_server_answers_metrics() {
+        let server = Server::bind("127.0.0.1:0").unwrap();
+        let addr = server.addr().to_string();
+
+        let metrics = "cm3588_fan_temperature_max_celsius 61.5";
+        server.update_metrics(metrics.to_owned());
+        let response = get(&addr, "/metrics");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("Content-Type: text/plain; version=0.0.4\r\n"));
+        assert!(response.ends_with(&format!("\r\n\r\n{metrics}\n")));
+    }
+}
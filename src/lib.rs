@@ -1,6 +1,28 @@
 pub mod checker;
+pub mod cli;
 pub mod config;
+#[cfg(feature = "dbus")]
+pub mod dbus;
+pub mod error;
+pub mod events;
 pub mod fan;
+pub mod governor;
+pub mod history;
+pub mod http;
+pub mod load;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+pub mod pidfile;
+pub mod policy;
+pub mod status;
+pub mod sysfs;
+#[cfg(feature = "syslog")]
+pub mod syslog;
+#[cfg(feature = "systemd")]
+pub mod systemd;
 pub mod temp;
 
-pub const THERMAL_DIR: &str = "/sys/class/thermal";
+pub const DEFAULT_SYSFS_ROOT: &str = "/sys";
+// Below `SYSFS_ROOT`
+pub const THERMAL_DIR: &str = "class/thermal";
+pub const PWM_DIR: &str = "class/pwm";
@@ -0,0 +1,83 @@
+use std::{io, path::Path, thread};
+
+use crate::sysfs::SysfsAccess;
+
+// Load rises as soon as a burst starts, well before the SoC heats up.
+pub const LOADAVG_PATH: &str = "/proc/loadavg";
+
+// 1 minute load average, the first field of `/proc/loadavg`.
+#[must_use]
+pub fn parse_loadavg(content: &str) -> Option<f32> {
+    content
+        .split_whitespace()
+        .next()?
+        .parse::<f32>()
+        .ok()
+        .filter(|load| load.is_finite() && *load >= 0.0)
+}
+
+pub fn read_load(sysfs: &dyn SysfsAccess) -> io::Result<f32> {
+    let content = sysfs.read_to_string(Path::new(LOADAVG_PATH))?;
+    parse_loadavg(&content).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unexpected load average: {}", content.trim()),
+        )
+    })
+}
+
+// States added on top of the temperature decision, `bias` once every CPU is
+// busy and proportionally less below, so an idle board isn't biased.
+#[must_use]
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub fn load_bump(load: f32, cpus: usize, bias: u8) -> u8 {
+    let ratio = (load / cpus.max(1) as f32).clamp(0.0, 1.0);
+    // ratio is clamped to [0, 1] so the bump always fits in [0, bias]
+    (ratio * f32::from(bias)) as u8
+}
+
+#[must_use]
+pub fn cpu_count() -> usize {
+    thread::available_parallelism().map_or(1, usize::from)
+}
+
+#[cfg(test)]
+#[allow(clippy::float_cmp)]
+mod tests {
+    use super::*;
+    use crate::sysfs::MockSysfs;
+
+    #[test]
+    fn test_parse_loadavg() {
+        assert_eq!(parse_loadavg("0.52 0.58 0.59 1/389 12345\n"), Some(0.52));
+        assert_eq!(parse_loadavg("12.00 8.10 4.20 9/512 4242"), Some(12.0));
+        assert_eq!(parse_loadavg(""), None);
+        assert_eq!(parse_loadavg("busy 0.58 0.59"), None);
+        assert_eq!(parse_loadavg("-1.0 0.58 0.59"), None);
+        assert_eq!(parse_loadavg("NaN 0.58 0.59"), None);
+    }
+
+    #[test]
+    fn test_read_load() {
+        let sysfs = MockSysfs::default();
+        assert!(read_load(&sysfs).is_err());
+        sysfs.set(LOADAVG_PATH, "garbage\n");
+        assert_eq!(
+            read_load(&sysfs).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        sysfs.set(LOADAVG_PATH, "3.50 2.00 1.00 4/300 999\n");
+        assert_eq!(read_load(&sysfs).unwrap(), 3.5);
+    }
+
+    #[test]
+    fn test_load_bump() {
+        assert_eq!(load_bump(0.3, 8, 2), 0);
+        assert_eq!(load_bump(4.0, 8, 2), 1);
+        assert_eq!(load_bump(8.0, 8, 2), 2);
+        // Clamped whatever the load
+        assert_eq!(load_bump(40.0, 8, 2), 2);
+        assert_eq!(load_bump(1.0, 0, 1), 1);
+        assert_eq!(load_bump(8.0, 8, 0), 0);
+    }
+}
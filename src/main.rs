@@ -1,15 +1,125 @@
-use std::{thread, time::Duration};
+use std::{
+    process::ExitCode,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
 
-use log::debug;
+use log::{debug, error, info};
+use signal_hook::{
+    consts::{SIGHUP, SIGINT, SIGTERM, SIGUSR1},
+    flag,
+};
 
-use cm3588_fan::checker::Checker;
+use clap::Parser;
+#[cfg(feature = "systemd")]
+use cm3588_fan::systemd::Notifier;
+use cm3588_fan::{checker::Checker, cli::Args, config::Config, pidfile::PidFile};
 
-fn main() {
-    let mut checker = Checker::new();
+const SIGNAL_POLL_INTERVAL: Duration = Duration::from_millis(100);
 
-    loop {
-        checker.adjust_speed();
-        debug!("Sleeping for {} seconds", checker.config.sleep_time);
-        thread::sleep(Duration::from_secs(checker.config.sleep_time));
+fn sleep(duration: Duration, term: &AtomicBool) {
+    let deadline = Instant::now() + duration;
+    while !term.load(Ordering::Relaxed) {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+        thread::sleep(remaining.min(SIGNAL_POLL_INTERVAL));
     }
 }
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    if args.print_config {
+        print!("{}", Checker::describe(&args, &Config::for_report(&args)));
+        return ExitCode::SUCCESS;
+    }
+    if args.list_devices {
+        print!("{}", Checker::list_devices(&Config::for_report(&args)));
+        return ExitCode::SUCCESS;
+    }
+    if args.validate {
+        let (report, passed) = Checker::validate(&args, &Config::for_report(&args));
+        print!("{report}");
+        return if passed {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        };
+    }
+
+    let config = Config::with_args(&args);
+    // Taken before any device or socket is opened, released last
+    let _pid_file = match config.pid_file.as_deref() {
+        Some(path) if !config.run_once => match PidFile::create(path) {
+            Ok(pid_file) => Some(pid_file),
+            Err(err) => {
+                error!("Can't write PID file {}: {err}", path.display());
+                return ExitCode::FAILURE;
+            }
+        },
+        _ => None,
+    };
+
+    let mut checker = Checker::with_config(&args, config);
+    checker.log_startup_summary();
+
+    if checker.config.run_once {
+        return if checker.step().controlled {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        };
+    }
+
+    let term = Arc::new(AtomicBool::new(false));
+    for signal in [SIGTERM, SIGINT] {
+        if let Err(err) = flag::register(signal, Arc::clone(&term)) {
+            error!("Can't register handler for signal {signal}: {err}");
+        }
+    }
+
+    let hup = Arc::new(AtomicBool::new(false));
+    if let Err(err) = flag::register(SIGHUP, Arc::clone(&hup)) {
+        error!("Can't register handler for signal {SIGHUP}: {err}");
+    }
+
+    let usr1 = Arc::new(AtomicBool::new(false));
+    if let Err(err) = flag::register(SIGUSR1, Arc::clone(&usr1)) {
+        error!("Can't register handler for signal {SIGUSR1}: {err}");
+    }
+
+    #[cfg(feature = "systemd")]
+    let mut notifier = Notifier::new();
+
+    while !term.load(Ordering::Relaxed) {
+        if hup.swap(false, Ordering::Relaxed) {
+            info!("SIGHUP received, resetting statistics");
+            checker.reset_stats();
+        }
+        if usr1.swap(false, Ordering::Relaxed) {
+            info!("SIGUSR1 received, dumping decision history");
+            checker.dump_history();
+        }
+
+        #[cfg_attr(not(feature = "systemd"), allow(unused_variables))]
+        let outcome = checker.step();
+        #[cfg(feature = "systemd")]
+        notifier.update(outcome.controlled);
+
+        let sleep_time = checker.jittered_sleep_time();
+        debug!("Sleeping for {:.1} seconds", sleep_time.as_secs_f32());
+        sleep(sleep_time, &term);
+    }
+
+    info!("Termination signal received");
+    #[cfg(feature = "systemd")]
+    Notifier::stopping();
+    checker.shutdown();
+
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,320 @@
+use std::{
+    io::{self, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    process,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError},
+    },
+    thread,
+    time::Duration,
+};
+
+use log::{debug, error, info, warn};
+
+// Retained QoS 0 messages only take CONNECT, PUBLISH and PINGREQ, small
+// enough to write by hand rather than pull an async MQTT client and its
+// runtime into a daemon that has neither.
+const DEFAULT_PORT: u16 = 1883;
+const TIMEOUT: Duration = Duration::from_secs(5);
+// Brokers drop a client that stays silent for 1.5 times this
+const KEEP_ALIVE: Duration = Duration::from_mins(1);
+
+static CLIENT_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+struct Update {
+    temp: f32,
+    state: Option<u8>,
+}
+
+// Publishes from a background thread so a slow or unreachable broker never
+// delays the control loop. Updates are dropped while the thread is busy.
+pub struct Publisher {
+    sender: SyncSender<Update>,
+}
+
+impl Publisher {
+    #[must_use]
+    pub fn new(host: &str, topic_prefix: &str) -> Self {
+        let (sender, receiver) = mpsc::sync_channel(1);
+        let host = if host.contains(':') {
+            host.to_owned()
+        } else {
+            format!("{host}:{DEFAULT_PORT}")
+        };
+        let topic_prefix = topic_prefix.trim_end_matches('/').to_owned();
+        let client_id = format!(
+            "cm3588-fan-{}-{}",
+            process::id(),
+            CLIENT_COUNT.fetch_add(1, Ordering::Relaxed)
+        );
+
+        info!("Publishing to MQTT broker {host} under {topic_prefix}/");
+        thread::spawn(move || run(&host, &topic_prefix, &client_id, &receiver));
+
+        Self { sender }
+    }
+
+    pub fn publish(&self, temp: f32, state: Option<u8>) {
+        match self.sender.try_send(Update { temp, state }) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => debug!("MQTT publisher busy, dropping update"),
+            Err(TrySendError::Disconnected(_)) => error!("MQTT publisher stopped"),
+        }
+    }
+}
+
+fn run(host: &str, topic_prefix: &str, client_id: &str, receiver: &Receiver<Update>) {
+    let mut stream: Option<TcpStream> = None;
+    let mut failing = false;
+
+    loop {
+        let update = match receiver.recv_timeout(KEEP_ALIVE) {
+            Ok(update) => update,
+            Err(RecvTimeoutError::Timeout) => {
+                if let Some(connected) = stream.as_mut()
+                    && let Err(err) = ping(connected)
+                {
+                    warn!("MQTT broker {host} didn't answer the ping: {err}, reconnecting");
+                    stream = None;
+                }
+                continue;
+            }
+            Err(RecvTimeoutError::Disconnected) => return,
+        };
+
+        if stream.is_none() {
+            match connect(host, client_id) {
+                Ok(connected) => {
+                    info!("Connected to MQTT broker {host}");
+                    failing = false;
+                    stream = Some(connected);
+                }
+                Err(err) if failing => {
+                    debug!("Still can't connect to MQTT broker {host}: {err}");
+                    continue;
+                }
+                Err(err) => {
+                    warn!("Can't connect to MQTT broker {host}: {err}, will retry");
+                    failing = true;
+                    continue;
+                }
+            }
+        }
+
+        if let Some(connected) = stream.as_mut()
+            && let Err(err) = publish_update(connected, topic_prefix, &update)
+        {
+            warn!("Can't publish to MQTT broker {host}: {err}, reconnecting");
+            stream = None;
+        }
+    }
+}
+
+fn connect(host: &str, client_id: &str) -> io::Result<TcpStream> {
+    let addr = host
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no address found"))?;
+    let mut stream = TcpStream::connect_timeout(&addr, TIMEOUT)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+
+    stream.write_all(&connect_packet(client_id))?;
+
+    let mut connack = [0u8; 4];
+    stream.read_exact(&mut connack)?;
+    match connack {
+        [0x20, 0x02, _, 0] => Ok(stream),
+        [0x20, 0x02, _, code] => Err(io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            format!("connection refused with code {code}"),
+        )),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "unexpected CONNACK",
+        )),
+    }
+}
+
+fn ping(stream: &mut TcpStream) -> io::Result<()> {
+    stream.write_all(&[0xc0, 0x00])?;
+    let mut pingresp = [0u8; 2];
+    stream.read_exact(&mut pingresp)?;
+    if pingresp == [0xd0, 0x00] {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "unexpected PINGRESP",
+        ))
+    }
+}
+
+fn publish_update(stream: &mut TcpStream, topic_prefix: &str, update: &Update) -> io::Result<()> {
+    let temp = format!("{:.2}", update.temp);
+    stream.write_all(&publish_packet(
+        &format!("{topic_prefix}/temperature"),
+        temp.as_bytes(),
+    ))?;
+
+    if let Some(state) = update.state {
+        stream.write_all(&publish_packet(
+            &format!("{topic_prefix}/state"),
+            state.to_string().as_bytes(),
+        ))?;
+    }
+
+    Ok(())
+}
+
+fn encode_remaining_length(packet: &mut Vec<u8>, mut len: usize) {
+    loop {
+        // `len % 128` always fits in 7 bits
+        let mut byte = u8::try_from(len % 128).unwrap_or_default();
+        len /= 128;
+        if len > 0 {
+            byte |= 0x80;
+        }
+        packet.push(byte);
+        if len == 0 {
+            break;
+        }
+    }
+}
+
+fn encode_string(buf: &mut Vec<u8>, s: &str) {
+    let len = u16::try_from(s.len()).unwrap_or(u16::MAX);
+    buf.extend_from_slice(&len.to_be_bytes());
+    buf.extend_from_slice(&s.as_bytes()[..usize::from(len)]);
+}
+
+fn packet(header: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![header];
+    encode_remaining_length(&mut packet, body.len());
+    packet.extend_from_slice(body);
+    packet
+}
+
+// MQTT 3.1.1 CONNECT with a clean session and `KEEP_ALIVE`
+fn connect_packet(client_id: &str) -> Vec<u8> {
+    let mut body = Vec::new();
+    encode_string(&mut body, "MQTT");
+    body.extend_from_slice(&[0x04, 0x02]);
+    let keep_alive = u16::try_from(KEEP_ALIVE.as_secs()).unwrap_or(u16::MAX);
+    body.extend_from_slice(&keep_alive.to_be_bytes());
+    encode_string(&mut body, client_id);
+    packet(0x10, &body)
+}
+
+// QoS 0 PUBLISH with the retain flag set
+fn publish_packet(topic: &str, payload: &[u8]) -> Vec<u8> {
+    let mut body = Vec::new();
+    encode_string(&mut body, topic);
+    body.extend_from_slice(payload);
+    packet(0x31, &body)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use super::*;
+
+    #[test]
+    fn test_remaining_length_encoding() {
+        for (len, expected) in [
+            (0, vec![0x00]),
+            (127, vec![0x7f]),
+            (128, vec![0x80, 0x01]),
+            (16_383, vec![0xff, 0x7f]),
+            (16_384, vec![0x80, 0x80, 0x01]),
+        ] {
+            let mut buf = Vec::new();
+            encode_remaining_length(&mut buf, len);
+            assert_eq!(buf, expected);
+        }
+    }
+
+    #[test]
+    fn test_connect_packet() {
+        assert_eq!(
+            connect_packet("fan"),
+            [
+                0x10, 15, 0, 4, b'M', b'Q', b'T', b'T', 4, 0x02, 0, 60, 0, 3, b'f', b'a', b'n'
+            ]
+        );
+    }
+
+    #[test]
+    fn test_publish_packet_is_retained() {
+        assert_eq!(
+            publish_packet("a/b", b"42"),
+            [0x31, 7, 0, 3, b'a', b'/', b'b', b'4', b'2']
+        );
+    }
+
+    #[test]
+    fn test_publisher_sends_retained_updates() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let host = listener.local_addr().unwrap().to_string();
+
+        let publisher = Publisher::new(&host, "home/fan/");
+        publisher.publish(52.345, Some(3));
+
+        let (mut stream, _) = listener.accept().unwrap();
+        stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+
+        let mut header = [0u8; 2];
+        stream.read_exact(&mut header).unwrap();
+        assert_eq!(header[0], 0x10);
+        let mut connect = vec![0u8; usize::from(header[1])];
+        stream.read_exact(&mut connect).unwrap();
+        stream.write_all(&[0x20, 0x02, 0x00, 0x00]).unwrap();
+
+        let temperature = publish_packet("home/fan/temperature", b"52.35");
+        let state = publish_packet("home/fan/state", b"3");
+        let mut received = vec![0u8; temperature.len() + state.len()];
+        stream.read_exact(&mut received).unwrap();
+        assert_eq!(received, [temperature, state].concat());
+    }
+
+    #[test]
+    fn test_connect_refused() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let host = listener.local_addr().unwrap().to_string();
+
+        let broker = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut header = [0u8; 2];
+            stream.read_exact(&mut header).unwrap();
+            let mut connect = vec![0u8; usize::from(header[1])];
+            stream.read_exact(&mut connect).unwrap();
+            // Not authorized
+            stream.write_all(&[0x20, 0x02, 0x00, 0x05]).unwrap();
+        });
+
+        let err = connect(&host, "fan").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+        broker.join().unwrap();
+    }
+
+    #[test]
+    fn test_ping() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let broker = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut pingreq = [0u8; 2];
+            stream.read_exact(&mut pingreq).unwrap();
+            assert_eq!(pingreq, [0xc0, 0x00]);
+            stream.write_all(&[0xd0, 0x00]).unwrap();
+        });
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+        ping(&mut stream).unwrap();
+        broker.join().unwrap();
+    }
+}
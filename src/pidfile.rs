@@ -0,0 +1,107 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process,
+};
+
+use log::{info, warn};
+
+// Written at startup and removed when dropped, so a second daemon refuses
+// to drive the same fan.
+pub struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        Self::create_with_pid(path, process::id())
+    }
+
+    fn create_with_pid(path: &Path, pid: u32) -> io::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(content) => match content.trim().parse::<u32>() {
+                Ok(other) if other != pid && is_running(other) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AlreadyExists,
+                        format!("{} belongs to running process {other}", path.display()),
+                    ));
+                }
+                _ => warn!("Replacing stale PID file {}", path.display()),
+            },
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+
+        fs::write(path, format!("{pid}\n"))?;
+        info!("PID file: {}", path.display());
+        Ok(Self {
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+fn is_running(pid: u32) -> bool {
+    Path::new("/proc").join(pid.to_string()).exists()
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pid_file_is_removed_on_drop() {
+        let dir = std::env::temp_dir().join("test_pidfile_drop");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("cm3588-fan.pid");
+
+        let pid_file = PidFile::create(&path).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            format!("{}\n", process::id())
+        );
+
+        drop(pid_file);
+        assert!(!path.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_stale_pid_file_is_replaced() {
+        let dir = std::env::temp_dir().join("test_pidfile_stale");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("cm3588-fan.pid");
+
+        // Above the kernel's pid_max, so never a live process
+        fs::write(&path, "4194305\n").unwrap();
+        let pid_file = PidFile::create_with_pid(&path, 42).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "42\n");
+
+        drop(pid_file);
+        fs::write(&path, "garbage").unwrap();
+        drop(PidFile::create_with_pid(&path, 42).unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_live_pid_file_is_refused() {
+        let dir = std::env::temp_dir().join("test_pidfile_live");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("cm3588-fan.pid");
+
+        fs::write(&path, format!("{}\n", process::id())).unwrap();
+        let err = PidFile::create_with_pid(&path, 42).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            format!("{}\n", process::id())
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
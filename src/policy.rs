@@ -0,0 +1,468 @@
+// Fan speed decisions from plain numbers. Only `core` and `log` are used,
+// without I/O or allocations, so the policy can be reused in a simulator or
+// on a microcontroller. Reading the devices and the config is left to `fan`.
+
+use log::trace;
+
+// One slot per state of the CM3588 fan, 0 to 5
+pub const MAX_LEVEL: usize = 6;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CurveMode {
+    #[default]
+    Step,
+    Linear,
+}
+
+// State picked by a temperature exactly on a slot threshold.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BoundaryBias {
+    Low,
+    #[default]
+    High,
+}
+
+// Everything a decision depends on besides the temperature and the last
+// state.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Policy<'a> {
+    pub min_temp: f32,
+    pub max_temp: f32,
+    pub min_state: u8,
+    // `MAX_STATE`, or the device max state when unset
+    pub max_state: u8,
+    // Forced at the critical temperature whatever `MAX_STATE` is
+    pub device_max_state: u8,
+    pub fan_off_temp: Option<f32>,
+    pub critical_temp: Option<f32>,
+    pub fixed_state: Option<u8>,
+    pub hysteresis: f32,
+    pub curve_mode: CurveMode,
+    pub boundary_bias: BoundaryBias,
+    pub slots: &'a [Option<(u8, f32)>],
+    // Ascending `(temp, state)` points replacing the thresholds and slots
+    pub curve: Option<&'a [(f32, u8)]>,
+}
+
+impl Policy<'_> {
+    #[must_use]
+    pub fn is_critical(&self, temp: f32) -> bool {
+        self.critical_temp
+            .is_some_and(|critical_temp| temp >= critical_temp)
+    }
+
+    // State that doesn't depend on the curve, at the critical temperature
+    // or with `FIXED_STATE`.
+    #[must_use]
+    pub fn pinned_state(&self, temp: f32) -> Option<u8> {
+        if self.is_critical(temp) {
+            return Some(self.device_max_state);
+        }
+        self.fixed_state.map(|fixed_state| {
+            trace!("Fixed state {fixed_state} desired");
+            fixed_state.min(self.device_max_state)
+        })
+    }
+
+    #[must_use]
+    pub fn choose_speed(&self, temp: f32, last_state: Option<u8>) -> u8 {
+        if let Some(state) = self.pinned_state(temp) {
+            return state;
+        }
+
+        let speed = self.speed_for_temp(temp);
+
+        // Stepping up is immediate, stepping down only happens once the
+        // temperature dropped `hysteresis` degrees below the slot threshold.
+        match last_state {
+            Some(last_state) if speed < last_state => {
+                let held = self.speed_for_temp(temp + self.hysteresis).min(last_state);
+                if held != speed {
+                    trace!("Holding state {held} within hysteresis band");
+                }
+                held
+            }
+            _ => speed,
+        }
+    }
+
+    #[must_use]
+    pub fn speed_for_temp(&self, temp: f32) -> u8 {
+        if let Some(curve) = self.curve
+            && self.fan_off_temp.is_none_or(|off| temp >= off)
+        {
+            trace!("Desired state from curve");
+            return interpolate_curve(curve, temp);
+        }
+
+        match temp {
+            t if self.fan_off_temp.is_some_and(|off| t < off) => {
+                trace!("Fan off desired");
+                0
+            }
+            // A thermostat whatever the curve mode, the slot math has
+            // nothing to spread over a single step
+            t if self.is_on_off() => {
+                let on_temp = self.on_temp();
+                let on = match self.boundary_bias {
+                    BoundaryBias::Low => t > on_temp,
+                    BoundaryBias::High => t >= on_temp,
+                };
+                trace!("On/off fan, {} desired", if on { "on" } else { "off" });
+                if on { self.max_state } else { self.min_state }
+            }
+            t if t < self.min_temp => {
+                trace!("Min state desired");
+                self.min_state
+            }
+            t if t <= self.max_temp => match self.curve_mode {
+                // Still proportional when there are no slots to step through
+                CurveMode::Step if self.slots.iter().all(Option::is_none) => {
+                    trace!("No slots, desired state interpolated");
+                    self.interpolate_speed(temp)
+                }
+                CurveMode::Step => {
+                    trace!("Desired state in slots");
+                    self.slots
+                        .iter()
+                        .flatten()
+                        .rev()
+                        .find(|(_, slot_temp)| match self.boundary_bias {
+                            BoundaryBias::Low => *slot_temp < temp,
+                            BoundaryBias::High => *slot_temp <= temp,
+                        })
+                        .map_or(self.min_state, |(state, _)| *state)
+                }
+                CurveMode::Linear => {
+                    trace!("Desired state interpolated");
+                    self.interpolate_speed(temp)
+                }
+            },
+            _ => {
+                trace!("Max state desired {}", self.max_state);
+                self.max_state
+            }
+        }
+    }
+
+    // A single state above `min_state`, e.g. a fan with a max state of 1.
+    #[must_use]
+    pub const fn is_on_off(&self) -> bool {
+        self.max_state.saturating_sub(self.min_state) == 1
+    }
+
+    // `min_temp`, unless `SLOTS` moved the slot of the on state.
+    fn on_temp(&self) -> f32 {
+        self.slots
+            .iter()
+            .flatten()
+            .find(|(state, _)| *state == self.max_state)
+            .map_or(self.min_temp, |(_, temp)| *temp)
+    }
+
+    fn interpolate_speed(&self, temp: f32) -> u8 {
+        let span = self.max_state.saturating_sub(self.min_state);
+        let range = self.max_temp - self.min_temp;
+        if span == 0 || range <= 0.0 {
+            return self.min_state;
+        }
+
+        let ratio = ((temp - self.min_temp) / range).clamp(0.0, 1.0);
+        // ratio is clamped to [0, 1] so the offset always fits in [0, span]
+        self.min_state + round(ratio * f32::from(span))
+    }
+}
+
+// Evenly spread slots from `min_temp` for the state above `min_state` to
+// `max_temp` for `max_state`.
+#[must_use]
+#[allow(clippy::suboptimal_flops)]
+pub fn calculate_slots(
+    min_temp: f32,
+    max_temp: f32,
+    min_state: u8,
+    max_state: u8,
+) -> [Option<(u8, f32)>; MAX_LEVEL] {
+    let num_slots: usize = max_state.saturating_sub(min_state).into();
+    let step = slot_step(min_temp, max_temp, min_state, max_state);
+
+    trace!("Calculate slots, min_state: {min_state}, num_slots: {num_slots}, step: {step}");
+
+    let mut results = [None; MAX_LEVEL];
+
+    for (i, result) in results
+        .iter_mut()
+        .enumerate()
+        .take(num_slots.min(MAX_LEVEL))
+    {
+        let state = min_state.saturating_add(u8::try_from(i).unwrap() + 1);
+
+        // `mul_add` isn't available without `std`
+        let value = if num_slots <= 1 {
+            min_temp
+        } else {
+            i as f32 * step + min_temp
+        };
+
+        *result = Some((state, value));
+    }
+
+    results
+}
+
+// Temperature between two computed slots, 0 with a single slot.
+#[must_use]
+pub fn slot_step(min_temp: f32, max_temp: f32, min_state: u8, max_state: u8) -> f32 {
+    let num_slots: usize = max_state.saturating_sub(min_state).into();
+    if num_slots <= 1 {
+        0.0
+    } else {
+        (max_temp - min_temp) / (num_slots - 1) as f32
+    }
+}
+
+// Points are ascending by temperature, the first and last states hold
+// outside of the curve.
+#[must_use]
+#[allow(clippy::suboptimal_flops)]
+pub fn interpolate_curve(curve: &[(f32, u8)], temp: f32) -> u8 {
+    let (Some(&(first_temp, first_state)), Some(&(_, last_state))) = (curve.first(), curve.last())
+    else {
+        return 0;
+    };
+    if temp <= first_temp {
+        return first_state;
+    }
+
+    curve
+        .windows(2)
+        .find(|pair| temp < pair[1].0)
+        .map_or(last_state, |pair| {
+            let ((low_temp, low_state), (high_temp, high_state)) = (pair[0], pair[1]);
+            let ratio = (temp - low_temp) / (high_temp - low_temp);
+            // interpolating between two u8 states always stays within u8
+            round(ratio * (f32::from(high_state) - f32::from(low_state)) + f32::from(low_state))
+        })
+}
+
+#[must_use]
+pub fn state_to_duty(state: u8, period: u32, max_state: u8) -> u32 {
+    if max_state == 0 {
+        return 0;
+    }
+    let duty = u64::from(period) * u64::from(state.min(max_state)) / u64::from(max_state);
+    u32::try_from(duty).unwrap_or(period)
+}
+
+#[must_use]
+pub fn duty_to_state(duty: u32, period: u32, max_state: u8) -> u8 {
+    if period == 0 {
+        return 0;
+    }
+    let duty = u64::from(duty.min(period));
+    let state = (duty * u64::from(max_state) + u64::from(period) / 2) / u64::from(period);
+    u8::try_from(state).unwrap_or(max_state)
+}
+
+// Half away from zero like `f32::round`, which needs `std`. Callers only
+// pass values within [0, 255].
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn round(value: f32) -> u8 {
+    let whole = value as u8;
+    if value - f32::from(whole) >= 0.5 {
+        whole.saturating_add(1)
+    } else {
+        whole
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::float_cmp)]
+mod tests {
+    use super::*;
+
+    const SLOTS: [Option<(u8, f32)>; MAX_LEVEL] = [
+        Some((1, 45.0)),
+        Some((2, 50.0)),
+        Some((3, 55.0)),
+        Some((4, 60.0)),
+        Some((5, 65.0)),
+        None,
+    ];
+
+    fn policy() -> Policy<'static> {
+        Policy {
+            min_temp: 45.0,
+            max_temp: 65.0,
+            min_state: 0,
+            max_state: 5,
+            device_max_state: 5,
+            fan_off_temp: None,
+            critical_temp: None,
+            fixed_state: None,
+            hysteresis: 2.0,
+            curve_mode: CurveMode::Step,
+            boundary_bias: BoundaryBias::High,
+            slots: &SLOTS,
+            curve: None,
+        }
+    }
+
+    #[test]
+    fn test_calculate_slots() {
+        assert_eq!(calculate_slots(45.0, 65.0, 0, 5), SLOTS);
+        let slots = calculate_slots(40.0, 60.0, 2, 4);
+        assert_eq!(slots[..3], [Some((3, 40.0)), Some((4, 60.0)), None]);
+        assert_eq!(calculate_slots(45.0, 65.0, 5, 5), [None; MAX_LEVEL]);
+        assert_eq!(slot_step(45.0, 65.0, 0, 5), 5.0);
+        assert_eq!(slot_step(45.0, 65.0, 4, 5), 0.0);
+    }
+
+    #[test]
+    fn test_choose_speed() {
+        let policy = policy();
+        assert_eq!(policy.choose_speed(30.0, None), 0);
+        assert_eq!(policy.choose_speed(52.0, None), 2);
+        assert_eq!(policy.choose_speed(70.0, None), 5);
+        // Within the hysteresis band of state 3
+        assert_eq!(policy.choose_speed(54.0, Some(3)), 3);
+        assert_eq!(policy.choose_speed(52.0, Some(3)), 2);
+    }
+
+    #[test]
+    fn test_boundary_bias() {
+        let high = policy();
+        let low = Policy {
+            boundary_bias: BoundaryBias::Low,
+            ..policy()
+        };
+        for (temp, high_state, low_state) in [
+            (45.0, 1, 0),
+            (50.0, 2, 1),
+            (55.0, 3, 2),
+            (65.0, 5, 4),
+            (55.5, 3, 3),
+        ] {
+            assert_eq!(high.speed_for_temp(temp), high_state, "{temp}");
+            assert_eq!(low.speed_for_temp(temp), low_state, "{temp}");
+        }
+        // Stepping down lands on the boundary of state 3
+        assert_eq!(high.choose_speed(53.0, Some(3)), 3);
+        assert_eq!(low.choose_speed(53.0, Some(3)), 2);
+    }
+
+    #[test]
+    fn test_on_off() {
+        let slots = calculate_slots(45.0, 65.0, 0, 1);
+        assert_eq!(slots[..2], [Some((1, 45.0)), None]);
+        for curve_mode in [CurveMode::Step, CurveMode::Linear] {
+            let policy = Policy {
+                max_state: 1,
+                device_max_state: 1,
+                curve_mode,
+                slots: &slots,
+                ..policy()
+            };
+            assert!(policy.is_on_off());
+            assert_eq!(policy.choose_speed(44.9, None), 0);
+            assert_eq!(policy.choose_speed(45.0, None), 1);
+            assert_eq!(policy.choose_speed(55.0, None), 1);
+            assert_eq!(policy.choose_speed(80.0, None), 1);
+            // Off only once 2°C below the threshold
+            assert_eq!(policy.choose_speed(43.5, Some(1)), 1);
+            assert_eq!(policy.choose_speed(42.9, Some(1)), 0);
+            assert_eq!(policy.choose_speed(44.0, Some(0)), 0);
+        }
+
+        // Without slots, or with a slot moved by `SLOTS`
+        let policy = Policy {
+            max_state: 1,
+            device_max_state: 1,
+            slots: &[],
+            ..policy()
+        };
+        assert_eq!(policy.speed_for_temp(45.0), 1);
+        let moved = [Some((1, 50.0))];
+        let policy = Policy {
+            slots: &moved,
+            ..policy
+        };
+        assert_eq!(policy.speed_for_temp(49.0), 0);
+        assert_eq!(policy.speed_for_temp(50.0), 1);
+        let policy = Policy {
+            boundary_bias: BoundaryBias::Low,
+            ..policy
+        };
+        assert_eq!(policy.speed_for_temp(50.0), 0);
+    }
+
+    #[test]
+    fn test_choose_speed_pinned() {
+        let policy = Policy {
+            critical_temp: Some(80.0),
+            fixed_state: Some(2),
+            max_state: 4,
+            ..policy()
+        };
+        assert_eq!(policy.choose_speed(60.0, None), 2);
+        assert_eq!(policy.choose_speed(85.0, None), 5);
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let policy = Policy {
+            curve_mode: CurveMode::Linear,
+            ..policy()
+        };
+        assert_eq!(policy.speed_for_temp(45.0), 0);
+        assert_eq!(policy.speed_for_temp(55.0), 3);
+        assert_eq!(policy.speed_for_temp(65.0), 5);
+
+        let curve = [(40.0, 1), (60.0, 3)];
+        assert_eq!(interpolate_curve(&curve, 30.0), 1);
+        assert_eq!(interpolate_curve(&curve, 45.0), 2);
+        assert_eq!(interpolate_curve(&curve, 70.0), 3);
+    }
+
+    #[test]
+    fn test_interpolate_without_slots() {
+        for slots in [&[][..], &[None; MAX_LEVEL]] {
+            let policy = Policy { slots, ..policy() };
+            assert_eq!(policy.speed_for_temp(40.0), 0);
+            assert_eq!(policy.speed_for_temp(60.0), 4);
+            assert_eq!(policy.speed_for_temp(70.0), 5);
+        }
+
+        // Nothing to interpolate over a single state
+        let policy = Policy {
+            slots: &[],
+            min_state: 3,
+            max_state: 3,
+            ..policy()
+        };
+        assert_eq!(policy.speed_for_temp(60.0), 3);
+    }
+
+    #[test]
+    fn test_pwm_duty_mapping() {
+        assert_eq!(state_to_duty(0, 40_000, 5), 0);
+        assert_eq!(state_to_duty(2, 40_000, 5), 16_000);
+        assert_eq!(state_to_duty(5, 40_000, 5), 40_000);
+        assert_eq!(state_to_duty(9, 40_000, 5), 40_000);
+
+        for state in 0..=5 {
+            let duty = state_to_duty(state, 40_000, 5);
+            assert_eq!(duty_to_state(duty, 40_000, 5), state);
+        }
+        assert_eq!(duty_to_state(17_000, 40_000, 5), 2);
+    }
+
+    #[test]
+    fn test_round() {
+        assert_eq!(round(0.0), 0);
+        assert_eq!(round(0.49), 0);
+        assert_eq!(round(0.5), 1);
+        assert_eq!(round(2.5), 3);
+        assert_eq!(round(254.9), 255);
+    }
+}
@@ -0,0 +1,105 @@
+use std::{
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Debug)]
+pub struct Status {
+    pub temp: f32,
+    pub current_state: Option<u8>,
+    pub desired_state: u8,
+    pub max_state: u8,
+    pub timestamp: u64,
+}
+
+impl Status {
+    #[must_use]
+    pub fn new(temp: f32, current_state: Option<u8>, desired_state: u8, max_state: u8) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+
+        Self {
+            temp,
+            current_state,
+            desired_state,
+            max_state,
+            timestamp,
+        }
+    }
+
+    #[must_use]
+    pub fn to_json(&self) -> String {
+        let current_state = self
+            .current_state
+            .map_or_else(|| "null".to_owned(), |state| state.to_string());
+
+        format!(
+            r#"{{"temp":{:.2},"current_state":{current_state},"desired_state":{},"max_state":{},"timestamp":{}}}"#,
+            self.temp, self.desired_state, self.max_state, self.timestamp
+        )
+    }
+
+    pub fn write_atomic(&self, path: &Path) -> io::Result<()> {
+        let mut tmp = OsString::from(path.as_os_str());
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+
+        fs::write(&tmp, self.to_json())?;
+        fs::rename(&tmp, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_to_json() {
+        let status = Status {
+            temp: 52.345,
+            current_state: Some(2),
+            desired_state: 3,
+            max_state: 5,
+            timestamp: 1_700_000_000,
+        };
+
+        assert_eq!(
+            status.to_json(),
+            r#"{"temp":52.35,"current_state":2,"desired_state":3,"max_state":5,"timestamp":1700000000}"#
+        );
+    }
+
+    #[test]
+    fn test_status_to_json_without_current_state() {
+        let status = Status {
+            temp: -4.0,
+            current_state: None,
+            desired_state: 0,
+            max_state: 5,
+            timestamp: 0,
+        };
+
+        assert_eq!(
+            status.to_json(),
+            r#"{"temp":-4.00,"current_state":null,"desired_state":0,"max_state":5,"timestamp":0}"#
+        );
+    }
+
+    #[test]
+    fn test_status_write_atomic() {
+        let dir = std::env::temp_dir().join("test_status_write_atomic");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("status.json");
+
+        let status = Status::new(50.0, Some(2), 2, 5);
+        status.write_atomic(&path).unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), status.to_json());
+        assert!(!dir.join("status.json.tmp").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,310 @@
+#[cfg(any(test, feature = "testing"))]
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    sync::{Mutex, PoisonError},
+};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+// Every access to the devices, discovery included, so that a whole scenario
+// can run against `MockSysfs` without touching the disk.
+pub trait SysfsAccess: Send + Sync {
+    fn read_to_string(&self, path: &Path) -> io::Result<String>;
+    fn write(&self, path: &Path, contents: &str) -> io::Result<()>;
+    // Whether `path` could be written, without writing anything.
+    fn check_write(&self, path: &Path) -> io::Result<()>;
+    // Full paths of the entries of the directory, in no particular order.
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>>;
+    fn exists(&self, path: &Path) -> bool;
+    // Resolves symlinks such as the `cdevN` links of a thermal zone.
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf>;
+}
+
+pub struct RealSysfs;
+
+impl SysfsAccess for RealSysfs {
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        fs::read_to_string(path)
+    }
+
+    fn write(&self, path: &Path, contents: &str) -> io::Result<()> {
+        fs::write(path, contents)
+    }
+
+    // Opening a sysfs attribute doesn't trigger its store, only writing does
+    fn check_write(&self, path: &Path) -> io::Result<()> {
+        fs::OpenOptions::new().write(true).open(path).map(drop)
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        Ok(fs::read_dir(path)?
+            .flatten()
+            .map(|entry| entry.path())
+            .collect())
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        fs::canonicalize(path)
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
+fn not_found(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("{} not found", path.display()),
+    )
+}
+
+// In-memory files, to drive a fan and a thermal zone without touching the
+// disk. Directories exist as long as a file or a link is below them.
+#[cfg(any(test, feature = "testing"))]
+#[derive(Default)]
+pub struct MockSysfs {
+    files: Mutex<HashMap<PathBuf, String>>,
+    links: Mutex<HashMap<PathBuf, PathBuf>>,
+    read_only: Mutex<HashSet<PathBuf>>,
+    failing_reads: Mutex<HashMap<PathBuf, u32>>,
+    writes: Mutex<Vec<(PathBuf, String)>>,
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl MockSysfs {
+    pub fn set(&self, path: impl Into<PathBuf>, contents: impl Into<String>) {
+        self.files
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(path.into(), contents.into());
+    }
+
+    // Symlink at `path` pointing to `target`.
+    pub fn link(&self, path: impl Into<PathBuf>, target: impl Into<PathBuf>) {
+        self.links
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(path.into(), target.into());
+    }
+
+    fn paths(&self) -> Vec<PathBuf> {
+        let files = self.files.lock().unwrap_or_else(PoisonError::into_inner);
+        let links = self.links.lock().unwrap_or_else(PoisonError::into_inner);
+        files.keys().chain(links.keys()).cloned().collect()
+    }
+
+    pub fn remove(&self, path: &Path) {
+        self.files
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(path);
+    }
+
+    // Writes to `path` fail like they do for an unprivileged user.
+    pub fn set_read_only(&self, path: impl Into<PathBuf>) {
+        self.read_only
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(path.into());
+    }
+
+    // The next `count` reads of `path` return garbage, like a glitching
+    // sensor.
+    pub fn fail_reads(&self, path: impl Into<PathBuf>, count: u32) {
+        self.failing_reads
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(path.into(), count);
+    }
+
+    // Every successful write so far, oldest first.
+    #[must_use]
+    pub fn writes(&self) -> Vec<(PathBuf, String)> {
+        self.writes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    fn check_read_only(&self, path: &Path) -> io::Result<()> {
+        if self
+            .read_only
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .contains(path)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("{} is read-only", path.display()),
+            ));
+        }
+        Ok(())
+    }
+
+    #[must_use]
+    pub fn get(&self, path: &Path) -> Option<String> {
+        self.files
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(path)
+            .cloned()
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl SysfsAccess for MockSysfs {
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        if let Some(count) = self
+            .failing_reads
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get_mut(path)
+            .filter(|count| **count > 0)
+        {
+            *count -= 1;
+            return Ok("garbage".to_owned());
+        }
+        self.get(path).ok_or_else(|| not_found(path))
+    }
+
+    fn write(&self, path: &Path, contents: &str) -> io::Result<()> {
+        self.check_read_only(path)?;
+        self.set(path, contents);
+        self.writes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push((path.to_path_buf(), contents.to_owned()));
+        Ok(())
+    }
+
+    fn check_write(&self, path: &Path) -> io::Result<()> {
+        self.check_read_only(path)?;
+        self.get(path).map(drop).ok_or_else(|| not_found(path))
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        let entries: BTreeSet<PathBuf> = self
+            .paths()
+            .iter()
+            .filter_map(|file| {
+                let name = file.strip_prefix(path).ok()?.components().next()?;
+                Some(path.join(name))
+            })
+            .collect();
+        if entries.is_empty() {
+            return Err(not_found(path));
+        }
+        Ok(entries.into_iter().collect())
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.paths().iter().any(|file| file.starts_with(path))
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        let target = self
+            .links
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(path)
+            .cloned();
+        match target {
+            Some(target) => Ok(target),
+            None if self.exists(path) => Ok(path.to_path_buf()),
+            None => Err(not_found(path)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mock_sysfs() {
+        let sysfs = MockSysfs::default();
+        let path = Path::new("thermal_zone0/temp");
+        assert_eq!(
+            sysfs.read_to_string(path).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+
+        sysfs.write(path, "45000").unwrap();
+        assert_eq!(sysfs.read_to_string(path).unwrap(), "45000");
+        assert_eq!(
+            sysfs.writes(),
+            vec![(path.to_path_buf(), "45000".to_owned())]
+        );
+
+        sysfs.fail_reads(path, 1);
+        assert_eq!(sysfs.read_to_string(path).unwrap(), "garbage");
+        assert_eq!(sysfs.read_to_string(path).unwrap(), "45000");
+
+        sysfs.check_write(path).unwrap();
+        sysfs.set_read_only(path);
+        assert_eq!(
+            sysfs.check_write(path).unwrap_err().kind(),
+            io::ErrorKind::PermissionDenied
+        );
+        assert_eq!(
+            sysfs.write(path, "50000").unwrap_err().kind(),
+            io::ErrorKind::PermissionDenied
+        );
+        assert_eq!(sysfs.read_to_string(path).unwrap(), "45000");
+
+        sysfs.remove(path);
+        assert!(sysfs.get(path).is_none());
+    }
+
+    #[test]
+    fn test_mock_sysfs_directories() {
+        let sysfs = MockSysfs::default();
+        sysfs.set("thermal/thermal_zone0/temp", "45000");
+        sysfs.set("thermal/thermal_zone0/type", "soc-thermal");
+        sysfs.set("thermal/cooling_device0/cur_state", "0");
+        sysfs.link("thermal/thermal_zone0/cdev0", "thermal/cooling_device0");
+
+        let mut entries = sysfs.read_dir(Path::new("thermal")).unwrap();
+        entries.sort();
+        assert_eq!(
+            entries,
+            vec![
+                PathBuf::from("thermal/cooling_device0"),
+                PathBuf::from("thermal/thermal_zone0"),
+            ]
+        );
+        assert_eq!(
+            sysfs
+                .read_dir(Path::new("thermal/thermal_zone0"))
+                .unwrap()
+                .len(),
+            3
+        );
+        assert!(sysfs.read_dir(Path::new("pwm")).is_err());
+
+        assert!(sysfs.exists(Path::new("thermal/thermal_zone0")));
+        assert!(!sysfs.exists(Path::new("thermal/thermal_zone1")));
+
+        assert_eq!(
+            sysfs
+                .canonicalize(Path::new("thermal/thermal_zone0/cdev0"))
+                .unwrap(),
+            PathBuf::from("thermal/cooling_device0")
+        );
+        assert_eq!(
+            sysfs
+                .canonicalize(Path::new("thermal/cooling_device0"))
+                .unwrap(),
+            PathBuf::from("thermal/cooling_device0")
+        );
+        assert!(
+            sysfs
+                .canonicalize(Path::new("thermal/thermal_zone1"))
+                .is_err()
+        );
+    }
+}
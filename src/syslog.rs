@@ -0,0 +1,83 @@
+use std::{io::Write, sync::Mutex};
+
+use ::syslog::{Facility, Formatter3164, LogFormat, Logger, LoggerBackend, Severity};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+use crate::config::LogContext;
+
+// Sends the logs to the local syslog daemon with the crate name as the ident.
+pub struct Syslog {
+    logger: Mutex<Logger<LoggerBackend, Formatter3164>>,
+}
+
+impl Syslog {
+    pub fn init(level_filter: LevelFilter) -> Result<(), String> {
+        let formatter = Formatter3164 {
+            facility: Facility::LOG_DAEMON,
+            hostname: None,
+            process: env!("CARGO_PKG_NAME").to_owned(),
+            pid: std::process::id(),
+        };
+        let logger = ::syslog::unix(formatter).map_err(|err| err.to_string())?;
+        log::set_boxed_logger(Box::new(Self {
+            logger: Mutex::new(logger),
+        }))
+        .map_err(|err| err.to_string())?;
+        log::set_max_level(level_filter);
+        Ok(())
+    }
+}
+
+// Trace has no syslog priority of its own, it's sent as debug.
+#[must_use]
+pub const fn severity(level: Level) -> Severity {
+    match level {
+        Level::Error => Severity::LOG_ERR,
+        Level::Warn => Severity::LOG_WARNING,
+        Level::Info => Severity::LOG_INFO,
+        Level::Debug | Level::Trace => Severity::LOG_DEBUG,
+    }
+}
+
+impl Log for Syslog {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let message = format!("{}{}", LogContext::prefix(), record.args());
+        let Ok(mut logger) = self.logger.lock() else {
+            return;
+        };
+        let Logger { formatter, backend } = &mut *logger;
+        // Nowhere left to report a lost log line
+        let _ = formatter.format(backend, severity(record.level()), message);
+    }
+
+    fn flush(&self) {
+        if let Ok(mut logger) = self.logger.lock() {
+            let _ = logger.backend.flush();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_severity() {
+        for (level, priority) in [
+            (Level::Error, 3),
+            (Level::Warn, 4),
+            (Level::Info, 6),
+            (Level::Debug, 7),
+            (Level::Trace, 7),
+        ] {
+            assert_eq!(severity(level) as u8, priority, "{level}");
+        }
+    }
+}
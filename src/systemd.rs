@@ -0,0 +1,55 @@
+use log::{debug, error, info};
+use sd_notify::NotifyState;
+
+pub struct Notifier {
+    ready: bool,
+    watchdog: bool,
+}
+
+impl Default for Notifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Notifier {
+    #[must_use]
+    pub fn new() -> Self {
+        let mut usec = 0;
+        let watchdog = sd_notify::watchdog_enabled(false, &mut usec);
+        if watchdog {
+            info!("systemd watchdog enabled ({usec}µs)");
+        }
+
+        Self {
+            ready: false,
+            watchdog,
+        }
+    }
+
+    pub fn update(&mut self, controlled: bool) {
+        if !controlled {
+            debug!("Fan not under control, skipping systemd notification");
+            return;
+        }
+
+        if !self.ready {
+            match sd_notify::notify(false, &[NotifyState::Ready]) {
+                Ok(()) => self.ready = true,
+                Err(err) => error!("Can't notify systemd readiness: {err}"),
+            }
+        }
+
+        if self.watchdog
+            && let Err(err) = sd_notify::notify(false, &[NotifyState::Watchdog])
+        {
+            error!("Can't ping systemd watchdog: {err}");
+        }
+    }
+
+    pub fn stopping() {
+        if let Err(err) = sd_notify::notify(false, &[NotifyState::Stopping]) {
+            error!("Can't notify systemd shutdown: {err}");
+        }
+    }
+}
@@ -1,78 +1,385 @@
-use core::fmt;
-use log::info;
+use log::{debug, error, info, warn};
 use std::{
-    fs::{self, File},
-    io::{self, Read},
-    num::ParseFloatError,
-    path::PathBuf,
-    str::Utf8Error,
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError},
+    thread,
+    time::Duration,
 };
 
-use crate::{THERMAL_DIR, config::DEFAULT_MAX_STATE};
+use crate::{
+    config::{Config, TempAggregation, TempScale},
+    error::FanError,
+    sysfs::SysfsAccess,
+};
 
 pub struct Temp {
-    pub path: PathBuf,
+    pub paths: Vec<PathBuf>,
+    pub aggregation: TempAggregation,
+    pub scale: TempScale,
+    // One weight per path when `TEMP_ZONES` is set, empty otherwise
+    pub weights: Vec<f32>,
+    // `TEMP_OFFSET`, added to the aggregated reading
+    pub offset: f32,
+    pub sysfs: Arc<dyn SysfsAccess>,
+    // Scale of each `Auto` zone, kept once a reading settles it
+    pub detected_scales: Mutex<HashMap<PathBuf, TempScale>>,
 }
 
-#[derive(Debug)]
-pub enum Error {
-    Io(io::Error),
-    Parse(ParseFloatError),
-    Utf8Error(Utf8Error),
-}
+pub use crate::policy::MAX_LEVEL;
+
+const THERMAL_ZONE_NAME: &str = "thermal_zone";
+
+const SAMPLE_DELAY: Duration = Duration::from_millis(20);
 
-impl From<io::Error> for Error {
-    fn from(err: io::Error) -> Self {
-        Self::Io(err)
+// Boards can run outdoors below 0°C. Millidegree readings within a degree
+// of 0 would also fit the other scales, so they don't settle one.
+const PLAUSIBLE_TEMP: std::ops::RangeInclusive<f32> = -60.0..=200.0;
+const AMBIGUOUS_TEMP: f32 = 1.0;
+
+impl Temp {
+    pub fn new(config: &Config, sysfs: Arc<dyn SysfsAccess>) -> Result<Self, FanError> {
+        // No fallback, the thermal zones are most likely not what the fan
+        // should follow when a file is given
+        if let Some(path) = &config.temp_file_path {
+            let scale = TempScale::Divisor(config.temp_file_divisor);
+            if let Err(err) = Self::read_temp(sysfs.as_ref(), path, scale) {
+                error!("Invalid temperature file {}: {err}", path.display());
+                return Err(err);
+            }
+            info!(
+                "Temp path: {} (divided by {})",
+                path.display(),
+                config.temp_file_divisor
+            );
+            return Ok(Self {
+                paths: vec![path.clone()],
+                aggregation: config.temp_aggregation,
+                scale,
+                weights: Vec::new(),
+                offset: config.temp_offset,
+                sysfs,
+                detected_scales: Mutex::default(),
+            });
+        }
+
+        if let Some(path) = &config.thermal_zone_path {
+            match Self::read_temp(sysfs.as_ref(), path, config.temp_scale) {
+                Ok(_) => {
+                    info!("Temp path: {}", path.display());
+                    return Ok(Self {
+                        paths: vec![path.clone()],
+                        aggregation: config.temp_aggregation,
+                        scale: config.temp_scale,
+                        weights: Vec::new(),
+                        offset: config.temp_offset,
+                        sysfs,
+                        detected_scales: Mutex::default(),
+                    });
+                }
+                Err(err) => error!(
+                    "Invalid thermal zone path {}: {err}, falling back to auto-detection",
+                    path.display()
+                ),
+            }
+        }
+
+        if let Some(zones) = &config.temp_zones {
+            let weighted = Self::find_weighted_paths(sysfs.as_ref(), &config.thermal_dir(), zones)?;
+            if weighted.is_empty() {
+                warn!("None of the TEMP_ZONES found, falling back to auto-detection");
+            } else {
+                let (paths, weights) = weighted.into_iter().unzip();
+                return Ok(Self {
+                    paths,
+                    aggregation: config.temp_aggregation,
+                    scale: config.temp_scale,
+                    weights,
+                    offset: config.temp_offset,
+                    sysfs,
+                    detected_scales: Mutex::default(),
+                });
+            }
+        }
+
+        let paths = Self::get_temp_paths(config, sysfs.as_ref())?;
+        Ok(Self {
+            paths,
+            aggregation: config.temp_aggregation,
+            scale: config.temp_scale,
+            weights: Vec::new(),
+            offset: config.temp_offset,
+            sysfs,
+            detected_scales: Mutex::default(),
+        })
     }
-}
 
-impl From<ParseFloatError> for Error {
-    fn from(err: ParseFloatError) -> Self {
-        Self::Parse(err)
+    pub fn get_current_temp(&self) -> Result<f32, FanError> {
+        Ok(self.get_aggregated_temp()? + self.offset)
     }
-}
 
-impl From<Utf8Error> for Error {
-    fn from(err: Utf8Error) -> Self {
-        Self::Utf8Error(err)
+    fn get_aggregated_temp(&self) -> Result<f32, FanError> {
+        if !self.weights.is_empty() {
+            return self.get_weighted_temp();
+        }
+
+        let mut paths = self.paths.iter();
+        let first = paths.next().ok_or(FanError::NoThermalZone)?;
+        let mut temp = self.read_zone_temp(first)?;
+
+        match self.aggregation {
+            TempAggregation::First => {}
+            TempAggregation::Max => {
+                for path in paths {
+                    temp = temp.max(self.read_zone_temp(path)?);
+                }
+            }
+            TempAggregation::Mean => {
+                for path in paths {
+                    temp += self.read_zone_temp(path)?;
+                }
+                temp /= self.paths.len() as f32;
+            }
+        }
+
+        Ok(temp)
     }
-}
 
-impl fmt::Display for Error {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Self::Io(e) => write!(f, "IO error: {e}"),
-            Self::Parse(e) => write!(f, "Parse error: {e}"),
-            Self::Utf8Error(e) => write!(f, "UTF-8 error: {e}"),
+    // Lowest `critical` trip point of the zones read, in Celsius.
+    #[must_use]
+    pub fn read_critical_trip(&self) -> Option<f32> {
+        self.paths
+            .iter()
+            .filter_map(|path| Self::find_critical_trip(self.sysfs.as_ref(), path.parent()?))
+            .min_by(f32::total_cmp)
+    }
+
+    // Trip points are `trip_point_N_type` and `trip_point_N_temp` pairs,
+    // always in millidegrees.
+    fn find_critical_trip(sysfs: &dyn SysfsAccess, zone: &Path) -> Option<f32> {
+        sysfs
+            .read_dir(zone)
+            .ok()?
+            .into_iter()
+            .filter_map(|path| {
+                let index = path
+                    .file_name()?
+                    .to_str()?
+                    .strip_prefix("trip_point_")?
+                    .strip_suffix("_type")?
+                    .to_owned();
+                if sysfs.read_to_string(&path).ok()?.trim() != "critical" {
+                    return None;
+                }
+                let temp = sysfs
+                    .read_to_string(&zone.join(format!("trip_point_{index}_temp")))
+                    .ok()?;
+                Some(temp.trim().parse::<f32>().ok()? / 1000.0)
+            })
+            .min_by(f32::total_cmp)
+    }
+
+    fn get_weighted_temp(&self) -> Result<f32, FanError> {
+        let mut sum = 0.0;
+        let mut total_weight = 0.0;
+        for (path, weight) in self.paths.iter().zip(&self.weights) {
+            sum += self.read_zone_temp(path)? * weight;
+            total_weight += weight;
         }
+        if total_weight <= 0.0 {
+            return Err(FanError::NoThermalZone);
+        }
+        Ok(sum / total_weight)
     }
-}
 
-pub const MAX_LEVEL: usize = DEFAULT_MAX_STATE as usize + 1;
+    pub fn get_median_temp(&self, samples: usize) -> Result<f32, FanError> {
+        let mut temps = Vec::with_capacity(samples);
+        for i in 0..samples.max(1) {
+            if i > 0 {
+                thread::sleep(SAMPLE_DELAY);
+            }
+            temps.push(self.get_current_temp()?);
+        }
+        Ok(median(&mut temps))
+    }
 
-const THERMAL_ZONE_NAME: &str = "thermal_zone";
+    fn read_temp(sysfs: &dyn SysfsAccess, path: &Path, scale: TempScale) -> Result<f32, FanError> {
+        let content = sysfs.read_to_string(path)?;
+        Ok(Self::scale_temp(content.trim().parse::<f32>()?, scale))
+    }
 
-impl Temp {
-    pub fn new() -> io::Result<Self> {
-        let path = Self::get_temp_path()?;
-        Ok(Self { path })
+    fn scale_temp(raw: f32, scale: TempScale) -> f32 {
+        match scale {
+            TempScale::Divisor(divisor) => raw / divisor as f32,
+            TempScale::Milli => raw / 1000.0,
+            TempScale::Deci => raw / 10.0,
+            TempScale::Unit => raw,
+            TempScale::Auto => {
+                Self::detect_scale(raw).map_or(raw / 1000.0, |scale| Self::scale_temp(raw, scale))
+            }
+        }
     }
 
-    pub fn get_current_temp(&self) -> Result<f32, Error> {
-        let mut buf = [0u8; 8];
-        let n = File::open(&self.path)?.read(&mut buf)?;
-        let s = std::str::from_utf8(&buf[..n])
-            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
-            .trim();
-        Ok(s.parse::<f32>()? / 1000.0)
+    // Millidegrees are the sysfs convention and always whole numbers, so they
+    // win whenever plausible. None when the reading doesn't settle the scale,
+    // it's then read as millidegrees.
+    fn detect_scale(raw: f32) -> Option<TempScale> {
+        let whole = raw.fract() == 0.0;
+        if whole && (raw / 1000.0).abs() < AMBIGUOUS_TEMP {
+            return None;
+        }
+        [
+            (TempScale::Milli, 1000.0),
+            (TempScale::Unit, 1.0),
+            (TempScale::Deci, 10.0),
+        ]
+        .into_iter()
+        .filter(|&(scale, _)| whole || scale != TempScale::Milli)
+        .find(|&(_, divisor)| PLAUSIBLE_TEMP.contains(&(raw / divisor)))
+        .map(|(scale, _)| scale)
     }
 
-    pub fn get_temp_path() -> io::Result<PathBuf> {
-        for entry in fs::read_dir(THERMAL_DIR)? {
-            let entry = entry?;
-            let path = entry.path();
+    // `Auto` zones keep the scale of their first reading that settles it, so
+    // a later reading near 0 isn't read with another scale.
+    fn read_zone_temp(&self, path: &Path) -> Result<f32, FanError> {
+        if self.scale != TempScale::Auto {
+            return Self::read_temp(self.sysfs.as_ref(), path, self.scale);
+        }
+        let content = self.sysfs.read_to_string(path)?;
+        let raw = content.trim().parse::<f32>()?;
+        let detected = self
+            .detected_scales
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(path)
+            .copied();
+        let scale = detected.or_else(|| {
+            let scale = Self::detect_scale(raw)?;
+            info!("Detected {scale:?} readings on {}", path.display());
+            self.detected_scales
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .insert(path.to_owned(), scale);
+            Some(scale)
+        });
+        Ok(scale.map_or(raw / 1000.0, |scale| Self::scale_temp(raw, scale)))
+    }
 
+    pub fn get_temp_paths(
+        config: &Config,
+        sysfs: &dyn SysfsAccess,
+    ) -> Result<Vec<PathBuf>, FanError> {
+        Self::find_temp_paths(sysfs, &config.thermal_dir(), config)
+    }
+
+    fn find_temp_paths(
+        sysfs: &dyn SysfsAccess,
+        dir: &Path,
+        config: &Config,
+    ) -> Result<Vec<PathBuf>, FanError> {
+        let zones = Self::get_valid_zones(sysfs, dir)?;
+
+        // The first type with a zone wins, so one config works across kernels
+        // naming the zones differently
+        for zone_type in &config.thermal_zone_types {
+            if let Some(zone) = zones.iter().find(|zone| {
+                Self::get_zone_type(sysfs, zone).as_deref() == Some(zone_type.as_str())
+            }) {
+                let temp_path = zone.join("temp");
+                info!("Temp path: {} ({zone_type})", temp_path.display());
+                return Ok(vec![temp_path]);
+            }
+            debug!("No thermal zone of type {zone_type} found");
+        }
+        if !config.thermal_zone_types.is_empty() {
+            warn!(
+                "No thermal zone of type {} found, falling back to auto-detection",
+                config.thermal_zone_types.join(", ")
+            );
+        }
+
+        let count = match config.temp_aggregation {
+            TempAggregation::First => 1,
+            TempAggregation::Max | TempAggregation::Mean => zones.len(),
+        };
+        let paths: Vec<PathBuf> = zones
+            .iter()
+            .take(count)
+            .map(|zone| zone.join("temp"))
+            .collect();
+
+        if paths.is_empty() {
+            return Err(FanError::NoThermalZone);
+        }
+
+        for path in &paths {
+            info!("Temp path: {}", path.display());
+        }
+
+        Ok(paths)
+    }
+
+    fn find_weighted_paths(
+        sysfs: &dyn SysfsAccess,
+        dir: &Path,
+        zones: &[(String, f32)],
+    ) -> Result<Vec<(PathBuf, f32)>, FanError> {
+        let valid_zones = Self::get_valid_zones(sysfs, dir)?;
+
+        Ok(zones
+            .iter()
+            .filter_map(|(zone_type, weight)| {
+                let Some(zone) = valid_zones.iter().find(|zone| {
+                    Self::get_zone_type(sysfs, zone).as_deref() == Some(zone_type.as_str())
+                }) else {
+                    warn!("No thermal zone of type {zone_type} found, ignoring it");
+                    return None;
+                };
+                let temp_path = zone.join("temp");
+                info!(
+                    "Temp path: {} ({zone_type}, weight {weight})",
+                    temp_path.display()
+                );
+                Some((temp_path, *weight))
+            })
+            .collect())
+    }
+
+    // Every thermal zone with its type and temperature, including the ones
+    // that can't be read.
+    #[must_use]
+    pub fn list_zones(
+        sysfs: &dyn SysfsAccess,
+        dir: &Path,
+        scale: TempScale,
+    ) -> Vec<(PathBuf, Option<String>, Option<f32>)> {
+        let Ok(entries) = sysfs.read_dir(dir) else {
+            return Vec::new();
+        };
+        let mut zones: Vec<PathBuf> = entries
+            .into_iter()
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|s| s.to_str())
+                    .is_some_and(|s| s.starts_with(THERMAL_ZONE_NAME))
+            })
+            .collect();
+        zones.sort();
+
+        zones
+            .into_iter()
+            .map(|zone| {
+                let zone_type = Self::get_zone_type(sysfs, &zone);
+                let temp = Self::read_temp(sysfs, &zone.join("temp"), scale).ok();
+                (zone, zone_type, temp)
+            })
+            .collect()
+    }
+
+    fn get_valid_zones(sysfs: &dyn SysfsAccess, dir: &Path) -> Result<Vec<PathBuf>, FanError> {
+        let mut zones = Vec::new();
+
+        for path in sysfs.read_dir(dir)? {
             if !path
                 .file_name()
                 .and_then(|s| s.to_str())
@@ -81,31 +388,51 @@ impl Temp {
                 continue;
             }
 
-            let temp_path = path.join("temp");
-
-            let Ok(mut file) = fs::File::open(&temp_path) else {
+            let Ok(content) = sysfs.read_to_string(&path.join("temp")) else {
                 continue;
             };
 
-            let mut buf = [0u8; 8]; // temp is in millidegrees, e.g. "101000\n" — 7 bytes max
-            let n = file.read(&mut buf)?;
-            let content = std::str::from_utf8(&buf[..n])
-                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
-                .trim();
-
-            if content.parse::<f64>().is_ok() {
-                info!("Temp path: {}", temp_path.display());
-                return Ok(temp_path);
+            if content.trim().parse::<f64>().is_ok() {
+                zones.push(path);
             }
         }
 
-        Err(io::Error::other("No valid thermal zone found"))
+        zones.sort();
+        Ok(zones)
+    }
+
+    // Type of the thermal zone `temp_path` belongs to, or the chip name of
+    // a hwmon sensor such as `drivetemp`.
+    #[must_use]
+    pub fn zone_type(sysfs: &dyn SysfsAccess, temp_path: &Path) -> Option<String> {
+        let dir = temp_path.parent()?;
+        Self::get_zone_type(sysfs, dir).or_else(|| {
+            let name = sysfs.read_to_string(&dir.join("name")).ok()?;
+            Some(name.trim().to_owned())
+        })
+    }
+
+    fn get_zone_type(sysfs: &dyn SysfsAccess, zone: &Path) -> Option<String> {
+        let zone_type = sysfs.read_to_string(&zone.join("type")).ok()?;
+        Some(zone_type.trim().to_owned())
+    }
+}
+
+fn median(values: &mut [f32]) -> f32 {
+    values.sort_by(f32::total_cmp);
+    let mid = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        f32::midpoint(values[mid - 1], values[mid])
+    } else {
+        values[mid]
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::float_cmp)]
 mod tests {
     use super::*;
+    use crate::sysfs::{MockSysfs, RealSysfs};
     use std::fs;
 
     struct TempTestDir {
@@ -120,10 +447,22 @@ mod tests {
         }
 
         fn create_temp_file(&self, content: &str) -> PathBuf {
-            let temp_file = self.path.join("temp");
+            self.create_zone_file("temp", content)
+        }
+
+        fn create_zone_file(&self, name: &str, content: &str) -> PathBuf {
+            let temp_file = self.path.join(name);
             fs::write(&temp_file, content).unwrap();
             temp_file
         }
+
+        fn create_zone(&self, name: &str, zone_type: &str, content: &str) -> PathBuf {
+            let zone = self.path.join(name);
+            fs::create_dir_all(&zone).unwrap();
+            fs::write(zone.join("type"), format!("{zone_type}\n")).unwrap();
+            fs::write(zone.join("temp"), content).unwrap();
+            zone.join("temp")
+        }
     }
 
     impl Drop for TempTestDir {
@@ -137,7 +476,15 @@ mod tests {
         let test_dir = TempTestDir::new("test_temp_valid");
         let temp_file = test_dir.create_temp_file("45000\n");
 
-        let temp = Temp { path: temp_file };
+        let temp = Temp {
+            paths: vec![temp_file],
+            aggregation: TempAggregation::First,
+            scale: TempScale::Auto,
+            weights: Vec::new(),
+            offset: 0.0,
+            sysfs: Arc::new(RealSysfs),
+            detected_scales: Mutex::default(),
+        };
 
         let result = temp.get_current_temp();
         assert!(result.is_ok());
@@ -149,7 +496,15 @@ mod tests {
         let test_dir = TempTestDir::new("test_temp_whitespace");
         let temp_file = test_dir.create_temp_file("  50000  \n");
 
-        let temp = Temp { path: temp_file };
+        let temp = Temp {
+            paths: vec![temp_file],
+            aggregation: TempAggregation::First,
+            scale: TempScale::Auto,
+            weights: Vec::new(),
+            offset: 0.0,
+            sysfs: Arc::new(RealSysfs),
+            detected_scales: Mutex::default(),
+        };
 
         let result = temp.get_current_temp();
         assert!(result.is_ok());
@@ -161,7 +516,15 @@ mod tests {
         let test_dir = TempTestDir::new("test_temp_zero");
         let temp_file = test_dir.create_temp_file("0");
 
-        let temp = Temp { path: temp_file };
+        let temp = Temp {
+            paths: vec![temp_file],
+            aggregation: TempAggregation::First,
+            scale: TempScale::Auto,
+            weights: Vec::new(),
+            offset: 0.0,
+            sysfs: Arc::new(RealSysfs),
+            detected_scales: Mutex::default(),
+        };
 
         let result = temp.get_current_temp();
         assert!(result.is_ok());
@@ -173,7 +536,15 @@ mod tests {
         let test_dir = TempTestDir::new("test_temp_high");
         let temp_file = test_dir.create_temp_file("100000");
 
-        let temp = Temp { path: temp_file };
+        let temp = Temp {
+            paths: vec![temp_file],
+            aggregation: TempAggregation::First,
+            scale: TempScale::Auto,
+            weights: Vec::new(),
+            offset: 0.0,
+            sysfs: Arc::new(RealSysfs),
+            detected_scales: Mutex::default(),
+        };
 
         let result = temp.get_current_temp();
         assert!(result.is_ok());
@@ -185,20 +556,34 @@ mod tests {
         let test_dir = TempTestDir::new("test_temp_invalid");
         let temp_file = test_dir.create_temp_file("not_a_number");
 
-        let temp = Temp { path: temp_file };
+        let temp = Temp {
+            paths: vec![temp_file],
+            aggregation: TempAggregation::First,
+            scale: TempScale::Auto,
+            weights: Vec::new(),
+            offset: 0.0,
+            sysfs: Arc::new(RealSysfs),
+            detected_scales: Mutex::default(),
+        };
 
         let result = temp.get_current_temp();
-        assert!(result.is_err());
+        assert!(matches!(result, Err(FanError::ParseTemp(_))));
     }
 
     #[test]
     fn test_get_current_temp_file_not_found() {
         let temp = Temp {
-            path: PathBuf::from("/nonexistent/path/temp"),
+            paths: vec![PathBuf::from("/nonexistent/path/temp")],
+            aggregation: TempAggregation::First,
+            scale: TempScale::Auto,
+            weights: Vec::new(),
+            offset: 0.0,
+            sysfs: Arc::new(RealSysfs),
+            detected_scales: Mutex::default(),
         };
 
         let result = temp.get_current_temp();
-        assert!(result.is_err());
+        assert!(matches!(result, Err(FanError::Io(_))));
     }
 
     #[test]
@@ -206,7 +591,15 @@ mod tests {
         let test_dir = TempTestDir::new("test_temp_empty");
         let temp_file = test_dir.create_temp_file("");
 
-        let temp = Temp { path: temp_file };
+        let temp = Temp {
+            paths: vec![temp_file],
+            aggregation: TempAggregation::First,
+            scale: TempScale::Auto,
+            weights: Vec::new(),
+            offset: 0.0,
+            sysfs: Arc::new(RealSysfs),
+            detected_scales: Mutex::default(),
+        };
 
         let result = temp.get_current_temp();
         assert!(result.is_err());
@@ -217,10 +610,542 @@ mod tests {
         let test_dir = TempTestDir::new("test_temp_negative");
         let temp_file = test_dir.create_temp_file("-5000");
 
-        let temp = Temp { path: temp_file };
+        let temp = Temp {
+            paths: vec![temp_file],
+            aggregation: TempAggregation::First,
+            scale: TempScale::Auto,
+            weights: Vec::new(),
+            offset: 0.0,
+            sysfs: Arc::new(RealSysfs),
+            detected_scales: Mutex::default(),
+        };
 
         let result = temp.get_current_temp();
         assert!(result.is_ok());
         assert_eq!(result.unwrap(), -5.0);
     }
+
+    #[test]
+    fn test_get_current_temp_max_of_zones() {
+        let test_dir = TempTestDir::new("test_temp_aggregation_max");
+        let soc = test_dir.create_zone_file("soc", "45000");
+        let npu = test_dir.create_zone_file("npu", "62000");
+        let gpu = test_dir.create_zone_file("gpu", "50000");
+
+        let temp = Temp {
+            paths: vec![soc, npu, gpu],
+            aggregation: TempAggregation::Max,
+            scale: TempScale::Auto,
+            weights: Vec::new(),
+            offset: 0.0,
+            sysfs: Arc::new(RealSysfs),
+            detected_scales: Mutex::default(),
+        };
+
+        assert_eq!(temp.get_current_temp().unwrap(), 62.0);
+    }
+
+    #[test]
+    fn test_get_current_temp_mean_of_zones() {
+        let test_dir = TempTestDir::new("test_temp_aggregation_mean");
+        let soc = test_dir.create_zone_file("soc", "45000");
+        let npu = test_dir.create_zone_file("npu", "60000");
+        let gpu = test_dir.create_zone_file("gpu", "51000");
+
+        let temp = Temp {
+            paths: vec![soc, npu, gpu],
+            aggregation: TempAggregation::Mean,
+            scale: TempScale::Auto,
+            weights: Vec::new(),
+            offset: 0.0,
+            sysfs: Arc::new(RealSysfs),
+            detected_scales: Mutex::default(),
+        };
+
+        assert_eq!(temp.get_current_temp().unwrap(), 52.0);
+    }
+
+    #[test]
+    fn test_get_current_temp_first_zone() {
+        let test_dir = TempTestDir::new("test_temp_aggregation_first");
+        let soc = test_dir.create_zone_file("soc", "45000");
+        let npu = test_dir.create_zone_file("npu", "62000");
+
+        let temp = Temp {
+            paths: vec![soc, npu],
+            aggregation: TempAggregation::First,
+            scale: TempScale::Auto,
+            weights: Vec::new(),
+            offset: 0.0,
+            sysfs: Arc::new(RealSysfs),
+            detected_scales: Mutex::default(),
+        };
+
+        assert_eq!(temp.get_current_temp().unwrap(), 45.0);
+    }
+
+    #[test]
+    fn test_get_current_temp_fails_if_any_zone_fails() {
+        let test_dir = TempTestDir::new("test_temp_aggregation_error");
+        let soc = test_dir.create_zone_file("soc", "45000");
+
+        let temp = Temp {
+            paths: vec![soc, PathBuf::from("/nonexistent/path/temp")],
+            aggregation: TempAggregation::Max,
+            scale: TempScale::Auto,
+            weights: Vec::new(),
+            offset: 0.0,
+            sysfs: Arc::new(RealSysfs),
+            detected_scales: Mutex::default(),
+        };
+
+        assert!(temp.get_current_temp().is_err());
+    }
+
+    #[test]
+    fn test_get_current_temp_no_zone() {
+        let temp = Temp {
+            paths: Vec::new(),
+            aggregation: TempAggregation::Max,
+            scale: TempScale::Auto,
+            weights: Vec::new(),
+            offset: 0.0,
+            sysfs: Arc::new(RealSysfs),
+            detected_scales: Mutex::default(),
+        };
+
+        assert!(matches!(
+            temp.get_current_temp(),
+            Err(FanError::NoThermalZone)
+        ));
+    }
+
+    #[test]
+    fn test_new_with_explicit_zone_path() {
+        let test_dir = TempTestDir::new("test_temp_explicit_path");
+        let temp_file = test_dir.create_temp_file("48000");
+
+        let config = Config {
+            thermal_zone_path: Some(temp_file.clone()),
+            ..Config::default()
+        };
+
+        let temp = Temp::new(&config, Arc::new(RealSysfs)).unwrap();
+        assert_eq!(temp.paths, vec![temp_file]);
+        assert_eq!(temp.get_current_temp().unwrap(), 48.0);
+    }
+
+    #[test]
+    fn test_new_with_invalid_explicit_zone_path_falls_back() {
+        let test_dir = TempTestDir::new("test_temp_invalid_explicit_path");
+        let temp_file = test_dir.create_temp_file("not_a_number");
+
+        let config = Config {
+            thermal_zone_path: Some(temp_file.clone()),
+            ..Config::default()
+        };
+
+        assert!(
+            Temp::new(&config, Arc::new(RealSysfs))
+                .map_or(true, |temp| temp.paths != vec![temp_file])
+        );
+    }
+
+    #[test]
+    fn test_new_with_temp_file_divisors() {
+        let test_dir = TempTestDir::new("test_temp_file_divisors");
+        let temp_file = test_dir.path.join("temp1_input");
+
+        for (content, divisor, expected) in [
+            ("42", 1, 42.0),
+            ("425", 10, 42.5),
+            ("42500\n", 1000, 42.5),
+            ("-5000", 1000, -5.0),
+            // Would be read as millidegrees by `TEMP_SCALE=auto`
+            ("4200", 100, 42.0),
+        ] {
+            fs::write(&temp_file, content).unwrap();
+            let config = Config {
+                temp_file_path: Some(temp_file.clone()),
+                temp_file_divisor: divisor,
+                ..Config::default()
+            };
+
+            let temp = Temp::new(&config, Arc::new(RealSysfs)).unwrap();
+            assert_eq!(temp.paths, vec![temp_file.clone()]);
+            assert_eq!(temp.scale, TempScale::Divisor(divisor));
+            assert_eq!(temp.get_current_temp().unwrap(), expected, "{content}");
+        }
+    }
+
+    #[test]
+    fn test_new_with_invalid_temp_file() {
+        let test_dir = TempTestDir::new("test_temp_file_invalid");
+        let temp_file = test_dir.create_zone_file("temp1_input", "unavailable");
+
+        let mut config = Config {
+            temp_file_path: Some(temp_file),
+            ..Config::default()
+        };
+        assert!(matches!(
+            Temp::new(&config, Arc::new(RealSysfs)),
+            Err(FanError::ParseTemp(_))
+        ));
+
+        config.temp_file_path = Some(test_dir.path.join("missing"));
+        assert!(matches!(
+            Temp::new(&config, Arc::new(RealSysfs)),
+            Err(FanError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn test_read_critical_trip() {
+        let test_dir = TempTestDir::new("test_temp_critical_trip");
+        let zone = test_dir.create_zone("thermal_zone0", "soc-thermal", "45000");
+        let temp = Temp {
+            paths: vec![zone.clone()],
+            aggregation: TempAggregation::First,
+            scale: TempScale::Auto,
+            weights: Vec::new(),
+            offset: 0.0,
+            sysfs: Arc::new(RealSysfs),
+            detected_scales: Mutex::default(),
+        };
+        assert_eq!(temp.read_critical_trip(), None);
+
+        let zone = zone.parent().unwrap();
+        for (index, trip_type, trip_temp) in [
+            (0, "passive", "85000"),
+            (1, "critical", "115000"),
+            (2, "hot", "95000"),
+        ] {
+            fs::write(
+                zone.join(format!("trip_point_{index}_type")),
+                format!("{trip_type}\n"),
+            )
+            .unwrap();
+            fs::write(
+                zone.join(format!("trip_point_{index}_temp")),
+                format!("{trip_temp}\n"),
+            )
+            .unwrap();
+        }
+        assert_eq!(temp.read_critical_trip(), Some(115.0));
+    }
+
+    #[test]
+    fn test_find_temp_paths_by_zone_type() {
+        let test_dir = TempTestDir::new("test_temp_zone_type");
+        test_dir.create_zone("thermal_zone0", "soc-thermal", "45000");
+        let gpu = test_dir.create_zone("thermal_zone1", "gpu-thermal", "50000");
+        test_dir.create_zone("thermal_zone2", "npu-thermal", "55000");
+
+        let config = Config {
+            thermal_zone_types: vec!["gpu-thermal".into()],
+            ..Config::default()
+        };
+
+        let paths = Temp::find_temp_paths(&RealSysfs, &test_dir.path, &config).unwrap();
+        assert_eq!(paths, vec![gpu]);
+    }
+
+    #[test]
+    fn test_zone_type_of_hwmon_sensor() {
+        let test_dir = TempTestDir::new("test_temp_zone_type_hwmon");
+        let zone = test_dir.create_zone("thermal_zone0", "soc-thermal", "45000");
+        assert_eq!(
+            Temp::zone_type(&RealSysfs, &zone).as_deref(),
+            Some("soc-thermal")
+        );
+
+        let hwmon = test_dir.path.join("hwmon3");
+        fs::create_dir_all(&hwmon).unwrap();
+        fs::write(hwmon.join("name"), "drivetemp\n").unwrap();
+        fs::write(hwmon.join("temp1_input"), "38000").unwrap();
+        let drive = hwmon.join("temp1_input");
+        assert_eq!(
+            Temp::zone_type(&RealSysfs, &drive).as_deref(),
+            Some("drivetemp")
+        );
+        assert_eq!(
+            Temp::zone_type(&RealSysfs, &test_dir.path.join("temp")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_zone_type_reads_through_sysfs() {
+        let sysfs = MockSysfs::default();
+        let zone = Path::new("thermal_zone0/temp");
+        let drive = Path::new("hwmon3/temp1_input");
+        assert_eq!(Temp::zone_type(&sysfs, zone), None);
+
+        sysfs.set("thermal_zone0/type", "soc-thermal\n");
+        sysfs.set("hwmon3/name", "drivetemp\n");
+        assert_eq!(
+            Temp::zone_type(&sysfs, zone).as_deref(),
+            Some("soc-thermal")
+        );
+        assert_eq!(Temp::zone_type(&sysfs, drive).as_deref(), Some("drivetemp"));
+
+        // The hwmon name is only a fallback for a zone without a type
+        sysfs.set("hwmon3/type", "nvme");
+        assert_eq!(Temp::zone_type(&sysfs, drive).as_deref(), Some("nvme"));
+    }
+
+    #[test]
+    fn test_find_temp_paths_zone_type_priority_fallback() {
+        let test_dir = TempTestDir::new("test_temp_zone_type_priority");
+        test_dir.create_zone("thermal_zone0", "gpu-thermal", "50000");
+        let cpu = test_dir.create_zone("thermal_zone1", "cpu-thermal", "45000");
+
+        let config = Config {
+            thermal_zone_types: Config::parse_zone_types("soc-thermal, cpu-thermal,gpu-thermal"),
+            ..Config::default()
+        };
+
+        let paths = Temp::find_temp_paths(&RealSysfs, &test_dir.path, &config).unwrap();
+        assert_eq!(paths, vec![cpu]);
+    }
+
+    #[test]
+    fn test_find_temp_paths_zone_type_priority_prefers_first() {
+        let test_dir = TempTestDir::new("test_temp_zone_type_priority_first");
+        let soc = test_dir.create_zone("thermal_zone0", "soc-thermal", "50000");
+        test_dir.create_zone("thermal_zone1", "cpu-thermal", "45000");
+
+        let config = Config {
+            thermal_zone_types: vec!["soc-thermal".into(), "cpu-thermal".into()],
+            ..Config::default()
+        };
+
+        let paths = Temp::find_temp_paths(&RealSysfs, &test_dir.path, &config).unwrap();
+        assert_eq!(paths, vec![soc]);
+    }
+
+    #[test]
+    fn test_find_temp_paths_unmatched_zone_type_falls_back() {
+        let test_dir = TempTestDir::new("test_temp_zone_type_unmatched");
+        let soc = test_dir.create_zone("thermal_zone0", "soc-thermal", "45000");
+        test_dir.create_zone("thermal_zone1", "gpu-thermal", "50000");
+
+        let config = Config {
+            thermal_zone_types: vec!["npu-thermal".into()],
+            ..Config::default()
+        };
+
+        let paths = Temp::find_temp_paths(&RealSysfs, &test_dir.path, &config).unwrap();
+        assert_eq!(paths, vec![soc]);
+    }
+
+    #[test]
+    fn test_find_temp_paths_skips_invalid_zone_of_matching_type() {
+        let test_dir = TempTestDir::new("test_temp_zone_type_invalid");
+        test_dir.create_zone("thermal_zone0", "gpu-thermal", "invalid");
+        let soc = test_dir.create_zone("thermal_zone1", "soc-thermal", "45000");
+
+        let config = Config {
+            thermal_zone_types: vec!["gpu-thermal".into()],
+            ..Config::default()
+        };
+
+        let paths = Temp::find_temp_paths(&RealSysfs, &test_dir.path, &config).unwrap();
+        assert_eq!(paths, vec![soc]);
+    }
+
+    #[test]
+    fn test_find_temp_paths_all_zones_with_aggregation() {
+        let test_dir = TempTestDir::new("test_temp_zone_all");
+        let soc = test_dir.create_zone("thermal_zone0", "soc-thermal", "45000");
+        let gpu = test_dir.create_zone("thermal_zone1", "gpu-thermal", "50000");
+
+        let config = Config {
+            temp_aggregation: TempAggregation::Max,
+            ..Config::default()
+        };
+
+        let paths = Temp::find_temp_paths(&RealSysfs, &test_dir.path, &config).unwrap();
+        assert_eq!(paths, vec![soc, gpu]);
+    }
+
+    #[test]
+    fn test_find_weighted_paths() {
+        let test_dir = TempTestDir::new("test_temp_weighted_zones");
+        let soc = test_dir.create_zone("thermal_zone0", "soc-thermal", "40000");
+        test_dir.create_zone("thermal_zone1", "gpu-thermal", "50000");
+        let npu = test_dir.create_zone("thermal_zone2", "npu-thermal", "70000");
+
+        let zones = [
+            ("npu-thermal".to_owned(), 2.0),
+            ("soc-thermal".to_owned(), 1.0),
+            ("dsp-thermal".to_owned(), 1.0),
+        ];
+        let weighted = Temp::find_weighted_paths(&RealSysfs, &test_dir.path, &zones).unwrap();
+        assert_eq!(weighted, vec![(npu, 2.0), (soc, 1.0)]);
+
+        let (paths, weights) = weighted.into_iter().unzip();
+        let temp = Temp {
+            paths,
+            aggregation: TempAggregation::First,
+            scale: TempScale::Auto,
+            weights,
+            offset: 0.0,
+            sysfs: Arc::new(RealSysfs),
+            detected_scales: Mutex::default(),
+        };
+        assert_eq!(temp.get_current_temp().unwrap(), 60.0);
+    }
+
+    #[test]
+    fn test_find_temp_paths_no_zone() {
+        let test_dir = TempTestDir::new("test_temp_zone_none");
+
+        let result = Temp::find_temp_paths(&RealSysfs, &test_dir.path, &Config::default());
+        assert!(matches!(result, Err(FanError::NoThermalZone)));
+    }
+
+    #[test]
+    fn test_scale_temp_fixed_scales() {
+        assert_eq!(Temp::scale_temp(45000.0, TempScale::Milli), 45.0);
+        assert_eq!(Temp::scale_temp(450.0, TempScale::Deci), 45.0);
+        assert_eq!(Temp::scale_temp(45.0, TempScale::Unit), 45.0);
+    }
+
+    #[test]
+    fn test_scale_temp_auto_detects_scale() {
+        assert_eq!(Temp::scale_temp(45000.0, TempScale::Auto), 45.0);
+        assert_eq!(Temp::scale_temp(105_000.0, TempScale::Auto), 105.0);
+        // Millidegrees are whole numbers
+        assert_eq!(Temp::scale_temp(45.5, TempScale::Auto), 45.5);
+        assert_eq!(Temp::scale_temp(452.5, TempScale::Auto), 45.25);
+    }
+
+    #[test]
+    fn test_scale_temp_auto_falls_back_to_milli() {
+        assert_eq!(Temp::scale_temp(0.0, TempScale::Auto), 0.0);
+        assert_eq!(Temp::scale_temp(-5000.0, TempScale::Auto), -5.0);
+    }
+
+    #[test]
+    fn test_scale_temp_auto_near_zero_is_milli() {
+        assert_eq!(Temp::scale_temp(-10_000.0, TempScale::Auto), -10.0);
+        assert_eq!(Temp::scale_temp(-40_000.0, TempScale::Auto), -40.0);
+        assert_eq!(Temp::scale_temp(-1000.0, TempScale::Auto), -1.0);
+        assert_eq!(Temp::scale_temp(-500.0, TempScale::Auto), -0.5);
+        assert_eq!(Temp::scale_temp(-100.0, TempScale::Auto), -0.1);
+        assert_eq!(Temp::scale_temp(100.0, TempScale::Auto), 0.1);
+        assert_eq!(Temp::scale_temp(999.0, TempScale::Auto), 0.999);
+        assert_eq!(Temp::detect_scale(-500.0), None);
+        assert_eq!(Temp::detect_scale(999.0), None);
+        assert_eq!(Temp::detect_scale(1000.0), Some(TempScale::Milli));
+    }
+
+    #[test]
+    fn test_auto_scale_is_kept_per_zone() {
+        let sysfs = Arc::new(MockSysfs::default());
+        let zone = PathBuf::from("thermal_zone0/temp");
+        let temp = Temp {
+            paths: vec![zone.clone()],
+            aggregation: TempAggregation::First,
+            scale: TempScale::Auto,
+            weights: Vec::new(),
+            offset: 0.0,
+            sysfs: sysfs.clone(),
+            detected_scales: Mutex::default(),
+        };
+
+        // Near 0 is read as millidegrees without settling the scale, the
+        // next reading away from 0 does
+        sysfs.set(&zone, "500\n");
+        assert_eq!(temp.get_current_temp().unwrap(), 0.5);
+        assert!(temp.detected_scales.lock().unwrap().is_empty());
+        sysfs.set(&zone, "-2000\n");
+        assert_eq!(temp.get_current_temp().unwrap(), -2.0);
+        sysfs.set(&zone, "-500\n");
+        assert_eq!(temp.get_current_temp().unwrap(), -0.5);
+        sysfs.set(&zone, "450\n");
+        assert_eq!(temp.get_current_temp().unwrap(), 0.45);
+
+        // A degree zone stays in degrees once a reading settled it
+        let temp = Temp {
+            detected_scales: Mutex::default(),
+            ..temp
+        };
+        sysfs.set(&zone, "45.5\n");
+        assert_eq!(temp.get_current_temp().unwrap(), 45.5);
+        sysfs.set(&zone, "30\n");
+        assert_eq!(temp.get_current_temp().unwrap(), 30.0);
+    }
+
+    #[test]
+    fn test_get_current_temp_degree_zone() {
+        let test_dir = TempTestDir::new("test_temp_degree_zone");
+        let temp_file = test_dir.create_temp_file("45.5\n");
+
+        let temp = Temp {
+            paths: vec![temp_file.clone()],
+            aggregation: TempAggregation::First,
+            scale: TempScale::Auto,
+            weights: Vec::new(),
+            offset: 0.0,
+            sysfs: Arc::new(RealSysfs),
+            detected_scales: Mutex::default(),
+        };
+        assert_eq!(temp.get_current_temp().unwrap(), 45.5);
+
+        let temp = Temp {
+            paths: vec![temp_file],
+            aggregation: TempAggregation::First,
+            scale: TempScale::Unit,
+            weights: Vec::new(),
+            offset: 0.0,
+            sysfs: Arc::new(RealSysfs),
+            detected_scales: Mutex::default(),
+        };
+        assert_eq!(temp.get_current_temp().unwrap(), 45.5);
+    }
+
+    #[test]
+    fn test_get_current_temp_decidegree_zone() {
+        let test_dir = TempTestDir::new("test_temp_decidegree_zone");
+        let temp_file = test_dir.create_temp_file("452\n");
+
+        let temp = Temp {
+            paths: vec![temp_file],
+            aggregation: TempAggregation::First,
+            scale: TempScale::Deci,
+            weights: Vec::new(),
+            offset: 0.0,
+            sysfs: Arc::new(RealSysfs),
+            detected_scales: Mutex::default(),
+        };
+        assert_eq!(temp.get_current_temp().unwrap(), 45.2);
+    }
+
+    #[test]
+    fn test_median_rejects_outlier() {
+        assert_eq!(median(&mut [45.0, 46.0, 95.0, 45.5, 44.0]), 45.5);
+        assert_eq!(median(&mut [45.0, 95.0, 46.0, 44.0]), 45.5);
+        assert_eq!(median(&mut [47.0]), 47.0);
+    }
+
+    #[test]
+    fn test_get_median_temp() {
+        let test_dir = TempTestDir::new("test_temp_median");
+        let temp_file = test_dir.create_temp_file("45000");
+
+        let temp = Temp {
+            paths: vec![temp_file],
+            aggregation: TempAggregation::First,
+            scale: TempScale::Auto,
+            weights: Vec::new(),
+            offset: 0.0,
+            sysfs: Arc::new(RealSysfs),
+            detected_scales: Mutex::default(),
+        };
+
+        assert_eq!(temp.get_median_temp(3).unwrap(), 45.0);
+        assert_eq!(temp.get_median_temp(0).unwrap(), 45.0);
+    }
 }